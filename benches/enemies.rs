@@ -0,0 +1,93 @@
+//! How enemy separation and whole simulation steps scale with the enemy
+//! count. Run with `cargo bench --bench enemies`.
+//!
+//! The brute-force pushing loop the grid replaced is kept here as a
+//! reference point.
+
+use std::time::{Duration, Instant};
+
+use lowrez2023::enemies::{Enemy, update_enemies_pushing};
+use lowrez2023::enemy_defs::EnemyDefs;
+use lowrez2023::input::InputState;
+use lowrez2023::player::Player;
+use lowrez2023::rng::Rng;
+use lowrez2023::spatial::SpatialGrid;
+use lowrez2023::world::World;
+use lowrez2023::{col, dist, get_dir_};
+
+const COUNTS: [usize; 4] = [250, 1000, 4000, 16000];
+
+/// Enemies scattered around the origin about as densely as a late run crowd
+fn crowd(count: usize, rng: &mut Rng) -> Vec<Enemy> {
+    let defs = EnemyDefs::builtin();
+    let slime = defs.get("slime").unwrap();
+    let half = (count as f32).sqrt() * 5.;
+    (0..count)
+        .map(|_| Enemy::from_def(slime, rng.gen_range_f32(-half, half), rng.gen_range_f32(-half, half), 3., 0.))
+        .collect()
+}
+
+fn brute_force_pushing(enemies: &mut [Enemy]) {
+    for i in 0..enemies.len() - 1 {
+        for j in i+1..enemies.len() {
+            let r = 8.;
+            if col(enemies[i].position, enemies[j].position, r) {
+                let dist = dist(enemies[i].position, enemies[j].position, 10.);
+                let dir = get_dir_(enemies[i].position, enemies[j].position);
+                let dif = r - dist;
+                enemies[i].position.x += dir.cos()*dif;
+                enemies[i].position.y += dir.sin()*dif;
+            }
+        }
+    }
+}
+
+/// Average time of `f` over as many runs as fit in about half a second
+fn time<F: FnMut()>(mut f: F) -> Duration {
+    let start = Instant::now();
+    let mut runs = 0;
+    while runs < 3 || (start.elapsed() < Duration::from_millis(500) && runs < 1000) {
+        f();
+        runs += 1;
+    }
+    start.elapsed() / runs
+}
+
+fn main() {
+    let mut rng = Rng::new(2023, 0);
+
+    println!("{:>8} {:>14} {:>14} {:>14}", "enemies", "brute force", "grid", "world step");
+    for count in COUNTS {
+        let enemies = crowd(count, &mut rng);
+
+        // Past a few thousand enemies the brute force takes seconds a run
+        let brute = if count <= 4000 {
+            let mut brute_enemies = enemies.clone();
+            format!("{:?}", time(|| brute_force_pushing(&mut brute_enemies)))
+        } else {
+            "-".to_string()
+        };
+
+        let mut grid_enemies = enemies.clone();
+        let mut grid = SpatialGrid::new(16.);
+        let grid_time = time(|| {
+            grid.rebuild(grid_enemies.iter().map(|e| e.position));
+            update_enemies_pushing(&mut grid_enemies, &grid);
+        });
+
+        let mut world = World::new(2023);
+        world.enemies = enemies;
+        let mut player = Player::new();
+        player.pos_x = 0.;
+        player.pos_y = 0.;
+        let input = InputState { move_x: 1., move_y: 0., dash_pressed: false, dash_direction: None };
+        let step_time = time(|| {
+            // Keep the player alive and out of the contact checks
+            player.hp = player.max_hp();
+            player.inv_timer.restart(world.clock.now());
+            world.step(&mut player, 1. / 60., &input);
+        });
+
+        println!("{:>8} {:>14} {:>14} {:>14}", count, brute, format!("{:?}", grid_time), format!("{:?}", step_time));
+    }
+}
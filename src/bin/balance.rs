@@ -0,0 +1,320 @@
+//! Plays a batch of seeded runs without a window and reports how each went,
+//! to compare balance changes before shipping them:
+//!
+//! ```text
+//! cargo run --release --bin balance -- --runs 50 --difficulty hard --csv runs.csv
+//! ```
+//!
+//! - `--runs <n>` how many runs (20), seeded `--seed <first>` (1) and up
+//! - `--difficulty easy|normal|hard` (normal)
+//! - `--bot kite|idle` who plays: the autoplay bot, or nobody at all (kite)
+//! - `--prefer <id,id,...>` upgrades the bot goes for
+//! - `--only <id,id,...>` upgrades and weapons level ups may offer, all of
+//!   them otherwise
+//! - `--enemies`, `--upgrades`, `--weapons`, `--levels`, `--spawns <file>`
+//!   balance data to try instead of the one in `assets/`
+//! - `--telemetry <dir>` also logs every run to `<dir>/run-<seed>.jsonl`
+//! - `--csv <file>` and `--json <file>` where the reports go,
+//!   `balance.csv` when neither is given
+//!
+//! A summary of the batch is printed on stderr.
+
+use std::collections::BTreeMap;
+use std::fmt::Display;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+use serde::Serialize;
+
+use lowrez2023::bot::Bot;
+use lowrez2023::defs;
+use lowrez2023::difficulty::{Difficulty, Preset};
+use lowrez2023::enemy_defs::EnemyDefs;
+use lowrez2023::input::InputState;
+use lowrez2023::levels::LevelRules;
+use lowrez2023::player::Player;
+use lowrez2023::spawn::{SpawnDirector, Timeline};
+use lowrez2023::stats;
+use lowrez2023::telemetry::TelemetryRecorder;
+use lowrez2023::upgrade::UpgradeDefs;
+use lowrez2023::weapons::{Inventory, WeaponDefs};
+use lowrez2023::world::{World, StepOutcome, LevelUpChoice, STAGE_DURATION_MILLIS};
+
+/// Every run ticks at a steady 60 fps
+const TICK: f32 = 1. / 60.;
+/// Seconds between two points of the XP curve
+const XP_SAMPLE_SECS: u32 = 10;
+const DEFAULT_CSV: &str = "balance.csv";
+
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let position = args.iter().position(|a| a == name)?;
+    args.get(position + 1).cloned()
+}
+
+fn list_arg(name: &str) -> Option<Vec<String>> {
+    arg_value(name).map(|ids| ids.split(',').map(|id| id.trim().to_string()).collect())
+}
+
+fn number_arg(name: &str, default: u64) -> u64 {
+    match arg_value(name).map(|v| v.parse::<u64>()) {
+        None => default,
+        Some(Ok(v)) => v,
+        Some(Err(_)) => fail(format!("{} expects a positive number", name)),
+    }
+}
+
+fn fail<E: Display>(e: E) -> ! {
+    eprintln!("{}", e);
+    process::exit(1);
+}
+
+fn load_or<T, E: Display>(name: &str, load: fn(String) -> Result<T, E>, builtin: fn() -> T) -> T {
+    match arg_value(name) {
+        Some(path) => load(path.clone()).unwrap_or_else(|e| fail(format!("{}: {}", path, e))),
+        None => builtin(),
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Strategy {
+    /// The autoplay bot
+    Kite,
+    /// Stands still and takes the first card, the floor any balance has to beat
+    Idle,
+}
+
+impl Strategy {
+    fn name(&self) -> &'static str {
+        match self {
+            Strategy::Kite => "kite",
+            Strategy::Idle => "idle",
+        }
+    }
+}
+
+/// Everything the runs of the batch share
+struct Batch {
+    strategy: Strategy,
+    bot: Bot,
+    difficulty: Preset,
+    enemy_defs: EnemyDefs,
+    upgrade_defs: UpgradeDefs,
+    weapon_defs: WeaponDefs,
+    level_rules: LevelRules,
+    timeline: Timeline,
+    telemetry_dir: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct XpSample {
+    secs: u32,
+    level: i32,
+    xp_gained: f32,
+}
+
+#[derive(Debug, Clone, Serialize)]
+struct RunReport {
+    seed: u64,
+    difficulty: &'static str,
+    bot: &'static str,
+    survived: bool,
+    survival_secs: f32,
+    /// What landed the killing blow, "survived" otherwise
+    death_cause: String,
+    level: i32,
+    gold: u32,
+    kills: BTreeMap<String, u32>,
+    damage_taken: BTreeMap<String, f32>,
+    upgrades: Vec<String>,
+    xp_curve: Vec<XpSample>,
+}
+
+impl Batch {
+    fn run(&self, seed: u64) -> RunReport {
+        let mut player = Player::new();
+        let mut world = World::new(seed);
+        world.enemy_defs = self.enemy_defs.clone();
+        world.upgrade_defs = self.upgrade_defs.clone();
+        world.weapon_defs = self.weapon_defs.clone();
+        world.weapons = Inventory::starting(&world.weapon_defs);
+        world.level_rules = self.level_rules.clone();
+        world.spawn_director = SpawnDirector::new(self.timeline.clone());
+        world.difficulty = Difficulty::new(self.difficulty);
+        let mut telemetry = self.telemetry_dir.as_ref().map(|dir| {
+            let path = dir.join(format!("run-{}.jsonl", seed));
+            TelemetryRecorder::create(&path, &world).unwrap_or_else(|e| fail(format!("{}: {}", path.display(), e)))
+        });
+
+        let mut xp_curve = Vec::new();
+        // Stops even if the stage never clears, a stuck run is still a report
+        let max_secs = (STAGE_DURATION_MILLIS / 1000) as f32 + 10.;
+        while player.active && !world.stage_cleared() && world.stopwatch.elapsed_secs() < max_secs {
+            let secs = world.stopwatch.elapsed_secs() as u32;
+            if xp_curve.len() as u32 <= secs / XP_SAMPLE_SECS {
+                xp_curve.push(XpSample { secs, level: player.level, xp_gained: world.stats.xp_gained });
+            }
+
+            let input = match self.strategy {
+                Strategy::Kite => self.bot.input(&world, &player),
+                Strategy::Idle => InputState::default(),
+            };
+            let outcome = world.step(&mut player, TICK, &input);
+            if let Some(telemetry) = telemetry.as_mut() {
+                telemetry.record_step(&world, &player);
+            }
+            if outcome == StepOutcome::LevelUp {
+                while let Some(mut offered) = world.next_level_up(&mut player) {
+                    if let Some(telemetry) = telemetry.as_mut() {
+                        let names: Vec<&str> = offered.iter().map(|u| u.get_name()).collect();
+                        telemetry.record_upgrades_offered(&world, &names);
+                    }
+                    let choice = match self.strategy {
+                        Strategy::Kite => self.bot.choose_upgrade(&offered),
+                        Strategy::Idle => 0,
+                    };
+                    if let Some(telemetry) = telemetry.as_mut() {
+                        telemetry.record_upgrade_chosen(&world, offered[choice].get_name());
+                    }
+                    world.resolve_level_up(&mut player, &mut offered, LevelUpChoice::Pick(choice));
+                }
+            }
+        }
+        if let Some(telemetry) = telemetry {
+            telemetry.finish(&world, &player);
+        }
+
+        let survived = player.active;
+        RunReport {
+            seed,
+            difficulty: self.difficulty.name(),
+            bot: self.strategy.name(),
+            survived,
+            survival_secs: world.stopwatch.elapsed_secs(),
+            death_cause: if survived {
+                "survived".to_string()
+            } else {
+                world.stats.last_hit_by.clone().unwrap_or_else(|| "unknown".to_string())
+            },
+            level: player.level,
+            gold: world.stats.gold,
+            kills: world.stats.kills.clone(),
+            damage_taken: world.stats.damage_taken.clone(),
+            upgrades: world.chosen_upgrades.clone(),
+            xp_curve,
+        }
+    }
+}
+
+/// One row per run. Kills and damage get a column per enemy kind, the
+/// upgrades and the XP curve (`secs:level:xp`) are `;` separated.
+fn to_csv(reports: &[RunReport], enemy_defs: &EnemyDefs) -> String {
+    let kinds: Vec<&str> = enemy_defs.iter().map(|d| d.id.as_str()).collect();
+    let sources: Vec<&str> = kinds.iter().copied().chain([stats::PROJECTILE]).collect();
+
+    let mut header = vec!["seed", "difficulty", "bot", "survived", "survival_secs", "death_cause", "level", "gold"]
+        .into_iter().map(String::from).collect::<Vec<_>>();
+    header.extend(kinds.iter().map(|k| format!("kills_{}", k)));
+    header.extend(sources.iter().map(|s| format!("damage_{}", s)));
+    header.push("upgrades".to_string());
+    header.push("xp_curve".to_string());
+
+    let mut csv = header.join(",") + "\n";
+    for r in reports {
+        let mut row = vec![
+            r.seed.to_string(),
+            r.difficulty.to_string(),
+            r.bot.to_string(),
+            r.survived.to_string(),
+            format!("{:.2}", r.survival_secs),
+            r.death_cause.clone(),
+            r.level.to_string(),
+            r.gold.to_string(),
+        ];
+        row.extend(kinds.iter().map(|k| r.kills.get(*k).copied().unwrap_or(0).to_string()));
+        row.extend(sources.iter().map(|s| format!("{:.0}", r.damage_taken.get(*s).copied().unwrap_or(0.))));
+        row.push(r.upgrades.join(";"));
+        row.push(r.xp_curve.iter().map(|x| format!("{}:{}:{:.0}", x.secs, x.level, x.xp_gained)).collect::<Vec<_>>().join(";"));
+        csv += &(row.join(",") + "\n");
+    }
+    csv
+}
+
+fn mean(values: impl Iterator<Item = f32>) -> f32 {
+    let (sum, count) = values.fold((0., 0), |(s, c), v| (s + v, c + 1));
+    if count == 0 { 0. } else { sum / count as f32 }
+}
+
+fn print_summary(reports: &[RunReport]) {
+    let survived = reports.iter().filter(|r| r.survived).count();
+    eprintln!("{} runs, {} survived ({:.0}%)", reports.len(), survived, 100. * survived as f32 / reports.len().max(1) as f32);
+    eprintln!("  survival time  {:.1}s on average", mean(reports.iter().map(|r| r.survival_secs)));
+    eprintln!("  level reached  {:.1} on average", mean(reports.iter().map(|r| r.level as f32)));
+    eprintln!("  kills          {:.1} on average", mean(reports.iter().map(|r| r.kills.values().sum::<u32>() as f32)));
+
+    let mut causes: BTreeMap<&str, usize> = BTreeMap::new();
+    for r in reports.iter().filter(|r| !r.survived) {
+        *causes.entry(r.death_cause.as_str()).or_default() += 1;
+    }
+    for (cause, count) in causes {
+        eprintln!("  killed by {:<8} {}", cause, count);
+    }
+}
+
+fn main() {
+    let strategy = match arg_value("--bot").as_deref() {
+        None | Some("kite") => Strategy::Kite,
+        Some("idle") => Strategy::Idle,
+        Some(other) => fail(format!("--bot expects kite or idle, not {}", other)),
+    };
+    let difficulty = match arg_value("--difficulty") {
+        None => Preset::Normal,
+        Some(name) => Preset::from_name(&name).unwrap_or_else(|| fail("--difficulty expects easy, normal or hard")),
+    };
+
+    let enemy_defs = load_or("--enemies", defs::load::<EnemyDefs, String>, EnemyDefs::builtin);
+    let mut upgrade_defs = load_or("--upgrades", defs::load::<UpgradeDefs, String>, UpgradeDefs::builtin);
+    let mut weapon_defs = load_or("--weapons", defs::load::<WeaponDefs, String>, WeaponDefs::builtin);
+    let level_rules = load_or("--levels", defs::load::<LevelRules, String>, LevelRules::builtin);
+    let timeline = load_or("--spawns", defs::load::<Timeline, String>, Timeline::builtin);
+    if let Err(e) = timeline.check_enemies(&enemy_defs) {
+        fail(e);
+    }
+    if let Some(only) = list_arg("--only") {
+        if let Some(unknown) = only.iter().find(|id| upgrade_defs.get(id).is_none() && weapon_defs.get(id).is_none()) {
+            fail(format!("--only: there's no upgrade or weapon called \"{}\"", unknown));
+        }
+        upgrade_defs.retain(|def| only.contains(&def.id));
+        weapon_defs.retain(|def| only.contains(&def.id));
+    }
+
+    let batch = Batch {
+        strategy,
+        bot: Bot::new(list_arg("--prefer").unwrap_or_default()),
+        difficulty,
+        enemy_defs,
+        upgrade_defs,
+        weapon_defs,
+        level_rules,
+        timeline,
+        telemetry_dir: arg_value("--telemetry").map(PathBuf::from),
+    };
+
+    let runs = number_arg("--runs", 20);
+    let first_seed = number_arg("--seed", 1);
+    let reports: Vec<RunReport> = (0..runs).map(|i| batch.run(first_seed + i)).collect();
+
+    let json_path = arg_value("--json");
+    let csv_path = arg_value("--csv").or_else(|| json_path.is_none().then(|| DEFAULT_CSV.to_string()));
+    if let Some(path) = csv_path.as_ref() {
+        fs::write(path, to_csv(&reports, &batch.enemy_defs)).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+        eprintln!("wrote {}", path);
+    }
+    if let Some(path) = json_path.as_ref() {
+        let json = serde_json::to_string_pretty(&reports).unwrap_or_else(|e| fail(e));
+        fs::write(path, json).unwrap_or_else(|e| fail(format!("{}: {}", path, e)));
+        eprintln!("wrote {}", path);
+    }
+    print_summary(&reports);
+}
@@ -0,0 +1,121 @@
+//! Prints how the difficulty presets ramp up over a run, to tune them
+//! without playing: `cargo run --bin difficulty [-- --csv]`.
+//!
+//! Level and kill rate depend on how the run goes, they're assumed to grow
+//! steadily: `--levels-per-minute <n>` (2 by default) and `--kill-rate <n>`
+//! kills per second (1 by default).
+
+use lowrez2023::difficulty::Preset;
+use lowrez2023::world::STAGE_DURATION_MILLIS;
+
+/// Seconds between two rows of the table
+const TABLE_STEP: u32 = 15;
+/// Seconds per column of the plot
+const PLOT_STEP: u32 = 5;
+const PLOT_HEIGHT: usize = 16;
+
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let position = args.iter().position(|a| a == name)?;
+    args.get(position + 1).cloned()
+}
+
+fn number_arg(name: &str, default: f32) -> f32 {
+    match arg_value(name).map(|v| v.parse::<f32>()) {
+        None => default,
+        Some(Ok(v)) => v,
+        Some(Err(_)) => {
+            eprintln!("{} expects a number, using {}", name, default);
+            default
+        }
+    }
+}
+
+struct Assumptions {
+    levels_per_minute: f32,
+    kill_rate: f32,
+}
+
+impl Assumptions {
+    fn level_at(&self, secs: u32) -> i32 {
+        1 + (self.levels_per_minute * secs as f32 / 60.) as i32
+    }
+
+    fn difficulty(&self, preset: Preset, secs: u32) -> f32 {
+        preset.curve().value(secs as f32, self.level_at(secs), self.kill_rate)
+    }
+}
+
+fn print_csv(assumptions: &Assumptions, stage_secs: u32) {
+    println!("preset,secs,level,difficulty,enemy_hp,spawn_caps,enemy_xp,projectile_speed");
+    for preset in Preset::ALL {
+        for secs in (0..=stage_secs).step_by(TABLE_STEP as usize) {
+            let d = assumptions.difficulty(preset, secs);
+            let s = preset.curve().scaling(d);
+            println!(
+                "{},{},{},{:.3},{:.3},{:.3},{:.3},{:.3}",
+                preset.name(), secs, assumptions.level_at(secs), d, s.enemy_hp, s.spawn_caps, s.enemy_xp, s.projectile_speed
+            );
+        }
+    }
+}
+
+fn print_tables(assumptions: &Assumptions, stage_secs: u32) {
+    for preset in Preset::ALL {
+        println!("{}", preset.name());
+        println!("  time  level  difficulty  enemy hp  spawn caps  xp/kill  projectile speed");
+        for secs in (0..=stage_secs).step_by(TABLE_STEP as usize) {
+            let d = assumptions.difficulty(preset, secs);
+            let s = preset.curve().scaling(d);
+            println!(
+                "  {}:{:02}  {:>5}  {:>10.2}  {:>7.2}x  {:>9.2}x  {:>7.1}  {:>15.2}x",
+                secs / 60, secs % 60, assumptions.level_at(secs), d, s.enemy_hp, s.spawn_caps, s.enemy_xp, s.projectile_speed
+            );
+        }
+        println!();
+    }
+}
+
+/// Difficulty over time, one letter per preset
+fn print_plot(assumptions: &Assumptions, stage_secs: u32) {
+    let columns = (stage_secs / PLOT_STEP + 1) as usize;
+    let max = Preset::ALL.iter()
+        .map(|p| assumptions.difficulty(*p, stage_secs))
+        .fold(0f32, f32::max)
+        .max(1.);
+    let mut rows = vec![vec![' '; columns]; PLOT_HEIGHT];
+    for preset in Preset::ALL {
+        let mark = preset.name().chars().next().unwrap().to_ascii_uppercase();
+        for (column, secs) in (0..=stage_secs).step_by(PLOT_STEP as usize).enumerate() {
+            let d = assumptions.difficulty(preset, secs);
+            let row = ((d / max) * (PLOT_HEIGHT - 1) as f32).round() as usize;
+            rows[PLOT_HEIGHT - 1 - row.min(PLOT_HEIGHT - 1)][column] = mark;
+        }
+    }
+    println!("difficulty (E easy, N normal, H hard)");
+    for (i, row) in rows.iter().enumerate() {
+        let value = max * (PLOT_HEIGHT - 1 - i) as f32 / (PLOT_HEIGHT - 1) as f32;
+        println!("{:>6.2} |{}", value, row.iter().collect::<String>());
+    }
+    println!("       +{}", "-".repeat(columns));
+    println!("        0:00{:>width$}", format!("{}:{:02}", stage_secs / 60, stage_secs % 60), width = columns - 4);
+}
+
+fn main() {
+    let assumptions = Assumptions {
+        levels_per_minute: number_arg("--levels-per-minute", 2.),
+        kill_rate: number_arg("--kill-rate", 1.),
+    };
+    let stage_secs = (STAGE_DURATION_MILLIS / 1000) as u32;
+
+    if std::env::args().any(|a| a == "--csv") {
+        print_csv(&assumptions, stage_secs);
+        return;
+    }
+    println!(
+        "assuming {} levels a minute and {} kills a second\n",
+        assumptions.levels_per_minute, assumptions.kill_rate
+    );
+    print_tables(&assumptions, stage_secs);
+    print_plot(&assumptions, stage_secs);
+}
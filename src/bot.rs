@@ -0,0 +1,195 @@
+use macroquad::prelude::*;
+
+use crate::direction::{direction_from_vec, quantize};
+use crate::input::InputState;
+use crate::player::Player;
+use crate::player_stats::Stat;
+use crate::upgrade::Upgrade;
+use crate::world::World;
+
+/// Where the player starts, the bot drifts back towards it when idle
+const HOME: Vec2 = Vec2::new(128., 128.);
+
+/// Plays the game by itself, for balance runs without a human. It produces
+/// the same input the keyboard does, so its runs can be recorded and replayed.
+///
+/// It keeps away from where enemies are packed the thickest, collects gems
+/// while nothing is close, dashes when a projectile or an enemy is about to
+/// hit and picks upgrades by preference.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bot {
+    /// Upgrade ids, most wanted first. Offers without any of them fall back
+    /// to the first card.
+    pub upgrade_preferences: Vec<String>,
+    /// Enemies closer than this push the bot away, the closer the harder
+    pub danger_radius: f32,
+    /// Incoming projectiles closer than this get dashed through
+    pub dodge_radius: f32,
+    /// Enemies closer than this get dashed away from
+    pub panic_radius: f32,
+    /// How far it wanders from the start before heading back
+    pub leash: f32,
+    /// Gems and drops closer than this get collected when it's safe
+    pub gem_radius: f32,
+}
+
+impl Default for Bot {
+    fn default() -> Self {
+        Bot {
+            upgrade_preferences: Vec::new(),
+            danger_radius: 40.,
+            dodge_radius: 12.,
+            panic_radius: 9.,
+            leash: 96.,
+            gem_radius: 64.,
+        }
+    }
+}
+
+impl Bot {
+    pub fn new(upgrade_preferences: Vec<String>) -> Self {
+        Bot { upgrade_preferences, ..Default::default() }
+    }
+
+    /// What it would press this tick
+    pub fn input(&self, world: &World, player: &Player) -> InputState {
+        if !player.active {
+            return InputState::default();
+        }
+        let center = vec2(player.pos_x + 2., player.pos_y + 2.);
+
+        // Every enemy pushes away, weighted by how close it is, so the sum
+        // points away from the densest crowd
+        let mut away = Vec2::ZERO;
+        let mut closest = f32::MAX;
+        for e in world.enemies.iter().filter(|e| e.alive) {
+            let offset = center - vec2(e.position.x, e.position.y);
+            let distance = offset.length();
+            closest = closest.min(distance);
+            if distance < self.danger_radius && distance > 0. {
+                away += offset / (distance * distance);
+            }
+        }
+
+        let from_home = center - HOME;
+        // The closest drop that won't fly over by itself
+        let pull = world.pickups.iter()
+            .map(|p| vec2(p.x, p.y) - center)
+            .filter(|offset| offset.length() < self.gem_radius && offset.length() > player.stat(Stat::PickupRadius) * 0.8)
+            .min_by(|a, b| a.length().total_cmp(&b.length()))
+            .map_or(Vec2::ZERO, |offset| offset.normalize());
+        let heading = if away == Vec2::ZERO {
+            // Nothing around, go collect what's close or wander back
+            // towards the start
+            if pull != Vec2::ZERO {
+                pull
+            } else if from_home.length() > self.leash / 2. {
+                -from_home
+            } else {
+                Vec2::ZERO
+            }
+        } else {
+            let mut heading = away.normalize() + pull * 0.8;
+            if from_home.length() > self.leash {
+                // Don't get cornered out in the open either
+                heading -= from_home.normalize() * 0.5;
+            }
+            heading
+        };
+        let step = quantize(heading);
+
+        let incoming = world.enemy_bullets.iter().filter(|b| b.active).any(|b| {
+            let offset = center - vec2(b.x, b.y);
+            // Bullets move against their dir
+            offset.length() < self.dodge_radius && offset.dot(vec2(-b.dir_x, -b.dir_y)) > 0.
+        });
+        let cornered = closest < self.panic_radius && player.inv_timer.value(world.clock.now()) == 1.0;
+        let dash_direction = if incoming || cornered {
+            // Through whatever's coming, or sideways if there's nowhere to go
+            direction_from_vec(step)
+                .or_else(|| direction_from_vec(quantize(vec2(-heading.y, heading.x))))
+                .and_then(|d| InputState::dash_towards(d.to_vec()))
+        } else {
+            None
+        };
+
+        InputState {
+            move_x: step.x,
+            // Input y points up, screen y points down
+            move_y: -step.y,
+            dash_pressed: dash_direction.is_some() && !player.is_dashing,
+            dash_direction,
+        }
+    }
+
+    /// Index of the card it picks out of `offered`
+    pub fn choose_upgrade(&self, offered: &[Box<dyn Upgrade>]) -> usize {
+        let rank = |u: &dyn Upgrade| {
+            self.upgrade_preferences.iter().position(|p| p == u.get_name()).unwrap_or(usize::MAX)
+        };
+        (0..offered.len()).min_by_key(|&i| rank(offered[i].as_ref())).unwrap_or(0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Bullet;
+    use crate::enemies::Enemy;
+    use crate::upgrade::UpgradeDefs;
+
+    fn offer(ids: &[&str]) -> Vec<Box<dyn Upgrade>> {
+        let defs = UpgradeDefs::builtin();
+        ids.iter().map(|id| Box::new(defs.get(id).unwrap().clone()) as Box<dyn Upgrade>).collect()
+    }
+
+    /// A world with one `kind` enemy `dx` to the right of the player
+    fn world_with(kind: &str, player: &Player, dx: f32) -> World {
+        let mut world = World::new(1);
+        let def = world.enemy_defs.get(kind).unwrap();
+        let enemy = Enemy::from_def(def, player.pos_x + 2. + dx, player.pos_y + 2., 1., 0.);
+        world.enemies.push(enemy);
+        world
+    }
+
+    #[test]
+    fn picks_by_preference_then_the_first_card() {
+        let bot = Bot::new(vec!["Recovery".to_string(), "Dash".to_string()]);
+        assert_eq!(bot.choose_upgrade(&offer(&["Speed", "Dash", "Recovery"])), 2);
+        assert_eq!(bot.choose_upgrade(&offer(&["Speed", "Dash"])), 1);
+        assert_eq!(bot.choose_upgrade(&offer(&["Speed", "FireRate"])), 0);
+        assert_eq!(bot.choose_upgrade(&[]), 0);
+    }
+
+    #[test]
+    fn walks_away_from_a_nearby_enemy() {
+        let bot = Bot::default();
+        let player = Player::new();
+        let world = world_with("slime", &player, 20.);
+        let input = bot.input(&world, &player);
+        assert!(input.move_x < 0., "{:?}", input);
+        assert_eq!(input.move_y, 0.);
+        assert!(!input.dash_pressed);
+    }
+
+    #[test]
+    fn dashes_out_of_the_way_of_incoming_bullets() {
+        let bot = Bot::default();
+        let player = Player::new();
+        let mut world = world_with("tower", &player, 20.);
+        // Bullets fly against their dir, this one straight at the player
+        let mut bullet = Bullet {
+            x: player.pos_x + 8., y: player.pos_y + 2., dir_x: 1., dir_y: 0., speed: 20., damage: 10.,
+            active: true, radius: 0., pierce: 0, hit: Vec::new(),
+        };
+        world.enemy_bullets.push(bullet.clone());
+        let input = bot.input(&world, &player);
+        assert!(input.dash_pressed);
+        assert!(input.dash_direction.is_some_and(|d| d.x < 0.), "{:?}", input);
+
+        // Flying away is no threat
+        bullet.dir_x = -1.;
+        world.enemy_bullets = vec![bullet];
+        assert!(!bot.input(&world, &player).dash_pressed);
+    }
+}
@@ -0,0 +1,119 @@
+/// Game time in seconds. Starts at zero and only moves forward when
+/// [`GameClock::advance`] is called, so it can be paused, slowed down, sped
+/// up or stepped by hand from a test. Each [`World`](crate::world::World)
+/// owns one and hands its `now` to the timers it checks.
+#[derive(Debug, Clone, PartialEq)]
+pub struct GameClock {
+    now: f64,
+    scale: f32,
+    paused: bool,
+}
+
+impl Default for GameClock {
+    fn default() -> Self {
+        GameClock::new()
+    }
+}
+
+impl GameClock {
+    pub fn new() -> Self {
+        GameClock { now: 0., scale: 1., paused: false }
+    }
+
+    pub fn now(&self) -> f64 {
+        self.now
+    }
+
+    /// Moves the clock by `delta` real seconds and returns how much game
+    /// time actually passed.
+    pub fn advance(&mut self, delta: f32) -> f32 {
+        if self.paused {
+            return 0.;
+        }
+        let scaled = delta * self.scale;
+        self.now += scaled as f64;
+        scaled
+    }
+
+    pub fn pause(&mut self) {
+        self.paused = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.paused = false;
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused
+    }
+
+    pub fn set_scale(&mut self, scale: f32) {
+        self.scale = scale.max(0.);
+    }
+
+    pub fn scale(&self) -> f32 {
+        self.scale
+    }
+
+    /// Puts the clock back at `now`, e.g. when a saved run is resumed, so
+    /// the saved timers keep their progress
+    pub fn set_now(&mut self, now: f64) {
+        self.now = now;
+    }
+}
+
+/// Wall-clock time in seconds, for menus and title screen effects that
+/// keep moving while the game is paused
+pub fn real_now() -> f64 {
+    macroquad::miniquad::date::now()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::timer::Timer;
+
+    #[test]
+    fn advances_with_scale_and_pause() {
+        let mut clock = GameClock::default();
+        assert_eq!(clock.advance(0.5), 0.5);
+        clock.set_scale(2.);
+        assert_eq!(clock.advance(0.25), 0.5);
+        clock.pause();
+        assert_eq!(clock.advance(1.), 0.);
+        clock.resume();
+        clock.set_scale(-3.);
+        assert_eq!(clock.advance(1.), 0.);
+        assert_eq!(clock.now(), 1.);
+    }
+
+    #[test]
+    fn set_now_keeps_scale_and_pause() {
+        let mut clock = GameClock::new();
+        clock.advance(2.);
+        clock.set_now(90.);
+        clock.advance(1.);
+        assert_eq!(clock.now(), 91.);
+        clock.pause();
+        clock.set_now(10.);
+        assert!(clock.is_paused());
+        assert_eq!(clock.now(), 10.);
+    }
+
+    #[test]
+    fn timers_read_the_clock_they_are_given() {
+        let mut clock = GameClock::new();
+        let game = Timer::new(50, clock.now());
+        clock.pause();
+        assert_eq!(clock.advance(0.05), 0.);
+        assert!(!game.finished(clock.now()));
+        clock.resume();
+        clock.advance(0.05);
+        assert!(game.finished(clock.now()));
+        // Another clock moving on leaves this one and its timers alone
+        let late = Timer::new(60_000, clock.now());
+        let mut other = GameClock::new();
+        other.advance(3600.);
+        assert!(!late.finished(clock.now()));
+    }
+}
@@ -2,6 +2,7 @@ use macroquad::prelude::*;
 use keyframe::{Keyframe, functions::{EaseOut, EaseInOut}};
 
 use crate::{tween::Tween, Position};
+use crate::events::{EventQueue, GameEvent};
 
 pub struct DamagePopup {
     pos: Position,
@@ -12,7 +13,7 @@ pub struct DamagePopup {
 }
 
 impl DamagePopup {
-    pub fn new(x: f32, y: f32, amount: i32) -> Self {
+    pub fn new(x: f32, y: f32, amount: i32, now: f64) -> Self {
         let opacity_tween = Tween::from_keyframes(
             vec![
                 Keyframe::new(1.0, 0.0, EaseOut),
@@ -21,6 +22,7 @@ impl DamagePopup {
             0,
             1,
             false,
+            now,
         );
         let tween = Tween::from_keyframes(
             vec![
@@ -30,13 +32,14 @@ impl DamagePopup {
             0,
             5,
             false,
+            now,
         );
         DamagePopup { pos: Position { x, y }, tween, opacity_tween, active: true, amount }
     }
 
-    pub fn update(&mut self) {
-        self.tween.update();
-        self.opacity_tween.update();
+    pub fn update(&mut self, now: f64) {
+        self.tween.update(now);
+        self.opacity_tween.update(now);
         if self.tween.finished() {
             self.active = false;
         }
@@ -59,3 +62,15 @@ impl DamagePopup {
         }
     }
 }
+
+/// A popup over everyone who got hurt
+pub fn spawn_damage_popups(popups: &mut Vec<DamagePopup>, events: &EventQueue, now: f64) {
+    for event in events.iter() {
+        match event {
+            GameEvent::EnemyDamaged { x, y, amount, .. } | GameEvent::PlayerHit { x, y, amount, .. } => {
+                popups.push(DamagePopup::new(*x, *y, amount.abs() as i32, now));
+            }
+            _ => {}
+        }
+    }
+}
@@ -0,0 +1,62 @@
+use std::collections::HashSet;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::de::DeserializeOwned;
+
+/// What's wrong with one of the `.ron` files under `assets/`. Callers say
+/// which file it was.
+#[derive(Debug)]
+pub enum DefError {
+    Io(io::Error),
+    Format(String),
+    /// The definition with this id doesn't make sense
+    InvalidDef { id: String, reason: String },
+    DuplicateId(String),
+    /// The file as a whole doesn't make sense
+    Invalid(String),
+}
+
+impl fmt::Display for DefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DefError::Io(e) => write!(f, "couldn't read it: {}", e),
+            DefError::Format(e) => write!(f, "doesn't parse: {}", e),
+            DefError::InvalidDef { id, reason } => write!(f, "\"{}\": {}", id, reason),
+            DefError::DuplicateId(id) => write!(f, "\"{}\" is defined more than once", id),
+            DefError::Invalid(reason) => write!(f, "{}", reason),
+        }
+    }
+}
+
+impl std::error::Error for DefError {}
+
+impl From<io::Error> for DefError {
+    fn from(e: io::Error) -> Self {
+        DefError::Io(e)
+    }
+}
+
+/// Reads `path` and parses it as `T` would from a string
+pub fn load<T: FromStr<Err = DefError>, P: AsRef<Path>>(path: P) -> Result<T, DefError> {
+    fs::read_to_string(path)?.parse()
+}
+
+/// Just the RON, whatever checking `T` needs is up to the caller
+pub fn from_ron<T: DeserializeOwned>(s: &str) -> Result<T, DefError> {
+    ron::from_str(s).map_err(|e| DefError::Format(e.to_string()))
+}
+
+/// Every id, failing on the first one seen twice
+pub fn unique_ids<'a, I: IntoIterator<Item = &'a str>>(ids: I) -> Result<HashSet<&'a str>, DefError> {
+    let mut seen = HashSet::new();
+    for id in ids {
+        if !seen.insert(id) {
+            return Err(DefError::DuplicateId(id.to_string()));
+        }
+    }
+    Ok(seen)
+}
@@ -0,0 +1,222 @@
+use serde::{Serialize, Deserialize};
+
+/// Kills per second are averaged over about this many seconds
+const KILL_RATE_WINDOW: f32 = 30.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Preset {
+    Easy,
+    Normal,
+    Hard,
+}
+
+impl Preset {
+    pub const ALL: [Preset; 3] = [Preset::Easy, Preset::Normal, Preset::Hard];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Preset::Easy => "easy",
+            Preset::Normal => "normal",
+            Preset::Hard => "hard",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Preset> {
+        Preset::ALL.into_iter().find(|p| p.name().eq_ignore_ascii_case(name))
+    }
+
+    pub fn curve(&self) -> DifficultyCurve {
+        match self {
+            Preset::Easy => DifficultyCurve {
+                start: 0.,
+                per_minute: 0.75,
+                per_level: 0.15,
+                per_kill_rate: 0.5,
+                enemy_hp: 0.05,
+                spawn_caps: 0.03,
+                base_xp: 45.,
+                xp_decay: 0.8,
+                min_xp: 5.,
+                projectile_speed: 0.03,
+            },
+            Preset::Normal => DifficultyCurve {
+                start: 0.,
+                per_minute: 1.,
+                per_level: 0.25,
+                per_kill_rate: 1.,
+                enemy_hp: 0.1,
+                spawn_caps: 0.05,
+                base_xp: 40.,
+                xp_decay: 0.7,
+                min_xp: 3.,
+                projectile_speed: 0.05,
+            },
+            Preset::Hard => DifficultyCurve {
+                start: 1.,
+                per_minute: 1.5,
+                per_level: 0.35,
+                per_kill_rate: 1.5,
+                enemy_hp: 0.15,
+                spawn_caps: 0.08,
+                base_xp: 35.,
+                xp_decay: 0.65,
+                min_xp: 2.,
+                projectile_speed: 0.08,
+            },
+        }
+    }
+}
+
+/// How hard the run gets, and what that does to it.
+///
+/// Difficulty is a single number made of time survived, player level and
+/// how fast the player is killing things. Everything it drives grows (or,
+/// for XP, shrinks) with it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DifficultyCurve {
+    pub start: f32,
+    pub per_minute: f32,
+    /// For every level past the first
+    pub per_level: f32,
+    /// For every kill per second
+    pub per_kill_rate: f32,
+    /// Extra share of enemy HP per difficulty point
+    pub enemy_hp: f32,
+    /// Extra share of the timeline's spawn caps per difficulty point
+    pub spawn_caps: f32,
+    /// XP a kill is worth at difficulty 0
+    pub base_xp: f32,
+    /// Share of the XP left after every difficulty point
+    pub xp_decay: f32,
+    pub min_xp: f32,
+    /// Extra share of enemy projectile speed per difficulty point
+    pub projectile_speed: f32,
+}
+
+/// What the current difficulty does to newly spawned enemies
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Scaling {
+    pub enemy_hp: f32,
+    pub spawn_caps: f32,
+    /// XP a kill is worth, not a multiplier
+    pub enemy_xp: f32,
+    pub projectile_speed: f32,
+}
+
+impl DifficultyCurve {
+    pub fn value(&self, elapsed_secs: f32, level: i32, kill_rate: f32) -> f32 {
+        self.start
+            + self.per_minute * elapsed_secs / 60.
+            + self.per_level * (level - 1).max(0) as f32
+            + self.per_kill_rate * kill_rate
+    }
+
+    pub fn scaling(&self, difficulty: f32) -> Scaling {
+        Scaling {
+            enemy_hp: 1. + self.enemy_hp * difficulty,
+            spawn_caps: 1. + self.spawn_caps * difficulty,
+            enemy_xp: (self.base_xp * self.xp_decay.powf(difficulty)).max(self.min_xp),
+            projectile_speed: 1. + self.projectile_speed * difficulty,
+        }
+    }
+}
+
+/// The difficulty of the run being played
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Difficulty {
+    pub preset: Preset,
+    pub curve: DifficultyCurve,
+    /// Kills per second, averaged over the last half minute or so
+    pub kill_rate: f32,
+}
+
+impl Difficulty {
+    pub fn new(preset: Preset) -> Self {
+        Difficulty { preset, curve: preset.curve(), kill_rate: 0. }
+    }
+
+    pub fn reset(&mut self) {
+        self.kill_rate = 0.;
+    }
+
+    pub fn record_kills(&mut self, kills: u32, delta: f32) {
+        self.kill_rate += (kills as f32 - self.kill_rate * delta) / KILL_RATE_WINDOW;
+    }
+
+    pub fn value(&self, elapsed_secs: f32, level: i32) -> f32 {
+        self.curve.value(elapsed_secs, level, self.kill_rate)
+    }
+
+    pub fn scaling(&self, elapsed_secs: f32, level: i32) -> Scaling {
+        self.curve.scaling(self.value(elapsed_secs, level))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Every scaled quantity, signed so that bigger is harder
+    fn hardness(s: Scaling) -> [f32; 4] {
+        [s.enemy_hp, s.spawn_caps, -s.enemy_xp, s.projectile_speed]
+    }
+
+    fn assert_no_easier(easier: Scaling, harder: Scaling, what: &str) {
+        for (a, b) in hardness(easier).into_iter().zip(hardness(harder)) {
+            assert!(a <= b, "{}: {:?} is harder than {:?}", what, easier, harder);
+        }
+    }
+
+    #[test]
+    fn presets_are_in_order() {
+        for secs in [0., 30., 120., 240.] {
+            for level in [1, 5, 20] {
+                for kill_rate in [0., 0.5, 3.] {
+                    let scaling = |p: Preset| p.curve().scaling(p.curve().value(secs, level, kill_rate));
+                    let what = format!("{}s, level {}, {} kills/s", secs, level, kill_rate);
+                    assert_no_easier(scaling(Preset::Easy), scaling(Preset::Normal), &what);
+                    assert_no_easier(scaling(Preset::Normal), scaling(Preset::Hard), &what);
+                }
+            }
+        }
+        for preset in Preset::ALL {
+            assert_eq!(Preset::from_name(&preset.name().to_uppercase()), Some(preset));
+        }
+    }
+
+    #[test]
+    fn scaling_grows_with_time() {
+        for preset in Preset::ALL {
+            let difficulty = Difficulty::new(preset);
+            let mut previous = difficulty.scaling(0., 1);
+            for secs in 1..=300 {
+                let scaling = difficulty.scaling(secs as f32, 1);
+                assert_no_easier(previous, scaling, preset.name());
+                previous = scaling;
+            }
+            assert!(difficulty.value(300., 1) > difficulty.value(0., 1));
+        }
+    }
+
+    #[test]
+    fn kill_rate_settles_whatever_the_frame_rate() {
+        let kills_per_sec = 2.;
+        let settle = |delta: f32| {
+            let mut difficulty = Difficulty::new(Preset::Normal);
+            // Kills come in whole numbers, owed ones carry over
+            let mut owed = 0.;
+            for _ in 0..(300. / delta) as usize {
+                owed += kills_per_sec * delta;
+                let kills = owed.floor();
+                owed -= kills;
+                difficulty.record_kills(kills as u32, delta);
+            }
+            difficulty.kill_rate
+        };
+        for delta in [1. / 30., 1. / 144.] {
+            let rate = settle(delta);
+            assert!((rate - kills_per_sec).abs() < 0.1, "{} kills/s at {} fps", rate, 1. / delta);
+        }
+        assert!((settle(1. / 30.) - settle(1. / 144.)).abs() < 0.1);
+    }
+}
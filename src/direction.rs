@@ -1,26 +1,30 @@
 use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, Serialize, Deserialize)]
 pub enum Direction {
     Up, Down, Left, Right, UpLeft, UpRight, DownLeft, DownRight,
 }
 
-pub fn get_direction() -> Option<Direction> {
-    let mut dir_vec = vec2(0.,0.);
-    
-    if is_key_down(KeyCode::Up) {
-        dir_vec.y = -1.;
-    } 
-    if is_key_down(KeyCode::Down) {
-        dir_vec.y = 1.; 
-    }
-     if is_key_down(KeyCode::Left) {
-        dir_vec.x = -1.;
-    }
-    if is_key_down(KeyCode::Right) {
-        dir_vec.x = 1.;
+impl Direction {
+    /// Screen-space unit vector (y grows downwards) pointing this way
+    pub fn to_vec(self) -> Vec2 {
+        let v = match self {
+            Direction::Up => vec2(0., -1.),
+            Direction::Down => vec2(0., 1.),
+            Direction::Left => vec2(-1., 0.),
+            Direction::Right => vec2(1., 0.),
+            Direction::UpLeft => vec2(-1., -1.),
+            Direction::UpRight => vec2(1., -1.),
+            Direction::DownLeft => vec2(-1., 1.),
+            Direction::DownRight => vec2(1., 1.),
+        };
+        v.normalize()
     }
+}
 
+/// Screen-space direction (y grows downwards) to one of the eight dash directions
+pub fn direction_from_vec(dir_vec: Vec2) -> Option<Direction> {
     if dir_vec.x == 0. && dir_vec.y == -1. {
         Some(Direction::Up)
     } else if dir_vec.x == 0. && dir_vec.y == 1. {
@@ -41,3 +45,16 @@ pub fn get_direction() -> Option<Direction> {
         None
     }
 }
+
+/// One of the eight directions (or none) closest to `v`, each axis -1, 0 or 1
+/// like the arrow keys give
+pub fn quantize(v: Vec2) -> Vec2 {
+    let length = v.length();
+    if length == 0. || !length.is_finite() {
+        return Vec2::ZERO;
+    }
+    // Diagonals win within 22.5 degrees of them
+    let threshold = length * 0.3827;
+    let axis = |c: f32| if c > threshold { 1. } else if c < -threshold { -1. } else { 0. };
+    vec2(axis(v.x), axis(v.y))
+}
@@ -3,9 +3,17 @@ use std::collections::HashMap;
 use instant::Duration;
 use keyframe::{Keyframe, functions::{EaseOut, EaseInOut}};
 use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
 use tween::{Tweener, SineOut};
 
-use crate::{Position, Collider, timer::Timer, tween::Tween, animation::Animation, Bullet, particles::{spawn_particle, ShotParticle, Particle, EnemyShotParticle}, damage_popup::DamagePopup, TestTween};
+use crate::{Position, Collider, timer::Timer, tween::Tween, animation::Animation, Bullet, particles::{spawn_particle, Particle, EnemyShotParticle}, TestTween};
+use crate::rng::Rng;
+use crate::spatial::SpatialGrid;
+use crate::enemy_defs::{EnemyDef, Movement};
+use crate::difficulty::Scaling;
+use crate::stats;
+use crate::events::{EventQueue, GameEvent};
+use crate::player::Player;
 use super::{col, get_dir_, dist};
 
 pub struct DeadEnemy {
@@ -17,7 +25,7 @@ pub struct DeadEnemy {
 }
 
 impl DeadEnemy {
-    pub fn new(x: f32, y: f32, frame: Option<Rect>) -> Self {
+    pub fn new(x: f32, y: f32, frame: Option<Rect>, now: f64) -> Self {
         let move_tween = Tween::from_keyframes(
             vec![
                 Keyframe::new(0.0, 0.0, EaseOut),
@@ -26,6 +34,7 @@ impl DeadEnemy {
             0,
             1,
             false,
+            now,
         );
 
         let opacity_tween : TestTween<f32, f32> = Tweener::new(1.0, 0., 1.5, Box::new(SineOut));
@@ -33,162 +42,308 @@ impl DeadEnemy {
     }
 }
 
-#[derive(Clone)]
-pub struct Enemies {
+/// How an enemy moves. Everything else (hp, xp, dying, getting cleaned up)
+/// works the same for every enemy.
+#[derive(Clone, Serialize, Deserialize)]
+pub enum Behaviour {
+    /// Walks straight to the player, shoving other chasers out of the way
+    Chase { speed: f32 },
+    /// Flies across the screen in a wave
+    Wave { initial_y: f32, x_dir: f32, speed: f32, amplitude: f32, wavelength: f32 },
+    Stationary,
+}
+
+/// Shoots at the player every `cooldown`
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Gun {
+    pub cooldown: Timer,
+    pub range: f32,
+    pub speed: f32,
+    pub damage: f32,
+}
+
+/// How long an enemy stays tinted after getting hit, in seconds
+const HIT_FLASH: f32 = 0.1;
+
+/// Where an enemy can be shot, relative to its position
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Hurtbox {
+    pub offset_x: f32,
+    pub offset_y: f32,
+    pub radius: f32,
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Enemy {
+    /// Id of the [`EnemyDef`] it was made from
+    pub kind: String,
+    /// Unique within a run, given when it spawns
+    #[serde(default)]
+    pub id: u64,
     pub position: Position,
     pub collider: Collider,
+    pub hurtbox: Hurtbox,
     pub alive: bool,
     pub hp: f32,
-    pub tween: Tween,
+    /// Seconds left of the hit flash
+    pub flash: f32,
+    pub given_xp: f32,
+    /// What touching the player does to the player, 0 means harmless
+    pub contact_damage: f32,
+    /// What touching the player does to the enemy itself
+    pub contact_self_damage: f32,
+    pub behaviour: Behaviour,
+    pub gun: Option<Gun>,
+    /// Leaves without giving XP once this runs out
+    pub lifetime: Option<Timer>,
+    #[serde(skip)]
     pub anims: HashMap<String, Animation>,
+    #[serde(skip)]
     pub curr_frame: Option<Rect>,
-    given_xp: f32
 }
 
-impl Enemies {
-    pub fn get_given_xp(&self) -> f32 {
-        self.given_xp
-    }
-
-    pub fn new(x: f32, y: f32, given_xp: f32) -> Self {
-        let tween = Tween::from_keyframes(
-            vec![
-                Keyframe::new(0.0, 0.0, EaseOut),
-                Keyframe::new(20.0, 0.2, EaseOut),
-            ],
-            0,
-            1,
-            false,
-        );
-        let mut idle_state_rects : Vec<Rect> = Vec::new();
-        idle_state_rects.push(Rect::new(1., 1., 9., 9.));
-        idle_state_rects.push(Rect::new(10., 1., 9., 9.));
-        idle_state_rects.push(Rect::new(19., 1., 9., 9.));
-        idle_state_rects.push(Rect::new(28., 1., 9., 9.));
-    
-        let idle_state_frame_lenghts : Vec<Duration> = vec![Duration::from_millis(200); idle_state_rects.len()];
-    
-        let idle_animation = Animation {
-            frames: idle_state_rects.clone(),
-            frame_length: idle_state_frame_lenghts.clone(),
-            anim_duration: Duration::from_secs(0),
-            current_frame: 0,
-            current_frame_length: idle_state_frame_lenghts[0],
-            repeating: true
+impl Enemy {
+    pub fn from_def(def: &EnemyDef, x: f32, y: f32, given_xp: f32, now: f64) -> Self {
+        let anims = def.animations();
+        let curr_frame = anims["idle"].frames.first().copied();
+        let behaviour = match def.movement {
+            Movement::Chase { speed } => Behaviour::Chase { speed },
+            Movement::Wave { speed, amplitude, wavelength } => Behaviour::Wave { initial_y: y, x_dir: 1., speed, amplitude, wavelength },
+            Movement::Stationary => Behaviour::Stationary,
         };
-        
-        let mut anims = HashMap::new();
-        anims.insert("idle".to_string(), idle_animation);
-
-        Enemies {
-            position: Position {
-                x,
-                y,
-            },
-            collider: Collider { 
+        Enemy {
+            kind: def.id.clone(),
+            id: 0,
+            position: Position { x, y },
+            collider: Collider {
                 x: 72.,
                 y: 90.,
-                width: 8, 
+                width: 8,
                 height: 8,
-                radius: 4. 
+                radius: def.collider_radius
             },
-            hp: 2.,
+            hurtbox: def.hurtbox,
             alive: true,
-            tween,
+            hp: def.hp,
+            flash: 0.,
+            given_xp: given_xp * def.xp,
+            contact_damage: def.contact_damage,
+            contact_self_damage: def.contact_self_damage,
+            behaviour,
+            gun: def.projectile.as_ref().map(|p| Gun {
+                cooldown: Timer::new(p.cooldown_millis, now),
+                range: p.range,
+                speed: p.speed,
+                damage: p.damage,
+            }),
+            lifetime: def.lifetime_millis.map(|millis| Timer::new(millis, now)),
             anims,
-            curr_frame: Some(Rect::new(1., 1., 9., 9.)),
-            given_xp
+            curr_frame,
+        }
+    }
+
+    /// Which way a wave flyer goes, 1 for right and -1 for left
+    pub fn heading(mut self, x_dir: f32) -> Self {
+        if let Behaviour::Wave { x_dir: dir, .. } = &mut self.behaviour {
+            *dir = x_dir;
+        }
+        self
+    }
+
+    /// Tougher, with faster projectiles, as the run gets harder
+    pub fn scaled(mut self, scaling: &Scaling) -> Self {
+        self.hp *= scaling.enemy_hp;
+        if let Some(gun) = &mut self.gun {
+            gun.speed *= scaling.projectile_speed;
         }
+        self
+    }
+
+    pub fn is_hit_by(&self, pos: Position) -> bool {
+        self.is_hit_within(pos, 0.)
+    }
+
+    /// Whether a circle of `radius` around `pos` touches its hurtbox
+    pub fn is_hit_within(&self, pos: Position, radius: f32) -> bool {
+        col(
+            pos,
+            Position { x: self.position.x + self.hurtbox.offset_x, y: self.position.y + self.hurtbox.offset_y },
+            self.hurtbox.radius + radius
+        )
+    }
+
+    /// Takes `amount` of damage and flashes. Dying is left to the kill pass.
+    pub fn hurt(&mut self, amount: f32, events: &mut EventQueue) {
+        events.emit(GameEvent::EnemyDamaged { kind: self.kind.clone(), x: self.position.x, y: self.position.y, amount });
+        self.flash = HIT_FLASH;
+        self.hp -= amount;
+    }
+
+    /// How far from its position this enemy can be hit
+    pub fn hurtbox_reach(&self) -> f32 {
+        self.hurtbox.offset_x.abs().max(self.hurtbox.offset_y.abs()) + self.hurtbox.radius
+    }
+
+    /// Chasers are the only ones that get in each other's way
+    pub fn pushes(&self) -> bool {
+        matches!(self.behaviour, Behaviour::Chase { .. })
     }
 }
 
-pub fn update_enemies_position(enemies: &mut Vec<Enemies>, x: &mut f32, y: &mut f32) {
-    let delta = get_frame_time();
-    let player_vec: Vec2 = Vec2::new(*x, *y);
+pub fn update_enemies(enemies: &mut [Enemy], player_x: &f32, player_y: &f32, bullets: &mut Vec<Bullet>, events: &mut EventQueue, delta: f32, now: f64) {
+    let player_vec: Vec2 = Vec2::new(*player_x, *player_y);
 
     for e in enemies.iter_mut() {
-        // x.atan2(other);
-        if e.hp > 0. {
-            let enem_vec = Vec2::new(e.position.x, e.position.y);
-            let dir = enem_vec - player_vec;
-            e.position.x -= dir.x * delta * 0.3; 
-            e.position.y -= dir.y * delta * 0.3;
+        e.flash = (e.flash - delta).max(0.);
+        match e.behaviour {
+            Behaviour::Chase { speed } => {
+                if e.hp > 0. {
+                    let enem_vec = Vec2::new(e.position.x, e.position.y);
+                    let dir = enem_vec - player_vec;
+                    e.position.x -= dir.x * delta * speed;
+                    e.position.y -= dir.y * delta * speed;
+                }
+            }
+            Behaviour::Wave { initial_y, x_dir, speed, amplitude, wavelength } => {
+                e.position.x += speed * delta * x_dir;
+                e.position.y = initial_y + (e.position.x / wavelength).cos() * amplitude;
+            }
+            Behaviour::Stationary => {}
+        }
+
+        if let Some(gun) = &mut e.gun {
+            if gun.cooldown.finished(now) {
+                let mut _dist= gun.range;
+                let mut _dir: Vec2 = vec2(1.,1.);
+                let _d = dist(e.position, Position { x: *player_x, y: *player_y }, _dist);
+                if _d < _dist {
+                    _dist= _d;
+                    _dir = Vec2::new(e.position.x, e.position.y) - player_vec;
+                    if let Some(d) = _dir.try_normalize() {
+                        _dir = d;
+                    }
+                }
+                bullets.push(Bullet {
+                    x: e.position.x + 2.,
+                    y: e.position.y + 2.,
+                    dir_x: _dir.x,
+                    dir_y: _dir.y,
+                    speed: gun.speed,
+                    damage: gun.damage,
+                    active: true,
+                    radius: 0.,
+                    pierce: 0,
+                    hit: Vec::new(),
+                });
+                events.emit(GameEvent::ProjectileFired { x: e.position.x + 2., y: e.position.y + 2., by_player: false });
+                gun.cooldown.restart(now);
+            }
+        }
+
+        if let Some(lifetime) = &e.lifetime {
+            if lifetime.finished(now) {
+                e.alive = false;
+            }
         }
     }
 }
 
 pub fn update_enemies_colliding(
-    enemies: &mut Vec<Enemies>, 
-    x: &f32, y: &f32, hp: &mut f32, 
-    player_is_dashing: &bool,
-    player_inv_timer: &mut Timer,
-    screen_shake_amount: &mut f32) 
+    enemies: &mut [Enemy],
+    grid: &SpatialGrid,
+    player: &mut Player,
+    events: &mut EventQueue,
+    now: f64)
 {
-    for e in enemies.iter() {
-        let player_pos = Position {
-            x: *x,
-            y: *y
-        };
-        if !*player_is_dashing && player_inv_timer.value() == 1.0 {
+    let player_pos = Position {
+        x: player.pos_x,
+        y: player.pos_y
+    };
+    let mut nearby = Vec::new();
+    grid.query(player_pos, 8., &mut nearby);
+    for &i in nearby.iter() {
+        let e = &mut enemies[i];
+        if e.contact_damage <= 0. {
+            continue;
+        }
+        if !player.is_dashing && player.inv_timer.value(now) == 1.0 {
             if col(player_pos, e.position, 8.) {
-                println!("colliding with player");
-                damage_player(hp);
-                *screen_shake_amount += 4.0;
-                player_inv_timer.restart();
+                let amount = player.armored(e.contact_damage);
+                player.take_damage(amount);
+                events.emit(GameEvent::PlayerHit { source: e.kind.clone(), x: player.pos_x, y: player.pos_y, amount });
+                player.inv_timer.restart(now);
+
+                if e.contact_self_damage > 0. && e.hp > 0. {
+                    e.hurt(e.contact_self_damage, events);
+                }
             }
         }
     }
 }
 
-fn damage_player(hp: &mut f32) {
-    *hp -= 10.;
-}
-
-pub fn update_enemies_pushing(enemies: &mut Vec<Enemies>) {
+/// `grid` has to hold the current positions of `enemies`
+pub fn update_enemies_pushing(enemies: &mut [Enemy], grid: &SpatialGrid) {
+    // let r = enemies[i].collider.radius + enemies[j].collider.radius;
+    let r = 8.;
+    let mut nearby = Vec::new();
     if enemies.len() > 0 {
         for i in 0..enemies.len() - 1 {
-            for j in i+1..enemies.len() {
-                // let r = enemies[i].collider.radius + enemies[j].collider.radius;
-                let r = 8.;
+            if !enemies[i].pushes() {
+                continue;
+            }
+            // Only enemies[i] moves in here. Looking twice as far as `r`
+            // covers it drifting up to `r` away, past that look again.
+            let mut origin = enemies[i].position;
+            grid.query(origin, r * 2., &mut nearby);
+            let mut k = 0;
+            while k < nearby.len() {
+                let j = nearby[k];
+                k += 1;
+                if j <= i || !enemies[j].pushes() {
+                    continue;
+                }
                 if col(enemies[i].position, enemies[j].position, r) {
-                    // println!("enemies colliding!");
                     let dist = dist(enemies[i].position, enemies[j].position, 10.);
                     let dir = get_dir_(enemies[i].position, enemies[j].position);
                     let dif = r - dist;
                     enemies[i].position.x += dir.cos()*dif;
                     enemies[i].position.y += dir.sin()*dif;
+
+                    if !col(origin, enemies[i].position, r) {
+                        origin = enemies[i].position;
+                        grid.query(origin, r * 2., &mut nearby);
+                        k = nearby.partition_point(|&n| n <= j);
+                    }
                 }
             }
         }
     }
 }
 
-pub fn draw_enemies(texture: Texture2D, enemies: &mut Vec<Enemies>, x: &mut f32, y: &mut f32) {
+pub fn draw_enemies(texture: Texture2D, enemies: &mut [Enemy], x: &mut f32, _y: &mut f32) {
     for e in enemies.iter_mut() {
         let frame = e.anims.get_mut("idle").unwrap().get_animation_source(Duration::from_secs_f32(get_frame_time()));
         e.curr_frame = frame;
-        let mut flip = false;
-        if e.position.x > *x {
-            flip = true
-        }
+        // Chasers look at the player
+        let flip = e.pushes() && e.position.x > *x;
+        let tint = if e.flash > 0. { Color::new(1.0, 0.3, 0.3, 1.0) } else { WHITE };
         draw_texture_ex(
-            texture, 
+            texture,
             e.position.x,
             e.position.y,
-            WHITE,
-    DrawTextureParams { 
-                dest_size: Some(vec2(9., 9.)), 
+            tint,
+    DrawTextureParams {
+                dest_size: frame.map(|f| vec2(f.w, f.h)),
                 source: frame,
                 flip_x: flip,
             ..Default::default()
         });
-    } 
+    }
 }
 
-pub fn update_dead_enemies(enemies: &mut Vec<DeadEnemy>, x: &mut f32) {
-    let delta = get_frame_time();
+pub fn update_dead_enemies(enemies: &mut [DeadEnemy], x: &mut f32, delta: f32, now: f64) {
     for e in enemies.iter_mut() {
-        e.move_tween.update();
+        e.move_tween.update(now);
         e.opacity_tween.move_by(delta);
         if e.position.x > *x {
             e.position.x += e.move_tween.value() * delta;
@@ -209,266 +364,64 @@ pub fn draw_dead_enemies(texture: Texture2D, enemies: &mut Vec<DeadEnemy>, x: &m
                 flip = true
             }
             draw_texture_ex(
-                texture, 
+                texture,
                 e.position.x,
                 e.position.y,
                 Color::new(1.0, 1.0, 1.0, e.opacity_tween.move_by(get_frame_time())),
-        DrawTextureParams { 
-                    dest_size: Some(vec2(8., 8.)), 
+        DrawTextureParams {
+                    dest_size: Some(vec2(8., 8.)),
                     source: e.curr_frame,
                     flip_x: flip,
                 ..Default::default()
             });
         }
-    } 
+    }
 }
 
-pub fn draw_enemies_collider(enemies: &mut Vec<Enemies>) {
+pub fn draw_enemies_collider(enemies: &[Enemy]) {
     for e in enemies.iter() {
         draw_circle(
-            e.position.x + 4., 
-            e.position.y + 4., 
-            e.collider.radius, 
+            e.position.x + 4.,
+            e.position.y + 4.,
+            e.collider.radius,
             Color::from_rgba(255, 0, 0, 60)
         );
     }
 }
 
-pub fn spawn_enemies(enemies: &mut Vec<Enemies>, player_pos_x: &f32, player_pos_y: &f32, given_xp: f32) {
-    // get a random position away from the player
-    // add an enemy to that position
-    let direction = rand::gen_range(-1, 2) as f32;
-    let random;
-    // let mut rng = ::rand::thread_rng();
-
-    match rand::gen_range(0, 2) {
-        0 => random = -1.,
-        _ => random = 1.,
-    }
-
-    let _rad = 60. + (rand::gen_range(0., 33.) as f32).floor();
-    let x = player_pos_x + direction.cos() * _rad * random;
-    let y = player_pos_y + direction.sin() * _rad * random;
-
-    enemies.push(Enemies::new(x, y, given_xp));
-}
-
-pub struct BatEnemy {
-    pub x: f32,
-    pub y: f32,
-    pub initial_y: f32,
-    pub anims: HashMap<String, Animation>,
-    pub curr_frame: Option<Rect>,
-    pub hp: f32,
-    pub active: bool,
-    pub x_dir: f32,
-    pub clean_timer: Timer,
-    pub given_xp: f32,
-}
-
-impl BatEnemy {
-    pub fn new(x: f32, y: f32, direction: f32, given_xp: f32) -> Self {
-        let mut idle_state_rects : Vec<Rect> = Vec::new();
-        idle_state_rects.push(Rect::new(1., 10., 9., 9.));
-        idle_state_rects.push(Rect::new(10., 10., 9., 9.));
-    
-        let idle_state_frame_lenghts : Vec<Duration> = vec![Duration::from_millis(200); idle_state_rects.len()];
-    
-        let idle_animation = Animation {
-            frames: idle_state_rects.clone(),
-            frame_length: idle_state_frame_lenghts.clone(),
-            anim_duration: Duration::from_secs(0),
-            current_frame: 0,
-            current_frame_length: idle_state_frame_lenghts[0],
-            repeating: true
-        };
-        
-        let mut anims = HashMap::new();
-        anims.insert("idle".to_string(), idle_animation);
-
-        BatEnemy {
-            x,
-            y,
-            hp: 1.,
-            initial_y: y,
-            anims,
-            curr_frame: Some(Rect::new(1., 10., 9., 9.)),
-            active: true,
-            x_dir: direction,
-            clean_timer: Timer::new(10000),
-            given_xp
-        }
-    }
-}
-
-pub fn update_bat_enemies_position(enemies: &mut Vec<BatEnemy>) {
-    let delta = get_frame_time();
-    for e in enemies.iter_mut() {
-        e.x += 20. * delta * e.x_dir;
-        e.y = e.initial_y + ((e.x / 10.)).cos() * 25.;
-    }
-}
-
-pub fn draw_bat_enemies(texture: Texture2D, enemies: &mut Vec<BatEnemy>) {
-    for e in enemies.iter_mut() {
-        let frame = e.anims.get_mut("idle").unwrap().get_animation_source(Duration::from_secs_f32(get_frame_time()));
-        e.curr_frame = frame;    
-        draw_texture_ex(
-            texture, 
-            e.x,
-            e.y,
-            WHITE,
-    DrawTextureParams { 
-                dest_size: Some(vec2(9., 9.)), 
-                source: frame,
-                // source: Some(Rect::new(1., 10., 9., 9.)),
-            ..Default::default()
-        });
-    }
-}
-
-pub fn update_bat_enemies_colliding(
-    enemies: &mut Vec<BatEnemy>, 
-    x: &f32, y: &f32, hp: &mut f32, 
-    player_inv_timer: &mut Timer,
-    screen_shake_amount: &mut f32,
-    dmg_pop: &mut Vec<DamagePopup>,
-    player_is_dashing: &bool
-) {
-    for e in enemies.iter_mut() {
-        let player_pos = Position {
-            x: *x,
-            y: *y
-        };
-        let enemy_pos = Position {
-            x: e.x,
-            y: e.y
-        };
-        if player_inv_timer.value() == 1.0 && !player_is_dashing {
-            if col(player_pos, enemy_pos, 8.) {
-                println!("colliding with player");
-                damage_player(hp);
-                *screen_shake_amount += 4.0;
-                player_inv_timer.restart();
-
-                if e.hp > 0. {
-                    dmg_pop.push(DamagePopup::new(e.x, e.y, 1));
-                    *screen_shake_amount += 1.0;
-                    e.hp -= 1.;
-                    println!("{}", e.hp);
-                } 
-            }
-        }
-    }
-}
-
-pub fn clean_bat_enemies(enemies: &mut Vec<BatEnemy>, x: &f32, y: &f32) {
-    for e in enemies.iter_mut() {
-        if e.clean_timer.finished() {
-            e.active = false;
-        }
-    }
-}
-
-pub struct TowerEnemy {
-    pub x: f32,
-    pub y: f32,
-    pub bullet_cooldown: Timer,
-    pub activity_cooldown: Timer,
-    pub active: bool
-}
-
-impl TowerEnemy {
-    pub fn new(x: f32, y: f32) -> Self {
-        let bullet_cooldown = Timer::new(1000);
-        let activity_cooldown = Timer::new(5000);
-        TowerEnemy { x, y, bullet_cooldown, activity_cooldown, active: true }
-    }
-
-    pub fn update(&mut self, player_x: f32, player_y: f32, bullets: &mut Vec<Bullet>) {
-        if self.bullet_cooldown.finished() {
-            let mut _dist= 128.;
-            let mut _dir: Vec2 = vec2(1.,1.);
-            let _d = dist(
-                Position { x: self.x, y: self.y} ,
-                Position { x: player_x, y: player_y },
-            _dist);
-            if _d < _dist {
-                _dist= _d;
-                _dir = Vec2::new(self.x, self.y) - Vec2::new(player_x, player_y);
-                if let Some(d) = _dir.try_normalize() {
-                    _dir = d;
-                }
-            }
-            bullets.push(Bullet { x: self.x + 2., y: self.y + 2., dir_x: _dir.x, dir_y: _dir.y, active: true });
-            self.bullet_cooldown.restart();         
-        }
-
-        if self.activity_cooldown.finished() {
-            self.active = false;
-        }
-    }
-}
-
-pub fn draw_tower_enemies(texture: Texture2D, enemies: &mut Vec<TowerEnemy>) {
-    for e in enemies.iter() {
-        if e.active {
-            draw_texture_ex(
-                texture, 
-                e.x,
-                e.y,
-                WHITE,
-        DrawTextureParams { 
-                    dest_size: Some(vec2(9., 9.)), 
-                    source: Some(Rect::new(1., 20., 9., 9.)),
-                ..Default::default()
-            });  
-        }
-    }
-}
-
-pub fn update_tower_enemies(enemies: &mut Vec<TowerEnemy>, player_x: &f32, player_y: &f32, bullets: &mut Vec<Bullet>) {
-    for e in enemies.iter_mut() {
-        e.update(*player_x, *player_y, bullets);
-    }
-}
-
-pub fn update_enemy_bullets(bullets: &mut Vec<Bullet>, particles: &mut Vec<Particle>, delta: f32) {
+pub fn update_enemy_bullets(bullets: &mut [Bullet], particles: &mut Vec<Particle>, delta: f32, fx_rng: &mut Rng, now: f64) {
     for bullet in bullets.iter_mut() {
         if bullet.active {
-            bullet.x -= bullet.dir_x * delta * 20.; 
-            bullet.y -= bullet.dir_y * delta * 20.;
-            spawn_particle(particles, bullet.x, bullet.y, Box::new(EnemyShotParticle{}));
+            bullet.x -= bullet.dir_x * delta * bullet.speed;
+            bullet.y -= bullet.dir_y * delta * bullet.speed;
+            spawn_particle(particles, bullet.x, bullet.y, Box::new(EnemyShotParticle{}), fx_rng, now);
         }
     }
 }
 
 pub fn bullet_damage_player(
     bullets: &mut Vec<Bullet>, 
-    x: &f32, y: &f32,
-    player_hp: &mut f32,
-    dmg_pop: &mut Vec<DamagePopup>,
-    screen_shake_amount: &mut f32,
-    player_inv_timer: &mut Timer,
-    player_is_dashing: &bool
+    player: &mut Player,
+    events: &mut EventQueue,
+    now: f64
 ) {
+    let (x, y) = (player.pos_x, player.pos_y);
     for bullet in bullets.iter_mut() {
         // Collide with enemies
         if col(
             Position { x: bullet.x, y: bullet.y }, 
-            Position { x: *x + 2., y: *y + 2. }, 
+            Position { x: x + 2., y: y + 2. }, 
             5.
         ) {
-            if *player_hp > 0. && 
-                player_inv_timer.value() == 1.0 &&
-                !player_is_dashing 
+            if player.hp > 0. && 
+                player.inv_timer.value(now) == 1.0 &&
+                !player.is_dashing 
             {
                 bullet.active = false;
-                dmg_pop.push(DamagePopup::new(*x, *y, 10));
-                *screen_shake_amount += 1.0;
-                damage_player(player_hp);
-                *screen_shake_amount += 4.0;
-                player_inv_timer.restart();
+                let amount = player.armored(bullet.damage);
+                player.take_damage(amount);
+                events.emit(GameEvent::PlayerHit { source: stats::PROJECTILE.to_string(), x, y, amount });
+                player.inv_timer.restart(now);
             }
         }
     }
@@ -494,4 +447,89 @@ pub fn draw_enemy_bullets(texture: Texture2D, bullets: &mut Vec<Bullet>) {
             })
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy_defs::EnemyDefs;
+    use crate::input::InputState;
+    use crate::pickups::PickupKind;
+    use crate::world::World;
+
+    fn spawn(kind: &str, x: f32, y: f32) -> Enemy {
+        Enemy::from_def(EnemyDefs::builtin().get(kind).unwrap(), x, y, 1., 0.)
+    }
+
+    /// Steps `enemy` from the start of the clock for `secs`, with the
+    /// player at the origin. Returns what it fired.
+    fn run(enemy: &mut Enemy, secs: f32) -> Vec<Bullet> {
+        let (mut bullets, mut events) = (Vec::new(), EventQueue::default());
+        let delta = 1. / 60.;
+        for i in 1..=(secs / delta).round() as usize {
+            update_enemies(std::slice::from_mut(enemy), &0., &0., &mut bullets, &mut events, delta, i as f64 * delta as f64);
+        }
+        bullets
+    }
+
+    #[test]
+    fn slimes_walk_to_the_player() {
+        let mut slime = spawn("slime", 40., 30.);
+        let fired = run(&mut slime, 1.);
+        assert!(slime.position.x < 40. && slime.position.y < 30.);
+        assert!(slime.position.x > 0. && slime.position.y > 0.);
+        assert!(fired.is_empty());
+        assert!(slime.alive);
+    }
+
+    #[test]
+    fn bats_fly_across_in_a_wave_and_leave() {
+        let mut bat = spawn("bat", 0., 50.).heading(-1.);
+        let fired = run(&mut bat, 1.);
+        assert!((bat.position.x + 20.).abs() < 0.1, "{}", bat.position.x);
+        assert!((bat.position.y - 50.).abs() > 1., "{}", bat.position.y);
+        assert!(fired.is_empty());
+        assert!(bat.alive);
+        run(&mut bat, 10.5);
+        assert!(!bat.alive, "gone once its lifetime is over");
+    }
+
+    #[test]
+    fn towers_stay_put_and_shoot_at_the_player() {
+        let mut tower = spawn("tower", 40., 0.);
+        let fired = run(&mut tower, 1.5);
+        assert_eq!((tower.position.x, tower.position.y), (40., 0.));
+        assert_eq!(fired.len(), 1);
+        // Bullets fly against their dir, this one towards the origin
+        assert!(fired[0].dir_x > 0.9);
+        assert_eq!(fired[0].damage, 10.);
+        assert!(tower.alive);
+        run(&mut tower, 5.5);
+        assert!(!tower.alive, "gone once its lifetime is over");
+    }
+
+    #[test]
+    fn every_kind_dies_and_drops_xp_the_same_way() {
+        let mut player = Player::new();
+        let mut world = World::new(3);
+        let kinds: Vec<String> = world.enemy_defs.iter().map(|d| d.id.clone()).collect();
+        for (i, kind) in kinds.iter().enumerate() {
+            let mut e = spawn(kind, 10. + 20. * i as f32, 10.);
+            e.id = 1000 + i as u64;
+            e.hurt(e.hp, &mut world.events);
+            world.enemies.push(e);
+        }
+        world.step(&mut player, 1. / 60., &InputState::default());
+
+        for kind in kinds.iter() {
+            let given_xp = EnemyDefs::builtin().get(kind).unwrap().xp;
+            let killed = world.events.iter().any(|e| matches!(e, GameEvent::EnemyKilled { kind: k, xp, .. } if k == kind && *xp == given_xp));
+            assert!(killed, "{} was killed", kind);
+            assert!(world.enemies.iter().all(|e| e.id < 1000), "{} was cleaned up", kind);
+        }
+        let gems = world.pickups.iter().filter(|p| matches!(p.kind, PickupKind::Xp(_))).count();
+        assert_eq!(gems, kinds.len());
+        assert_eq!(world.dead_enemies.len(), kinds.len());
+        assert_eq!(world.kill_count, kinds.len() as i32);
+    }
+}
@@ -0,0 +1,235 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::time::Duration;
+
+use macroquad::prelude::Rect;
+use serde::{Serialize, Deserialize};
+
+use crate::animation::Animation;
+use crate::defs::{self, DefError};
+use crate::enemies::Hurtbox;
+
+const BUILTIN: &str = include_str!("../assets/enemies.ron");
+
+/// How an enemy moves around
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Movement {
+    /// Closes `speed` of the distance to the player every second, so it's
+    /// fast when far and slow when close
+    Chase { speed: f32 },
+    /// Flies sideways at `speed` pixels a second, its height following
+    /// `cos(x / wavelength) * amplitude`
+    Wave { speed: f32, amplitude: f32, wavelength: f32 },
+    Stationary,
+}
+
+/// What an enemy shoots at the player
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ProjectileDef {
+    pub cooldown_millis: u64,
+    /// Aims at the player within this distance, shoots blindly otherwise
+    pub range: f32,
+    pub speed: f32,
+    pub damage: f32,
+}
+
+/// One enemy archetype out of `assets/enemies.ron`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EnemyDef {
+    pub id: String,
+    pub hp: f32,
+    /// Share of the run's current XP yield this enemy drops
+    #[serde(default = "one")]
+    pub xp: f32,
+    /// Dealt to the player on touch, 0 for harmless
+    #[serde(default)]
+    pub contact_damage: f32,
+    /// Taken by the enemy itself when touching the player
+    #[serde(default)]
+    pub contact_self_damage: f32,
+    pub collider_radius: f32,
+    pub hurtbox: Hurtbox,
+    /// `(x, y, w, h)` in the enemies atlas
+    pub frames: Vec<(f32, f32, f32, f32)>,
+    pub frame_millis: u64,
+    pub movement: Movement,
+    #[serde(default)]
+    pub projectile: Option<ProjectileDef>,
+    /// Leaves on its own after this long, without giving XP
+    #[serde(default)]
+    pub lifetime_millis: Option<u64>,
+}
+
+fn one() -> f32 {
+    1.
+}
+
+impl EnemyDef {
+    pub fn animations(&self) -> HashMap<String, Animation> {
+        let frames: Vec<Rect> = self.frames.iter().map(|&(x, y, w, h)| Rect::new(x, y, w, h)).collect();
+        let frame_lengths : Vec<Duration> = vec![Duration::from_millis(self.frame_millis); frames.len()];
+
+        let idle_animation = Animation {
+            frames,
+            frame_length: frame_lengths.clone(),
+            anim_duration: Duration::from_secs(0),
+            current_frame: 0,
+            current_frame_length: frame_lengths[0],
+            repeating: true
+        };
+
+        let mut anims = HashMap::new();
+        anims.insert("idle".to_string(), idle_animation);
+        anims
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("id can't be empty".to_string());
+        }
+        if !self.hp.is_finite() || self.hp <= 0. {
+            return Err(format!("hp must be above 0, got {}", self.hp));
+        }
+        for (name, value) in [
+            ("xp", self.xp),
+            ("contact_damage", self.contact_damage),
+            ("contact_self_damage", self.contact_self_damage),
+            ("collider_radius", self.collider_radius),
+            ("hurtbox.radius", self.hurtbox.radius),
+        ] {
+            if !value.is_finite() || value < 0. {
+                return Err(format!("{} can't be negative, got {}", name, value));
+            }
+        }
+        if self.frames.is_empty() {
+            return Err("needs at least one frame".to_string());
+        }
+        if let Some(&(x, y, w, h)) = self.frames.iter().find(|&&(x, y, w, h)| {
+            [x, y, w, h].iter().any(|v| !v.is_finite()) || x < 0. || y < 0. || w <= 0. || h <= 0.
+        }) {
+            return Err(format!("frame ({}, {}, {}, {}) is empty or outside the atlas", x, y, w, h));
+        }
+        if self.frame_millis == 0 {
+            return Err("frame_millis must be above 0".to_string());
+        }
+        match self.movement {
+            Movement::Chase { speed } if !speed.is_finite() || speed < 0. => {
+                return Err(format!("chase speed can't be negative, got {}", speed));
+            }
+            Movement::Wave { speed, amplitude, wavelength } if !speed.is_finite() || speed < 0. || !amplitude.is_finite() || !wavelength.is_finite() || wavelength <= 0. => {
+                return Err(format!("wave needs a speed of 0 or more, an amplitude and a wavelength above 0, got {}, {} and {}", speed, amplitude, wavelength));
+            }
+            _ => {}
+        }
+        if let Some(projectile) = &self.projectile {
+            if projectile.cooldown_millis == 0 {
+                return Err("projectile cooldown_millis must be above 0".to_string());
+            }
+            if [projectile.range, projectile.speed, projectile.damage].iter().any(|v| !v.is_finite() || *v < 0.) {
+                return Err("projectile range, speed and damage can't be negative".to_string());
+            }
+        }
+        if self.lifetime_millis == Some(0) {
+            return Err("lifetime_millis must be above 0, leave it out to live forever".to_string());
+        }
+        Ok(())
+    }
+}
+
+/// Every enemy archetype the game knows about, looked up by id
+#[derive(Debug, Clone, PartialEq)]
+pub struct EnemyDefs {
+    defs: Vec<EnemyDef>,
+}
+
+impl EnemyDefs {
+    pub fn builtin() -> Self {
+        BUILTIN.parse().expect("assets/enemies.ron is broken")
+    }
+
+    pub fn get(&self, id: &str) -> Option<&EnemyDef> {
+        self.defs.iter().find(|d| d.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &EnemyDef> {
+        self.defs.iter()
+    }
+}
+
+impl FromStr for EnemyDefs {
+    type Err = DefError;
+
+    fn from_str(s: &str) -> Result<EnemyDefs, DefError> {
+        let defs: Vec<EnemyDef> = defs::from_ron(s)?;
+        defs::unique_ids(defs.iter().map(|d| d.id.as_str()))?;
+        for def in defs.iter() {
+            def.validate().map_err(|reason| DefError::InvalidDef { id: def.id.clone(), reason })?;
+        }
+        Ok(EnemyDefs { defs })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SLIME: &str = r#"(
+        id: "slime",
+        hp: 2.0,
+        collider_radius: 4.0,
+        hurtbox: (offset_x: 2.0, offset_y: 2.0, radius: 5.0),
+        frames: [(1.0, 1.0, 9.0, 9.0)],
+        frame_millis: 200,
+        movement: Chase(speed: 0.3),
+        projectile: Some((cooldown_millis: 1000, range: 128.0, speed: 20.0, damage: 10.0)),
+    )"#;
+
+    /// The slime with `from` replaced by `to`
+    fn parse_with(from: &str, to: &str) -> Result<EnemyDefs, DefError> {
+        assert!(SLIME.contains(from), "{} isn't in the slime", from);
+        format!("[{}]", SLIME.replace(from, to)).parse()
+    }
+
+    #[test]
+    fn builtin_enemies_parse() {
+        let defs = EnemyDefs::builtin();
+        for id in ["slime", "bat", "tower"] {
+            assert!(defs.get(id).is_some(), "{} is missing", id);
+        }
+        assert!(format!("[{}]", SLIME).parse::<EnemyDefs>().is_ok());
+    }
+
+    #[test]
+    fn broken_files_are_rejected() {
+        assert!(matches!("[(id: \"slime\")]".parse::<EnemyDefs>(), Err(DefError::Format(_))));
+        let twice = format!("[{}, {}]", SLIME, SLIME).parse::<EnemyDefs>();
+        assert!(matches!(twice, Err(DefError::DuplicateId(id)) if id == "slime"));
+    }
+
+    #[test]
+    fn bad_definitions_are_rejected() {
+        for (from, to) in [
+            ("id: \"slime\"", "id: \"\""),
+            ("hp: 2.0", "hp: 0.0"),
+            ("hp: 2.0", "hp: inf"),
+            ("collider_radius: 4.0", "collider_radius: -1.0"),
+            ("radius: 5.0", "radius: NaN"),
+            ("frames: [(1.0, 1.0, 9.0, 9.0)]", "frames: []"),
+            ("(1.0, 1.0, 9.0, 9.0)", "(1.0, 1.0, 0.0, 9.0)"),
+            ("(1.0, 1.0, 9.0, 9.0)", "(NaN, 1.0, 9.0, 9.0)"),
+            ("(1.0, 1.0, 9.0, 9.0)", "(1.0, 1.0, inf, 9.0)"),
+            ("frame_millis: 200", "frame_millis: 0"),
+            ("Chase(speed: 0.3)", "Chase(speed: -0.3)"),
+            ("Chase(speed: 0.3)", "Chase(speed: inf)"),
+            ("Chase(speed: 0.3)", "Wave(speed: 20.0, amplitude: 25.0, wavelength: 0.0)"),
+            ("Chase(speed: 0.3)", "Wave(speed: 20.0, amplitude: NaN, wavelength: 10.0)"),
+            ("cooldown_millis: 1000", "cooldown_millis: 0"),
+            ("range: 128.0", "range: inf"),
+            ("damage: 10.0", "damage: -10.0"),
+            ("frame_millis: 200,", "frame_millis: 200, lifetime_millis: Some(0),"),
+        ] {
+            let result = parse_with(from, to);
+            assert!(matches!(result, Err(DefError::InvalidDef { .. })), "{} was let through", to);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+/// Something that happened during a [`crate::world::World::step`].
+///
+/// Gameplay code only says what happened, screen shake, popups, stats and
+/// whatever else wants to react go through the queue instead of being
+/// threaded into every collision function.
+#[derive(Debug, Clone, PartialEq)]
+pub enum GameEvent {
+    EnemySpawned { kind: String, x: f32, y: f32 },
+    /// Also sent for the hit that kills it
+    EnemyDamaged { kind: String, x: f32, y: f32, amount: f32 },
+    EnemyKilled { kind: String, x: f32, y: f32, xp: f32 },
+    /// `source` is the enemy kind, or [`crate::stats::PROJECTILE`]
+    PlayerHit { source: String, x: f32, y: f32, amount: f32 },
+    PlayerDied,
+    XpCollected { amount: f32 },
+    /// Every gem on the ground is on its way
+    VacuumCollected,
+    LevelUp { level: i32 },
+    /// `dir_x`/`dir_y` is the screen-space unit vector it goes along
+    DashStarted { dir_x: f32, dir_y: f32, x: f32, y: f32 },
+    ProjectileFired { x: f32, y: f32, by_player: bool },
+    /// Sent on every step of the first few seconds after the stage is
+    /// cleared, while the ground rumbles
+    StageCleared,
+}
+
+/// Events of the last step, in the order they happened. Cleared when the
+/// next step starts, so they can be read in between.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct EventQueue {
+    events: Vec<GameEvent>,
+}
+
+impl EventQueue {
+    pub fn emit(&mut self, event: GameEvent) {
+        self.events.push(event);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &GameEvent> {
+        self.events.iter()
+    }
+
+    pub fn clear(&mut self) {
+        self.events.clear();
+    }
+}
@@ -0,0 +1,120 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::direction::{direction_from_vec, quantize};
+use crate::input_map::{Action, ActionState};
+
+/// Replays store each move axis in a byte, analog input is rounded to what
+/// fits so replaying it gives the same run
+pub(crate) const MOVE_STEPS: f32 = 127.;
+/// Same for each axis of the dash direction, in two bytes
+pub(crate) const DASH_STEPS: f32 = 32767.;
+
+/// Which way a dash goes
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum DashAim {
+    /// The closest of the eight directions to where the player is moving,
+    /// like the arrow keys always did
+    #[default]
+    EightWay,
+    /// Exactly where the player is moving, for analog sticks
+    Movement,
+    /// Towards the mouse cursor, or where the player is moving when there's
+    /// no cursor to aim at
+    Mouse,
+}
+
+/// Everything the simulation needs to know about the player's input for a
+/// single tick. Built from the input map's actions when playing, or by hand
+/// when running the game headless.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct InputState {
+    /// -1 (left) to 1 (right)
+    pub move_x: f32,
+    /// -1 (down) to 1 (up)
+    pub move_y: f32,
+    pub dash_pressed: bool,
+    /// Screen-space unit vector (y grows downwards), see [`InputState::dash_towards`]
+    pub dash_direction: Option<Vec2>,
+}
+
+impl InputState {
+    /// `cursor` is where the mouse points in world space relative to the
+    /// player, only [`DashAim::Mouse`] looks at it
+    pub fn from_actions(actions: &ActionState, aim: DashAim, cursor: Option<Vec2>) -> Self {
+        let axis = |negative: Action, positive: Action| {
+            let value = (actions.value(positive) - actions.value(negative)).clamp(-1., 1.);
+            (value * MOVE_STEPS).round() / MOVE_STEPS
+        };
+        let move_x = axis(Action::MoveLeft, Action::MoveRight);
+        let move_y = axis(Action::MoveDown, Action::MoveUp);
+        let dash_pressed = actions.pressed(Action::Dash);
+        let movement = vec2(move_x, -move_y);
+        let dash_direction = match aim {
+            _ if !dash_pressed => None,
+            DashAim::EightWay => direction_from_vec(quantize(movement)).and_then(|d| InputState::dash_towards(d.to_vec())),
+            DashAim::Movement => InputState::dash_towards(movement),
+            DashAim::Mouse => cursor.and_then(InputState::dash_towards).or_else(|| InputState::dash_towards(movement)),
+        };
+        InputState { move_x, move_y, dash_pressed, dash_direction }
+    }
+
+    /// `v` as a dash direction: normalized and rounded to what a replay can
+    /// store, none when it doesn't point anywhere
+    pub fn dash_towards(v: Vec2) -> Option<Vec2> {
+        let v = v.try_normalize()?;
+        Some(vec2((v.x * DASH_STEPS).round() / DASH_STEPS, (v.y * DASH_STEPS).round() / DASH_STEPS))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn analog_input_is_rounded_to_replay_steps() {
+        for i in 0..=200 {
+            let value = i as f32 / 200.;
+            let mut actions = ActionState::default();
+            actions.set(Action::MoveRight, value);
+            actions.set(Action::MoveDown, value * 0.7);
+            let input = InputState::from_actions(&actions, DashAim::Movement, None);
+            for (axis, held) in [(input.move_x, value), (input.move_y, -value * 0.7)] {
+                assert_eq!((axis * MOVE_STEPS).round() / MOVE_STEPS, axis);
+                assert!((axis - held).abs() <= 0.5 / MOVE_STEPS);
+            }
+        }
+    }
+
+    #[test]
+    fn opposite_actions_cancel_out() {
+        let mut actions = ActionState::default();
+        actions.set(Action::MoveLeft, 1.);
+        actions.set(Action::MoveRight, 1.);
+        actions.set(Action::MoveUp, 0.25);
+        let input = InputState::from_actions(&actions, DashAim::EightWay, None);
+        assert_eq!(input.move_x, 0.);
+        assert_eq!(input.move_y, (0.25 * MOVE_STEPS).round() / MOVE_STEPS);
+    }
+
+    #[test]
+    fn dash_directions_are_rounded_unit_vectors() {
+        let mut actions = ActionState::default();
+        actions.set(Action::MoveRight, 0.9);
+        actions.set(Action::MoveUp, 0.3);
+        actions.press(Action::Dash);
+        let eight_way = InputState::from_actions(&actions, DashAim::EightWay, None).dash_direction.unwrap();
+        assert_eq!(eight_way, vec2(1., 0.));
+        let cursor = Some(vec2(-3., 4.));
+        let mouse = InputState::from_actions(&actions, DashAim::Mouse, cursor).dash_direction.unwrap();
+        assert_eq!(mouse, InputState::dash_towards(vec2(-0.6, 0.8)).unwrap());
+        for v in [mouse, InputState::from_actions(&actions, DashAim::Movement, None).dash_direction.unwrap()] {
+            assert!((v.length() - 1.).abs() < 1e-4);
+            assert_eq!((v * DASH_STEPS).round() / DASH_STEPS, v);
+        }
+        // Nothing to aim at, no dash
+        let mut still = ActionState::default();
+        still.press(Action::Dash);
+        assert_eq!(InputState::from_actions(&still, DashAim::Movement, None).dash_direction, None);
+    }
+}
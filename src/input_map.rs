@@ -0,0 +1,544 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::defs::{self, DefError};
+use crate::input::DashAim;
+
+const BUILTIN: &str = include_str!("../assets/input.ron");
+
+/// A stick or trigger has to be pushed this far for its action to count as
+/// held, and to fire once when it gets there
+const PRESS_THRESHOLD: f32 = 0.5;
+
+/// Something the player can do, whatever it is bound to
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+pub enum Action {
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    Dash,
+    Confirm,
+    Back,
+    Pause,
+    /// Picks a saved run back up from the title screen
+    Continue,
+}
+
+impl Action {
+    pub const ALL: [Action; 9] = [
+        Action::MoveUp,
+        Action::MoveDown,
+        Action::MoveLeft,
+        Action::MoveRight,
+        Action::Dash,
+        Action::Confirm,
+        Action::Back,
+        Action::Pause,
+        Action::Continue,
+    ];
+
+    fn index(self) -> usize {
+        self as usize
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum PadButton {
+    South,
+    East,
+    North,
+    West,
+    LeftBumper,
+    RightBumper,
+    LeftTrigger,
+    RightTrigger,
+    Select,
+    Start,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+/// One direction of a stick. Up and right are positive.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum StickDirection {
+    LeftUp,
+    LeftDown,
+    LeftLeft,
+    LeftRight,
+    RightUp,
+    RightDown,
+    RightLeft,
+    RightRight,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Binding {
+    /// A keyboard key by name: `"W"`, `"Up"`, `"Space"`, `"Escape"`...
+    Key(String),
+    Button(PadButton),
+    Stick(StickDirection),
+}
+
+impl Binding {
+    /// What prompts call it, the key's name or the pad button's
+    pub fn label(&self) -> String {
+        match self {
+            Binding::Key(name) => name.clone(),
+            Binding::Button(button) => format!("{:?}", button),
+            Binding::Stick(direction) => format!("{:?}", direction),
+        }
+    }
+}
+
+/// Bindings every action starts from before the config changes any
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Layout {
+    /// Arrow keys, Z to confirm and X to dash, as in the jam version
+    #[default]
+    Arrows,
+    /// WASD, Space to dash, J or Enter to confirm
+    Wasd,
+}
+
+/// What `assets/input.ron` holds
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct InputConfig {
+    #[serde(default)]
+    pub layout: Layout,
+    /// Replaces the preset's bindings of every action listed
+    #[serde(default)]
+    pub bindings: BTreeMap<Action, Vec<Binding>>,
+    /// How far sticks have to move before they count at all, 0 to 1
+    #[serde(default = "default_dead_zone")]
+    pub dead_zone: f32,
+    #[serde(default)]
+    pub dash_aim: DashAim,
+}
+
+fn default_dead_zone() -> f32 {
+    0.2
+}
+
+/// Keys that can be bound, by the name the config uses
+fn key_code(name: &str) -> Option<KeyCode> {
+    let code = match name {
+        "A" => KeyCode::A, "B" => KeyCode::B, "C" => KeyCode::C, "D" => KeyCode::D,
+        "E" => KeyCode::E, "F" => KeyCode::F, "G" => KeyCode::G, "H" => KeyCode::H,
+        "I" => KeyCode::I, "J" => KeyCode::J, "K" => KeyCode::K, "L" => KeyCode::L,
+        "M" => KeyCode::M, "N" => KeyCode::N, "O" => KeyCode::O, "P" => KeyCode::P,
+        "Q" => KeyCode::Q, "R" => KeyCode::R, "S" => KeyCode::S, "T" => KeyCode::T,
+        "U" => KeyCode::U, "V" => KeyCode::V, "W" => KeyCode::W, "X" => KeyCode::X,
+        "Y" => KeyCode::Y, "Z" => KeyCode::Z,
+        "0" => KeyCode::Key0, "1" => KeyCode::Key1, "2" => KeyCode::Key2, "3" => KeyCode::Key3,
+        "4" => KeyCode::Key4, "5" => KeyCode::Key5, "6" => KeyCode::Key6, "7" => KeyCode::Key7,
+        "8" => KeyCode::Key8, "9" => KeyCode::Key9,
+        "Up" => KeyCode::Up, "Down" => KeyCode::Down, "Left" => KeyCode::Left, "Right" => KeyCode::Right,
+        "Space" => KeyCode::Space,
+        "Enter" => KeyCode::Enter,
+        "Escape" => KeyCode::Escape,
+        "Backspace" => KeyCode::Backspace,
+        "Tab" => KeyCode::Tab,
+        "LeftShift" => KeyCode::LeftShift,
+        "RightShift" => KeyCode::RightShift,
+        "LeftControl" => KeyCode::LeftControl,
+        "RightControl" => KeyCode::RightControl,
+        "LeftAlt" => KeyCode::LeftAlt,
+        _ => return None,
+    };
+    Some(code)
+}
+
+impl Layout {
+    fn keys(&self, action: Action) -> &'static [&'static str] {
+        match (self, action) {
+            (Layout::Arrows, Action::MoveUp) => &["Up"],
+            (Layout::Arrows, Action::MoveDown) => &["Down"],
+            (Layout::Arrows, Action::MoveLeft) => &["Left"],
+            (Layout::Arrows, Action::MoveRight) => &["Right"],
+            (Layout::Arrows, Action::Dash) => &["X"],
+            (Layout::Arrows, Action::Confirm) => &["Z", "Enter"],
+            (Layout::Wasd, Action::MoveUp) => &["W"],
+            (Layout::Wasd, Action::MoveDown) => &["S"],
+            (Layout::Wasd, Action::MoveLeft) => &["A"],
+            (Layout::Wasd, Action::MoveRight) => &["D"],
+            (Layout::Wasd, Action::Dash) => &["Space"],
+            (Layout::Wasd, Action::Confirm) => &["J", "Enter"],
+            (_, Action::Back) => &["Escape", "Backspace"],
+            (_, Action::Pause) => &["Escape", "P"],
+            (_, Action::Continue) => &["C"],
+        }
+    }
+
+    /// Gamepads work the same with either: left stick or d-pad to move,
+    /// West or right bumper to dash, South to confirm, East to go back,
+    /// North to continue
+    pub fn bindings(&self) -> BTreeMap<Action, Vec<Binding>> {
+        Action::ALL.iter().map(|&action| {
+            let pad: &[Binding] = match action {
+                Action::MoveUp => &[Binding::Stick(StickDirection::LeftUp), Binding::Button(PadButton::DPadUp)],
+                Action::MoveDown => &[Binding::Stick(StickDirection::LeftDown), Binding::Button(PadButton::DPadDown)],
+                Action::MoveLeft => &[Binding::Stick(StickDirection::LeftLeft), Binding::Button(PadButton::DPadLeft)],
+                Action::MoveRight => &[Binding::Stick(StickDirection::LeftRight), Binding::Button(PadButton::DPadRight)],
+                Action::Dash => &[Binding::Button(PadButton::West), Binding::Button(PadButton::RightBumper)],
+                Action::Confirm => &[Binding::Button(PadButton::South)],
+                Action::Back => &[Binding::Button(PadButton::East)],
+                Action::Pause => &[Binding::Button(PadButton::Start)],
+                Action::Continue => &[Binding::Button(PadButton::North)],
+            };
+            let keys = self.keys(action).iter().map(|k| Binding::Key(k.to_string()));
+            (action, keys.chain(pad.iter().cloned()).collect())
+        }).collect()
+    }
+}
+
+/// Every binding of every action, checked
+#[derive(Debug, Clone, PartialEq)]
+pub struct InputMap {
+    bindings: BTreeMap<Action, Vec<Binding>>,
+    pub dead_zone: f32,
+    pub dash_aim: DashAim,
+}
+
+impl InputMap {
+    pub fn builtin() -> Self {
+        BUILTIN.parse().expect("assets/input.ron is broken")
+    }
+
+    pub fn from_config(config: InputConfig) -> Result<InputMap, DefError> {
+        if !(0. ..1.).contains(&config.dead_zone) {
+            return Err(DefError::Invalid(format!("dead_zone must be at least 0 and below 1, not {}", config.dead_zone)));
+        }
+        let mut bindings = config.layout.bindings();
+        bindings.extend(config.bindings);
+        for (action, list) in bindings.iter() {
+            for binding in list.iter() {
+                if let Binding::Key(key) = binding {
+                    if key_code(key).is_none() {
+                        return Err(DefError::InvalidDef {
+                            id: format!("{:?}", action),
+                            reason: format!("there's no key called \"{}\"", key),
+                        });
+                    }
+                }
+            }
+        }
+        Ok(InputMap { bindings, dead_zone: config.dead_zone, dash_aim: config.dash_aim })
+    }
+
+    pub fn bindings(&self, action: Action) -> &[Binding] {
+        self.bindings.get(&action).map_or(&[], |b| b.as_slice())
+    }
+
+    /// How prompts name `action`: its first key and its first gamepad
+    /// binding, e.g. "C / North"
+    pub fn label(&self, action: Action) -> String {
+        let bindings = self.bindings(action);
+        let key = bindings.iter().find(|b| matches!(b, Binding::Key(_)));
+        let pad = bindings.iter().find(|b| !matches!(b, Binding::Key(_)));
+        key.into_iter().chain(pad).map(Binding::label).collect::<Vec<_>>().join(" / ")
+    }
+}
+
+impl FromStr for InputMap {
+    type Err = DefError;
+
+    fn from_str(s: &str) -> Result<InputMap, DefError> {
+        let config: InputConfig = defs::from_ron(s)?;
+        InputMap::from_config(config)
+    }
+}
+
+/// How far every action is held this frame, 0 to 1, and which ones just
+/// started being held. This is all the game reads, so tests and tools can
+/// build one by hand instead of pressing keys.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ActionState {
+    values: [f32; Action::ALL.len()],
+    pressed: [bool; Action::ALL.len()],
+}
+
+impl ActionState {
+    pub fn value(&self, action: Action) -> f32 {
+        self.values[action.index()]
+    }
+
+    pub fn held(&self, action: Action) -> bool {
+        self.value(action) >= PRESS_THRESHOLD
+    }
+
+    /// Only on the frame it starts being held
+    pub fn pressed(&self, action: Action) -> bool {
+        self.pressed[action.index()]
+    }
+
+    pub fn set(&mut self, action: Action, value: f32) {
+        self.values[action.index()] = value.clamp(0., 1.);
+    }
+
+    /// Held all the way, and pressed this frame
+    pub fn press(&mut self, action: Action) {
+        self.set(action, 1.);
+        self.pressed[action.index()] = true;
+    }
+
+    /// `self` as the next frame after `previous`, working out what got pressed
+    pub fn after(mut self, previous: &ActionState) -> ActionState {
+        for action in Action::ALL {
+            self.pressed[action.index()] = self.held(action) && !previous.held(action);
+        }
+        self
+    }
+}
+
+/// Anything actions can be read from. [`Controls`] reads the real devices.
+pub trait InputSource {
+    /// How far `binding` is held right now, 0 to 1
+    fn binding_value(&mut self, binding: &Binding, dead_zone: f32) -> f32;
+
+    /// Called once a frame before any binding is read
+    fn update(&mut self) {}
+}
+
+/// Keyboard and, with the `gamepad` feature, every connected gamepad
+pub struct Devices {
+    #[cfg(feature = "gamepad")]
+    gilrs: Option<gilrs::Gilrs>,
+}
+
+impl Devices {
+    pub fn new() -> Self {
+        Devices {
+            #[cfg(feature = "gamepad")]
+            gilrs: match gilrs::Gilrs::new() {
+                Ok(gilrs) => Some(gilrs),
+                Err(e) => {
+                    eprintln!("gamepads won't work: {}", e);
+                    None
+                }
+            },
+        }
+    }
+
+    /// Where the stick behind `direction` is on every connected pad,
+    /// before any dead zone
+    #[cfg(feature = "gamepad")]
+    fn sticks(&self, direction: StickDirection) -> Vec<(f32, f32)> {
+        self.gilrs.as_ref().map_or(Vec::new(), |g| g.gamepads().map(|(_, pad)| gamepad::stick(&pad, direction)).collect())
+    }
+
+    #[cfg(not(feature = "gamepad"))]
+    fn sticks(&self, _direction: StickDirection) -> Vec<(f32, f32)> {
+        Vec::new()
+    }
+}
+
+impl Default for Devices {
+    fn default() -> Self {
+        Devices::new()
+    }
+}
+
+impl InputSource for Devices {
+    fn binding_value(&mut self, binding: &Binding, dead_zone: f32) -> f32 {
+        match binding {
+            Binding::Key(name) => key_code(name).map_or(0., |key| is_key_down(key) as u8 as f32),
+            #[cfg(feature = "gamepad")]
+            Binding::Button(button) => self.gilrs.as_ref().map_or(0., |g| {
+                g.gamepads().map(|(_, pad)| pad.is_pressed(gamepad::button(*button)) as u8 as f32).fold(0., f32::max)
+            }),
+            #[cfg(not(feature = "gamepad"))]
+            Binding::Button(_) => 0.,
+            Binding::Stick(direction) => self.sticks(*direction).into_iter()
+                .map(|(x, y)| stick_value(x, y, *direction, dead_zone))
+                .fold(0., f32::max),
+        }
+    }
+
+    fn update(&mut self) {
+        // gilrs only updates pad state while its events are drained
+        #[cfg(feature = "gamepad")]
+        if let Some(gilrs) = self.gilrs.as_mut() {
+            while gilrs.next_event().is_some() {}
+        }
+    }
+}
+
+#[cfg(feature = "gamepad")]
+mod gamepad {
+    use super::{PadButton, StickDirection};
+    use gilrs::{Axis, Button, Gamepad};
+
+    pub fn button(button: PadButton) -> Button {
+        match button {
+            PadButton::South => Button::South,
+            PadButton::East => Button::East,
+            PadButton::North => Button::North,
+            PadButton::West => Button::West,
+            PadButton::LeftBumper => Button::LeftTrigger,
+            PadButton::RightBumper => Button::RightTrigger,
+            PadButton::LeftTrigger => Button::LeftTrigger2,
+            PadButton::RightTrigger => Button::RightTrigger2,
+            PadButton::Select => Button::Select,
+            PadButton::Start => Button::Start,
+            PadButton::DPadUp => Button::DPadUp,
+            PadButton::DPadDown => Button::DPadDown,
+            PadButton::DPadLeft => Button::DPadLeft,
+            PadButton::DPadRight => Button::DPadRight,
+        }
+    }
+
+    /// Where the stick behind `direction` is, up and right positive
+    pub fn stick(pad: &Gamepad, direction: StickDirection) -> (f32, f32) {
+        use StickDirection::*;
+        let (x_axis, y_axis) = match direction {
+            LeftUp | LeftDown | LeftLeft | LeftRight => (Axis::LeftStickX, Axis::LeftStickY),
+            RightUp | RightDown | RightLeft | RightRight => (Axis::RightStickX, Axis::RightStickY),
+        };
+        (pad.value(x_axis), pad.value(y_axis))
+    }
+}
+
+/// How far a stick at `x`, `y` is pushed towards `direction`, 0 to 1. The
+/// dead zone is round, so diagonals don't get cut short.
+pub fn stick_value(x: f32, y: f32, direction: StickDirection, dead_zone: f32) -> f32 {
+    use StickDirection::*;
+    let length = (x * x + y * y).sqrt();
+    if length <= dead_zone {
+        return 0.;
+    }
+    let scale = ((length - dead_zone) / (1. - dead_zone)).min(1.) / length;
+    let (x, y) = (x * scale, y * scale);
+    let value = match direction {
+        LeftUp | RightUp => y,
+        LeftDown | RightDown => -y,
+        LeftLeft | RightLeft => -x,
+        LeftRight | RightRight => x,
+    };
+    value.max(0.)
+}
+
+/// Turns whatever `source` reports into actions, one frame at a time
+pub struct Controls<S: InputSource = Devices> {
+    pub map: InputMap,
+    pub source: S,
+    previous: ActionState,
+}
+
+impl<S: InputSource> Controls<S> {
+    pub fn new(map: InputMap, source: S) -> Self {
+        Controls { map, source, previous: ActionState::default() }
+    }
+
+    /// Reads every binding, call it once a frame
+    pub fn poll(&mut self) -> ActionState {
+        self.source.update();
+        let mut state = ActionState::default();
+        for action in Action::ALL {
+            let value = self.map.bindings(action).iter()
+                .map(|b| self.source.binding_value(b, self.map.dead_zone))
+                .fold(0., f32::max);
+            state.set(action, value);
+        }
+        let state = state.after(&self.previous);
+        self.previous = state;
+        state
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Holds some bindings all the way down and the left stick wherever
+    /// it's put
+    #[derive(Default)]
+    struct Fake {
+        held: Vec<Binding>,
+        left_stick: (f32, f32),
+    }
+
+    impl InputSource for Fake {
+        fn binding_value(&mut self, binding: &Binding, dead_zone: f32) -> f32 {
+            match binding {
+                Binding::Stick(direction @ (StickDirection::LeftUp
+                    | StickDirection::LeftDown
+                    | StickDirection::LeftLeft
+                    | StickDirection::LeftRight)) => {
+                    stick_value(self.left_stick.0, self.left_stick.1, *direction, dead_zone)
+                }
+                _ => self.held.contains(binding) as u8 as f32,
+            }
+        }
+    }
+
+    fn key(name: &str) -> Binding {
+        Binding::Key(name.to_string())
+    }
+
+    #[test]
+    fn shipped_controls_and_wasd_parse() {
+        let builtin = InputMap::builtin();
+        assert_eq!(builtin.bindings(Action::MoveUp)[0], key("Up"));
+
+        let wasd: InputMap = "(layout: Wasd)".parse().unwrap();
+        assert_eq!(wasd.bindings(Action::MoveUp)[0], key("W"));
+        assert_eq!(wasd.bindings(Action::Dash)[0], key("Space"));
+        assert_eq!(wasd.dead_zone, 0.2);
+        for action in Action::ALL {
+            assert!(!wasd.bindings(action).is_empty(), "{:?} isn't bound", action);
+        }
+    }
+
+    #[test]
+    fn labels_follow_the_bindings() {
+        assert_eq!(InputMap::builtin().label(Action::Continue), "C / North");
+        let map: InputMap = "(bindings: { Continue: [Button(Start), Key(\"K\"), Key(\"L\")] })".parse().unwrap();
+        assert_eq!(map.label(Action::Continue), "K / Start");
+        let map: InputMap = "(bindings: { Continue: [Key(\"Enter\")] })".parse().unwrap();
+        assert_eq!(map.label(Action::Continue), "Enter");
+    }
+
+    #[test]
+    fn broken_controls_are_rejected() {
+        for config in [
+            "(bindings: { Dash: [Key(\"Nope\")] })",
+            "(dead_zone: 1.)",
+            "(dead_zone: -0.1)",
+            "(layout: Dvorak)",
+        ] {
+            assert!(config.parse::<InputMap>().is_err(), "{} parsed", config);
+        }
+    }
+
+    #[test]
+    fn any_binding_holds_its_action() {
+        let map: InputMap = "(bindings: { Dash: [Key(\"LeftShift\"), Button(RightTrigger)] })".parse().unwrap();
+        for held in [key("LeftShift"), Binding::Button(PadButton::RightTrigger)] {
+            let mut controls = Controls::new(map.clone(), Fake { held: vec![held], ..Fake::default() });
+            let state = controls.poll();
+            assert!(state.held(Action::Dash) && state.pressed(Action::Dash));
+            assert!(!controls.poll().pressed(Action::Dash));
+        }
+        let mut controls = Controls::new(map, Fake { held: vec![key("X")], ..Fake::default() });
+        assert!(!controls.poll().held(Action::Dash), "listing Dash should replace the preset's X");
+    }
+
+    #[test]
+    fn dead_zone_zeroes_small_pushes() {
+        assert_eq!(stick_value(0.1, 0.1, StickDirection::LeftUp, 0.2), 0.);
+        assert_eq!(stick_value(0., 1., StickDirection::LeftUp, 0.2), 1.);
+        assert!((stick_value(0., 0.6, StickDirection::LeftUp, 0.2) - 0.5).abs() < 1e-6);
+        assert_eq!(stick_value(0., 0.6, StickDirection::LeftDown, 0.2), 0.);
+
+        let mut controls = Controls::new(InputMap::builtin(), Fake { left_stick: (0.15, 0.), ..Fake::default() });
+        assert_eq!(controls.poll().value(Action::MoveRight), 0.);
+        controls.source.left_stick = (0.6, 0.);
+        assert!(controls.poll().value(Action::MoveRight) > 0.);
+    }
+}
@@ -0,0 +1,175 @@
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::defs::{self, DefError};
+
+const BUILTIN: &str = include_str!("../assets/levels.ron");
+
+/// Fewer cards than this and there's hardly a choice to make
+pub const MIN_CHOICES: usize = 3;
+/// As many cards as fit on the level up screen
+pub const MAX_CHOICES: usize = 4;
+
+/// How much XP each level takes
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum LevelCurve {
+    /// Level 1 first, the last entry repeats
+    Table(Vec<f32>),
+    Formula { base: f32, per_level: f32, growth: f32 },
+}
+
+/// What passing on a level up gives instead
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum SkipReward {
+    Heal(f32),
+    Gold(u32),
+}
+
+impl SkipReward {
+    /// What the skip button says
+    pub fn label(&self) -> String {
+        match *self {
+            SkipReward::Heal(hp) => format!("+{}hp", hp),
+            SkipReward::Gold(gold) => format!("+{}g", gold),
+        }
+    }
+}
+
+/// How leveling up works, see `assets/levels.ron`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LevelRules {
+    pub curve: LevelCurve,
+    /// Cards on every level up screen
+    pub choices: usize,
+    /// Times a run can draw new cards
+    pub rerolls: u32,
+    /// Times a run can take a card out of the pool for good
+    pub banishes: u32,
+    pub skip: SkipReward,
+}
+
+impl LevelRules {
+    pub fn builtin() -> Self {
+        BUILTIN.parse().expect("assets/levels.ron is broken")
+    }
+}
+
+impl FromStr for LevelRules {
+    type Err = DefError;
+
+    fn from_str(s: &str) -> Result<LevelRules, DefError> {
+        let rules: LevelRules = defs::from_ron(s)?;
+        rules.curve.validate().map_err(DefError::Invalid)?;
+        if !(MIN_CHOICES..=MAX_CHOICES).contains(&rules.choices) {
+            return Err(DefError::Invalid(format!("choices must be between {} and {}", MIN_CHOICES, MAX_CHOICES)));
+        }
+        let reward = match rules.skip {
+            SkipReward::Heal(hp) => hp.is_finite() && hp >= 0.,
+            SkipReward::Gold(_) => true,
+        };
+        if !reward {
+            return Err(DefError::Invalid("skip can't heal a negative amount".to_string()));
+        }
+        Ok(rules)
+    }
+}
+
+impl LevelCurve {
+    /// Every level has to take some XP, or one gem would level up forever
+    fn validate(&self) -> Result<(), String> {
+        match self {
+            LevelCurve::Table(xp) => {
+                if xp.is_empty() {
+                    return Err("the table needs at least one level".to_string());
+                }
+                if let Some(i) = xp.iter().position(|x| !x.is_finite() || *x <= 0.) {
+                    return Err(format!("level {} must take more than 0 XP", i + 1));
+                }
+            }
+            LevelCurve::Formula { base, per_level, growth } => {
+                if !base.is_finite() || *base <= 0. {
+                    return Err("base must be above 0".to_string());
+                }
+                if !per_level.is_finite() || *per_level < 0. {
+                    return Err("per_level can't be negative".to_string());
+                }
+                if !growth.is_finite() || *growth < 1. {
+                    return Err("growth must be at least 1".to_string());
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// XP it takes to get from `level` to the one after
+    pub fn xp_to_next(&self, level: i32) -> f32 {
+        let n = (level.max(1) - 1) as usize;
+        match self {
+            LevelCurve::Table(xp) => xp[n.min(xp.len() - 1)],
+            LevelCurve::Formula { base, per_level, growth } => {
+                (base + per_level * n as f32) * growth.powi(n as i32)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputState;
+    use crate::player::Player;
+    use crate::world::{LevelUpChoice, StepOutcome, World};
+
+    fn rising() -> LevelCurve {
+        LevelCurve::Formula { base: 100., per_level: 50., growth: 1. }
+    }
+
+    #[test]
+    fn curves_give_xp_per_level() {
+        let table = LevelCurve::Table(vec![10., 20., 30.]);
+        assert_eq!(table.xp_to_next(0), 10.);
+        assert_eq!(table.xp_to_next(2), 20.);
+        assert_eq!(table.xp_to_next(9), 30.);
+        assert_eq!(rising().xp_to_next(3), 200.);
+        let growing = LevelCurve::Formula { base: 10., per_level: 0., growth: 2. };
+        assert_eq!(growing.xp_to_next(4), 80.);
+        assert!(LevelCurve::Table(vec![]).validate().is_err());
+        assert!(LevelCurve::Formula { base: 10., per_level: 0., growth: 0.5 }.validate().is_err());
+    }
+
+    #[test]
+    fn three_or_four_choices() {
+        for (choices, ok) in [(2, false), (3, true), (4, true), (5, false)] {
+            let rules = BUILTIN.replace("choices: 3", &format!("choices: {}", choices));
+            assert_eq!(rules.parse::<LevelRules>().is_ok(), ok, "{} choices", choices);
+        }
+    }
+
+    #[test]
+    fn big_pickups_queue_a_level_up_each_and_carry_the_rest() {
+        let mut player = Player::new();
+        let mut world = World::new(1);
+        world.level_rules.curve = rising();
+        world.level_rules.skip = SkipReward::Gold(5);
+
+        // 100 for level 2 and 150 for level 3, 170 of the 200 for level 4
+        player.add_xp(420.);
+        assert_eq!(world.step(&mut player, 1. / 60., &InputState::default()), StepOutcome::LevelUp);
+        assert_eq!(player.level, 3);
+        assert_eq!(player.pending_level_ups, 2);
+        assert_eq!(player.xp, 170.);
+        assert_eq!(player.max_xp, 200.);
+
+        // Still waiting on the next frame
+        assert_eq!(world.step(&mut player, 1. / 60., &InputState::default()), StepOutcome::LevelUp);
+        let mut offered = world.next_level_up(&mut player).unwrap();
+        assert!(world.resolve_level_up(&mut player, &mut offered, LevelUpChoice::Pick(0)));
+        assert_eq!(player.pending_level_ups, 1);
+        let mut offered = world.next_level_up(&mut player).unwrap();
+        assert!(world.resolve_level_up(&mut player, &mut offered, LevelUpChoice::Skip));
+        assert_eq!(world.stats.gold, 5);
+        assert!(world.next_level_up(&mut player).is_none());
+        assert_eq!(world.step(&mut player, 1. / 60., &InputState::default()), StepOutcome::Continue);
+    }
+}
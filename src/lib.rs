@@ -0,0 +1,133 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+
+pub mod ui;
+pub mod timer;
+pub mod upgrade;
+pub mod tween;
+pub mod enemies;
+pub mod enemy_defs;
+pub mod events;
+pub mod direction;
+pub mod player;
+pub mod player_stats;
+pub mod damage_popup;
+pub mod defs;
+pub mod difficulty;
+pub mod animation;
+pub mod particles;
+pub mod pickups;
+pub mod stats;
+pub mod telemetry;
+pub mod stopwatch;
+pub mod stopwatch_bevy;
+pub mod input;
+pub mod input_map;
+pub mod levels;
+pub mod clock;
+pub mod rng;
+pub mod replay;
+pub mod save;
+pub mod spatial;
+pub mod spawn;
+pub mod weapons;
+pub mod world;
+pub mod bot;
+use timer::Timer;
+use upgrade::Upgrade;
+
+use ::tween::Tweener;
+
+pub type TestTween<Value, Time> = Tweener<Value, Time, Box<dyn ::tween::Tween<Value>>>;
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Position {
+    pub x: f32,
+    pub y: f32
+}
+
+#[derive(Clone, Copy, Serialize, Deserialize)]
+pub struct Collider {
+    pub x: f32,
+    pub y: f32,
+    pub width: i32,
+    pub height: i32,
+    pub radius: f32
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Bullet {
+    pub x: f32,
+    pub y: f32,
+    pub dir_x: f32,
+    pub dir_y: f32,
+    pub speed: f32,
+    pub damage: f32,
+    pub active: bool,
+    /// How far from its center it hits, 0 is a point
+    #[serde(default)]
+    pub radius: f32,
+    /// Enemies it can still go through
+    #[serde(default)]
+    pub pierce: u32,
+    /// Ids of the enemies it went through already
+    #[serde(default)]
+    pub hit: Vec<u64>,
+}
+
+pub fn col(a: Position, b: Position, r: f32) -> bool {
+    let x = (b.x - a.x).abs();
+    if x > r {
+        return false
+    }
+    let y = (b.y - a.y).abs();
+    if y > r {
+        return false
+    }
+    return (x*x+y*y)<r*r
+}
+
+pub fn dist(a: Position, b: Position, r: f32) -> f32 {
+    let x = (a.x - b.x).abs();
+    let y = (a.y - b.y).abs();
+    if x+y < r*1.5 {
+        let _d = (x*x+y*y).sqrt();
+        if _d < r {
+            return _d;
+        } else {
+            return r;
+        }
+    } else {
+        return r;
+    }
+}
+
+pub fn get_dir_(vec1: Position, vec2: Position) -> f32 {
+    return (vec2.x - vec1.x).atan2(vec2.y - vec1.y);
+}
+
+pub fn axis(negative: bool, positive: bool) -> f32 {
+    ((positive as i8) - (negative as i8)) as f32
+}
+
+pub fn draw_bullets(texture: Texture2D, bullets: &[Bullet]) {
+    for bullet in bullets.iter() {
+        if bullet.active {
+            draw_texture_ex(
+                texture,
+                bullet.x,
+                bullet.y,
+                WHITE,
+        DrawTextureParams {
+                    dest_size: Some(vec2(8., 8.)),
+                    source: Some(Rect::new(
+                        40.,
+                        2.,
+                        8.,
+                        8.,
+                    )),
+                ..Default::default()
+            })
+        }
+    }
+}
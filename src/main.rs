@@ -1,28 +1,30 @@
-use std::{time::Duration, collections::HashMap, f32::consts::PI};
+use std::{time::Duration, collections::HashMap, str::FromStr};
 use keyframe::{Keyframe, functions::EaseOut};
 use macroquad::prelude::*;
 
-mod ui;
-mod timer;
-mod upgrade;
-mod tween;
-mod enemies;
-mod direction;
-mod player;
-mod damage_popup;
-mod animation;
-mod particles;
-mod stopwatch;
-mod stopwatch_bevy;
-use crate::tween::Tween;
-use ui::*;
-use timer::Timer;
-use upgrade::*;
-use enemies::*;
-use damage_popup::*;
-use animation::Animation;
-use particles::*;
-use stopwatch_bevy::*;
+use lowrez2023::*;
+use lowrez2023::tween::Tween;
+use lowrez2023::ui::*;
+use lowrez2023::upgrade::*;
+use lowrez2023::weapons::WeaponDefs;
+use lowrez2023::levels::LevelRules;
+use lowrez2023::enemies::*;
+use lowrez2023::enemy_defs::EnemyDefs;
+use lowrez2023::spawn::{SpawnDirector, Timeline};
+use lowrez2023::animation::Animation;
+use lowrez2023::particles::*;
+use lowrez2023::pickups::draw_pickups;
+use lowrez2023::player;
+use lowrez2023::input::InputState;
+use lowrez2023::input_map::{Action, ActionState, Controls, Devices, InputMap};
+use lowrez2023::world::*;
+use lowrez2023::clock;
+use lowrez2023::bot::Bot;
+use lowrez2023::difficulty::{Difficulty, Preset};
+use lowrez2023::rng::seed_from_time;
+use lowrez2023::replay::{Replay, ReplayRecorder, ReplayPlayer};
+use lowrez2023::save::{self, SaveGame};
+use lowrez2023::telemetry::TelemetryRecorder;
 
 use ::tween::{Tweener, Oscillator, CircInOut};
 
@@ -52,7 +54,7 @@ use ::tween::{Tweener, Oscillator, CircInOut};
 
 // Juicing
 // - screen shake ✅
-// - flash enemie on hit
+// - flash enemie on hit ✅
 // - particles ✅
 // - animate sprites ✅
 // - sound
@@ -60,79 +62,30 @@ use ::tween::{Tweener, Oscillator, CircInOut};
 // Improve collision
 // Collision avoidance?
 
-const PLAYER_SPEED: f32 = 10.;
-
-// ============================================================================
-// GAME STATE STRUCTS
-// ============================================================================
-
-pub struct World {
-    pub enemies: Vec<Enemies>,
-    pub bat_enemies: Vec<BatEnemy>,
-    pub tower_enemies: Vec<TowerEnemy>,
-    pub bullets: Vec<Bullet>,
-    pub enemy_bullets: Vec<Bullet>,
-    pub dead_enemies: Vec<DeadEnemy>,
-    pub damage_popups: Vec<DamagePopup>,
-    pub particles: Vec<Particle>,
-    pub intro_particles: Vec<Particle>,
-    pub progression: f32,
-    pub base_given_xp: f32,
-    pub kill_count: i32,
-    pub max_b_cooldown: Timer,
-    pub bullet_cooldown: Timer,
-    pub current_bullet_cooldown_bonus: f32,
-    pub screen_shake_amount: f32,
-    pub stopwatch: Stopwatch,
-}
-
-impl World {
-    pub fn new() -> Self {
-        World {
-            enemies: Vec::new(),
-            bat_enemies: Vec::new(),
-            tower_enemies: Vec::new(),
-            bullets: Vec::new(),
-            enemy_bullets: Vec::new(),
-            dead_enemies: Vec::new(),
-            damage_popups: Vec::new(),
-            particles: Vec::new(),
-            intro_particles: Vec::new(),
-            progression: 1.0,
-            base_given_xp: 50.0,
-            kill_count: 0,
-            max_b_cooldown: Timer::new(700),
-            bullet_cooldown: Timer::new(700),
-            current_bullet_cooldown_bonus: 1.0,
-            screen_shake_amount: 0.,
-            stopwatch: Stopwatch::new(),
+const LAST_RUN_REPLAY: &str = "replays/last_run.vsrp";
+const ENEMY_DEFS: &str = "assets/enemies.ron";
+const UPGRADE_DEFS: &str = "assets/upgrades.ron";
+const SPAWN_TIMELINE: &str = "assets/spawns.ron";
+const WEAPON_DEFS: &str = "assets/weapons.ron";
+const INPUT_MAP: &str = "assets/input.ron";
+const LEVEL_RULES: &str = "assets/levels.ron";
+
+/// Game data is read at startup so it can be tuned without a rebuild.
+/// A broken file stops the game right away, with what's wrong with it.
+async fn load_defs<T, E: std::fmt::Display>(path: &str, parse: fn(&str) -> Result<T, E>) -> T {
+    let text = match load_string(path).await {
+        Ok(text) => text,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
+        }
+    };
+    match parse(&text) {
+        Ok(defs) => defs,
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            std::process::exit(1);
         }
-    }
-
-    pub fn reset(&mut self) {
-        self.enemies.clear();
-        self.bat_enemies.clear();
-        self.tower_enemies.clear();
-        self.bullets.clear();
-        self.enemy_bullets.clear();
-        self.dead_enemies.clear();
-        self.damage_popups.clear();
-        self.particles.clear();
-        self.progression = 1.0;
-        self.base_given_xp = 50.0;
-        self.kill_count = 0;
-        self.bullet_cooldown = Timer::new(700);
-        self.current_bullet_cooldown_bonus = 1.0;
-        self.screen_shake_amount = 0.;
-        self.stopwatch = Stopwatch::new();
-    }
-
-    pub fn apply_screen_shake(&mut self, amount: f32) {
-        self.screen_shake_amount += amount;
-    }
-
-    pub fn decay_screen_shake(&mut self) {
-        self.screen_shake_amount *= 0.94;
     }
 }
 
@@ -146,6 +99,12 @@ pub struct Renderer {
     pub death_tweener: Tweener<f32, f32, Box<dyn ::tween::Tween<f32>>>,
     pub upgrade_menu_tween: Tween,
     pub choosen_upgrade_index: i32,
+    /// The next level up card confirmed gets banished
+    pub banishing: bool,
+    pub pause_index: usize,
+    /// The pause menu shows the options instead
+    pub pause_options_open: bool,
+    pub screen_shake: bool,
 }
 
 impl Renderer {
@@ -198,8 +157,13 @@ impl Renderer {
                 0,
                 1,
                 true,
+                clock::real_now(),
             ),
             choosen_upgrade_index: 0,
+            banishing: false,
+            pause_index: 0,
+            pause_options_open: false,
+            screen_shake: true,
         }
     }
 
@@ -223,8 +187,10 @@ impl Renderer {
             0,
             1,
             true,
+            clock::real_now(),
         );
         self.choosen_upgrade_index = 0;
+        self.banishing = false;
     }
 }
 
@@ -239,13 +205,38 @@ pub struct GameSession {
     pub slime_texture: Texture2D,
     pub main_title_texture: Texture2D,
     pub font: Font,
+    /// Seed asked for on the command line, every run reuses it when set
+    pub seed_override: Option<u64>,
+    /// Difficulty new runs are played at
+    pub difficulty: Preset,
+    pub recorder: Option<ReplayRecorder>,
+    /// Whether runs log telemetry, `--telemetry` on the command line
+    pub record_telemetry: bool,
+    pub telemetry: Option<TelemetryRecorder>,
+    /// Plays instead of the keyboard when set
+    pub bot: Option<Bot>,
+    pub replay_player: Option<ReplayPlayer>,
+    pub controls: Controls,
 }
 
 impl GameSession {
-    pub async fn new() -> Result<Self, macroquad::prelude::FileError> {
+    pub async fn new(seed_override: Option<u64>, difficulty: Preset) -> Result<Self, macroquad::prelude::FileError> {
+        let mut world = World::new(seed_override.unwrap_or_else(seed_from_time));
+        world.difficulty = Difficulty::new(difficulty);
+        world.enemy_defs = load_defs(ENEMY_DEFS, EnemyDefs::from_str).await;
+        world.upgrade_defs = load_defs(UPGRADE_DEFS, UpgradeDefs::from_str).await;
+        world.weapon_defs = load_defs(WEAPON_DEFS, WeaponDefs::from_str).await;
+        world.level_rules = load_defs(LEVEL_RULES, LevelRules::from_str).await;
+        let timeline = load_defs(SPAWN_TIMELINE, Timeline::from_str).await;
+        if let Err(e) = timeline.check_enemies(&world.enemy_defs) {
+            eprintln!("{}: {}", SPAWN_TIMELINE, e);
+            std::process::exit(1);
+        }
+        world.spawn_director = SpawnDirector::new(timeline);
+        let input_map = load_defs(INPUT_MAP, InputMap::from_str).await;
         Ok(GameSession {
             player: player::Player::new(),
-            world: World::new(),
+            world,
             renderer: Renderer::new(),
             main_texture: load_texture("assets/vs-dx-atlas-padded.png").await.unwrap(),
             ui_texture: load_texture("assets/vs-dx-ui-atlas.png").await.unwrap(),
@@ -254,13 +245,91 @@ impl GameSession {
             slime_texture: load_texture("assets/vs-dx-enemies-atlas.png").await.unwrap(),
             main_title_texture: load_texture("assets/vs-dx-maintitle-atlas.png").await.unwrap(),
             font: load_ttf_font("assets/smolFontMono.ttf").await.unwrap(),
+            seed_override,
+            difficulty,
+            recorder: None,
+            record_telemetry: false,
+            telemetry: None,
+            bot: None,
+            replay_player: None,
+            controls: Controls::new(input_map, Devices::new()),
         })
     }
 
     pub fn reset(&mut self) {
         self.player = player::Player::new();
-        self.world.reset();
+        // A replay or a continued run may have been at another difficulty
+        self.world.difficulty = Difficulty::new(self.difficulty);
+        self.world.reset(self.seed_override.unwrap_or_else(seed_from_time));
         self.renderer.reset();
+        self.recorder = Some(ReplayRecorder::new(self.world.seed(), self.difficulty));
+        self.start_telemetry();
+    }
+
+    /// What the oldest level up still waiting offers, None when there's
+    /// nothing to pick
+    pub fn next_level_up(&mut self) -> Option<Vec<Box<dyn Upgrade>>> {
+        let offered = self.world.next_level_up(&mut self.player)?;
+        if let Some(telemetry) = self.telemetry.as_mut() {
+            let names: Vec<&str> = offered.iter().map(|u| u.get_name()).collect();
+            telemetry.record_upgrades_offered(&self.world, &names);
+        }
+        Some(offered)
+    }
+
+    pub fn save_run(&self) {
+        if let Err(e) = SaveGame::capture(&self.world, &self.player).save(save::SAVE_PATH) {
+            eprintln!("couldn't save the run: {}", e);
+        }
+    }
+
+    /// Picks the saved run back up. Runs resumed this way aren't recorded,
+    /// a replay has to start from the seed.
+    pub fn continue_run(&mut self) -> bool {
+        match SaveGame::load(save::SAVE_PATH) {
+            Ok(saved) => {
+                saved.restore(&mut self.world, &mut self.player);
+                self.renderer.reset();
+                self.recorder = None;
+                self.start_telemetry();
+                true
+            }
+            Err(e) => {
+                eprintln!("couldn't continue: {}", e);
+                false
+            }
+        }
+    }
+
+    /// Keeps the run that just ended around, so it can be attached to a
+    /// bug report and played back with `--replay`
+    pub fn save_replay(&mut self) {
+        if let Some(recorder) = self.recorder.take() {
+            let replay = recorder.finish(&self.world, &self.player);
+            match replay.save(LAST_RUN_REPLAY) {
+                Ok(()) => println!("replay saved to {}", LAST_RUN_REPLAY),
+                Err(e) => eprintln!("couldn't save replay: {}", e),
+            }
+        }
+    }
+
+    fn start_telemetry(&mut self) {
+        self.finish_telemetry();
+        if !self.record_telemetry {
+            return;
+        }
+        let path = TelemetryRecorder::path_for(self.world.seed());
+        match TelemetryRecorder::create(&path, &self.world) {
+            Ok(recorder) => self.telemetry = Some(recorder),
+            Err(e) => eprintln!("couldn't record telemetry to {}: {}", path.display(), e),
+        }
+    }
+
+    pub fn finish_telemetry(&mut self) {
+        if let Some(telemetry) = self.telemetry.take() {
+            println!("telemetry saved to {}", telemetry.path().display());
+            telemetry.finish(&self.world, &self.player);
+        }
     }
 
     pub fn setup_textures(&self) {
@@ -285,21 +354,6 @@ fn window_conf() -> Conf {
 }
 
 
-#[derive(Clone, Copy)]
-pub struct Position {
-    pub x: f32,
-    pub y: f32
-}
-
-#[derive(Clone, Copy)]
-pub struct Collider {
-    pub x: f32,
-    pub y: f32,
-    pub width: i32,
-    pub height: i32,
-    pub radius: f32
-}
-
 fn sprite_rect(ix: u32) -> Rect {
     let sw = 8. as f32;
     let sh = 8. as f32;
@@ -310,9 +364,9 @@ fn sprite_rect(ix: u32) -> Rect {
     Rect::new(sx + 1., sy + 1., sw - 2.2, sh - 2.2)
 }
 
-fn draw_player(texture: Texture2D, frame: Option<Rect>, player: &player::Player) {
+fn draw_player(texture: Texture2D, frame: Option<Rect>, player: &player::Player, now: f64) {
     let mut color = WHITE;
-    if player.inv_timer.value() != 1.0 {
+    if player.inv_timer.value(now) != 1.0 {
         color = Color::new(1.0, 0., 0., 1.);
     }
     draw_texture_ex(
@@ -338,41 +392,10 @@ fn draw_player_collider(x: &mut f32, y: &mut f32) {
     );
 }
 
-pub fn col(a: Position, b: Position, r: f32) -> bool {
-    let x = (b.x - a.x).abs();
-    if x > r {
-        return false
-    }
-    let y = (b.y - a.y).abs();
-    if y > r {
-        return false
-    }
-    return (x*x+y*y)<r*r
-}
-
-pub fn dist(a: Position, b: Position, r: f32) -> f32 {
-    let x = (a.x - b.x).abs();
-    let y = (a.y - b.y).abs();
-    if x+y < r*1.5 {
-        let _d = (x*x+y*y).sqrt();
-        if _d < r {
-            return _d;
-        } else {
-            return r;
-        }
-    } else {
-        return r;
-    }
-}
-
 fn get_dir(x1: f32, y1: f32, x2: f32, y2: f32) -> f32 {
     return (x2 - x1).atan2(y2 - y1);
 }
 
-pub fn get_dir_(vec1: Position, vec2: Position) -> f32 {
-    return (vec2.x - vec1.x).atan2(vec2.y - vec1.y);
-}
-
 fn draw_map_cell(texture: Texture2D, x: i32, y: i32) {
     draw_texture_ex(
         texture, 
@@ -386,179 +409,97 @@ DrawTextureParams {
     })
 }
 
-pub struct Bullet {
-    x: f32,
-    y: f32,
-    dir_x: f32,
-    dir_y: f32,
-    active: bool
-}
-
-fn draw_bullets(texture: Texture2D, bullets: &mut Vec<Bullet>) {
-    for bullet in bullets.iter() {
-        if bullet.active {
-            draw_texture_ex(
-                texture, 
-                bullet.x,
-                bullet.y, 
-                WHITE,
-        DrawTextureParams { 
-                    dest_size: Some(vec2(8., 8.)), 
-                    source: Some(Rect::new(
-                        40.,
-                        2.,
-                        8.,
-                        8.,
-                    )),
-                ..Default::default()
-            })     
+/// Render pass for the running stage, reads the world but never advances it
+fn draw_in_game(session: &mut GameSession) {
+    for x in 0..160 {
+        for y in 0..100 {
+            draw_map_cell(session.main_texture, x, y);
         }
     }
-}
 
-fn spawn_bullet(bullets: &mut Vec<Bullet>, enemies: &mut Vec<Enemies>, x: &mut f32, y: &mut f32) {
-    let mut _dist= 128.;
-    let mut _dir: Vec2 = vec2(1.,1.);
-    if enemies.len() > 0 {
-        for e in enemies.iter() {
-            let _d = dist(
-                Position { x: *x, y: *y },
-                e.position,
-            _dist);
-            if _d < _dist {
-                _dist= _d;
-                // _dir = get_dir(e.position.x,e.position.y,*x,*y);
-                // let foo = na::Vector2::new(*x, *y);
-                // let bar = na::Vector2::new(e.position.x, e.position.y);
-                // _dir = foo.sub(bar).norm();
-                // println!("{}", _dir);
-                _dir = Vec2::new(*x, *y) - Vec2::new(e.position.x, e.position.y);
-                if let Some(d) = _dir.try_normalize() {
-                    _dir = d;
-                }
-            }
-        }
-        bullets.push(Bullet { x: *x + 2., y: *y + 2., dir_x: _dir.x, dir_y: _dir.y, active: true });
-    }
-}
+    let player_frame = session.renderer.anims.get_mut("idle").unwrap().get_animation_source(Duration::from_secs_f32(get_frame_time()));
 
-fn update_bullets(bullets: &mut Vec<Bullet>, particles: &mut Vec<Particle>) {
-    let delta = get_frame_time();
-    
-    for bullet in bullets.iter_mut() {
-        if bullet.active {
-            bullet.x -= bullet.dir_x * delta * 20.; 
-            bullet.y -= bullet.dir_y * delta * 20.;
-            spawn_particle(particles, bullet.x, bullet.y, Box::new(ShotParticle{}));
-        }
-    }
-}
+    draw_pickups(&session.world.pickups);
+    draw_particles(&session.world.particles, session.player_texture, session.world.clock.now());
+    draw_player(
+        session.player_texture,
+        player_frame,
+        &session.player,
+        session.world.clock.now()
+    );
+    draw_enemies(
+        session.slime_texture,
+        &mut session.world.enemies,
+        &mut session.player.pos_x,
+        &mut session.player.pos_y
+    );
+    draw_dead_enemies(session.slime_texture, &mut session.world.dead_enemies, &mut session.player.pos_x, &mut session.player.pos_y);
+    draw_bullets(session.main_texture, &session.world.bullets);
+    session.world.weapons.draw(session.main_texture, &session.player);
+    draw_enemy_bullets(session.main_texture, &mut session.world.enemy_bullets);
 
-fn damage_enemy(
-    bullets: &mut Vec<Bullet>, 
-    enemies: &mut Vec<Enemies>, 
-    dmg_pop: &mut Vec<DamagePopup>,
-    screen_shake_amount: &mut f32,
-    player_dmg: &f32
-) {
-    for e in enemies.iter_mut() {
-        for bullet in bullets.iter_mut() {
-            // Collide with enemies
-            if col(
-                Position { x: bullet.x, y: bullet.y }, 
-                Position { x: e.position.x + 2., y: e.position.y + 2. }, 
-                5.
-            ) {
-                if e.hp > 0. {
-                    bullet.active = false;
-                    dmg_pop.push(DamagePopup::new(e.position.x, e.position.y, player_dmg.abs() as i32));
-                    *screen_shake_amount += 1.0;
-                    e.hp -= player_dmg;
-                    // println!("{}", e.hp);
-                }
-            }
-        }
-    }
-}
+    // draw_player_collider(&mut session.player.pos_x, &mut session.player.pos_y);
+    // draw_enemies_collider(&session.world.enemies);
 
-fn kill_enemies(enemies: &mut Vec<Enemies>, player_xp: &mut f32, dead_enemies: &mut Vec<DeadEnemy>, kill_count: &mut i32, progression: &mut f32, base_given_xp: &mut f32) {
-    for e in enemies.iter_mut() {
-        if e.hp <= 0. { 
-            e.alive = false;
-            *player_xp += e.get_given_xp();
-            *kill_count += 1;
-            update_progress_level(progression, *kill_count);
-            let dead_enemy_obj = DeadEnemy::new(e.position.x, e.position.y, e.curr_frame);
-            dead_enemies.push(dead_enemy_obj);
-        }
+    for popup in session.world.damage_popups.iter() {
+        popup.draw(session.font);
     }
 }
 
-fn kill_bat_enemies(enemies: &mut Vec<BatEnemy>, player_xp: &mut f32, dead_enemies: &mut Vec<DeadEnemy>, kill_count: &mut i32, progression: &mut f32, base_given_xp: &mut f32) {
-    for e in enemies.iter_mut() {
-        if e.hp <= 0. { 
-            e.active = false;
-            *player_xp += e.given_xp;
-            *kill_count += 1;
-            update_progress_level(progression, *kill_count);
-            let dead_enemy_obj = DeadEnemy::new(e.x, e.y, e.curr_frame);
-            dead_enemies.push(dead_enemy_obj);
+/// What confirming on the level up screen does, `index` runs over the cards
+/// and then the buttons
+fn level_up_input(actions: &ActionState, index: usize, cards: usize, banishes_left: u32, banishing: &mut bool) -> Option<LevelUpChoice> {
+    if actions.pressed(Action::Back) {
+        *banishing = false;
+    }
+    if !actions.pressed(Action::Confirm) {
+        return None;
+    }
+    if index < cards {
+        if *banishing {
+            *banishing = false;
+            return Some(LevelUpChoice::Banish(index));
         }
+        return Some(LevelUpChoice::Pick(index));
     }
-}
-
-fn level_up_player(player_xp: &mut f32, player_max_xp: &mut f32, mut player_level: &mut i32, state: &mut LevelState) {
-    *player_xp = 0.;
-    *player_level += 1;
-    *state = LevelState::LevelUp;
-}
-
-fn level_up_input() -> Option<LevelState> {
-    if is_key_pressed(KeyCode::Z) {
-        return Some(LevelState::InGame)
+    match LEVEL_UP_BUTTONS[index - cards] {
+        LevelUpButton::Reroll => Some(LevelUpChoice::Reroll),
+        LevelUpButton::Banish => {
+            *banishing = !*banishing && banishes_left > 0;
+            None
+        }
+        LevelUpButton::Skip => Some(LevelUpChoice::Skip),
     }
-    None
 }
 
-fn choose_upgrade_input(index: &mut i32, tween: &mut Tween) {
-    if is_key_pressed(KeyCode::Right) {
-        if *index == 1 {
+/// Left and right go through the cards and then the buttons, down and up
+/// jump between the two rows
+fn choose_upgrade_input(actions: &ActionState, index: &mut i32, tween: &mut Tween, cards: usize) {
+    let count = (cards + LEVEL_UP_BUTTONS.len()) as i32;
+    let cards = cards as i32;
+    if actions.pressed(Action::MoveRight) {
+        if *index >= count - 1 {
             return;
         } else {
             *index += 1;
-            tween.restart();
+            tween.restart(clock::real_now());
         }
     }
-    if is_key_pressed(KeyCode::Left) {
+    if actions.pressed(Action::MoveLeft) {
         if *index == 0 {
             return;
         } else {
             *index -= 1;
-            tween.restart();
+            tween.restart(clock::real_now());
         }
     }
-}
-
-pub fn axis(negative: bool, positive: bool) -> f32 {
-    ((positive as i8) - (negative as i8)) as f32
-}
-
-fn move_player(player: &mut player::Player, delta: f32) {
-    let a = axis(is_key_down(KeyCode::Left), is_key_down(KeyCode::Right));
-    let b = axis(is_key_down(KeyCode::Down), is_key_down(KeyCode::Up));
-    let magnitude = (a.powi(2) + b.powi(2)).sqrt();
-    let foo_x = a / magnitude;
-    let foo_y = b / magnitude;
-    if a != 0. || b != 0. {
-        player.pos_x += foo_x * delta * PLAYER_SPEED * player.speed_bonus;
-        player.pos_y -= foo_y * delta * PLAYER_SPEED * player.speed_bonus;
-    }
-
-    if a == -1. {
-        player.flip_x = true
+    if actions.pressed(Action::MoveDown) && *index < cards {
+        *index = cards;
+        tween.restart(clock::real_now());
     }
-    if a == 1. {
-        player.flip_x = false
+    if actions.pressed(Action::MoveUp) && *index >= cards {
+        *index = 0;
+        tween.restart(clock::real_now());
     }
 }
 
@@ -566,7 +507,32 @@ enum LevelState {
     PreGame,
     LevelUp,
     InGame,
-    StageCleared
+    Paused,
+    StageCleared,
+    Replay
+}
+
+/// A frame longer than this pauses the run. It's not focus loss: macroquad
+/// 0.3 has no focus or minimize events to go by, but a minimized window
+/// stops getting frames and comes back with a long one. A hitch this long
+/// pauses too, and a window left in the background that keeps rendering
+/// doesn't.
+const LONG_FRAME_SECS: f32 = 0.5;
+
+const PAUSE_ITEMS: [&str; 4] = ["Resume", "Restart", "Options", "Quit to Title"];
+
+/// Up/Down go through `count` items, Confirm picks the current one
+fn menu_input(actions: &ActionState, index: &mut usize, count: usize) -> Option<usize> {
+    if actions.pressed(Action::MoveDown) {
+        *index = (*index + 1) % count;
+    }
+    if actions.pressed(Action::MoveUp) {
+        *index = (*index + count - 1) % count;
+    }
+    if actions.pressed(Action::Confirm) {
+        return Some(*index);
+    }
+    None
 }
 
 fn get_minutes_from_millis(elapsed_time: u128) -> String {
@@ -587,57 +553,157 @@ fn get_seconds_from_millis(elapsed_time: u128) -> String {
     }
 }
 
-pub type TestTween<Value, Time> = Tweener<Value, Time, Box<dyn ::tween::Tween<Value>>>;
+fn arg_value(name: &str) -> Option<String> {
+    let args: Vec<String> = std::env::args().collect();
+    let position = args.iter().position(|a| a == name)?;
+    args.get(position + 1).cloned()
+}
 
-pub fn update_progress_level(progression: &mut f32, kill_count: i32) {
-    // println!(" rem {}", kill_count.rem_euclid(15));
-     if kill_count > 0 && kill_count.rem_euclid(15) == 0 {
-        *progression += 1.0;
-     }
+/// `--seed <number>` replays the same run every time
+fn seed_from_args() -> Option<u64> {
+    match arg_value("--seed")?.parse::<u64>() {
+        Ok(seed) => Some(seed),
+        Err(_) => {
+            eprintln!("--seed expects a positive number, ignoring it");
+            None
+        }
+    }
 }
 
-pub fn update_given_xp(base_given_xp: &mut f32, kill_count: i32) {
-    let b = *base_given_xp;
-    *base_given_xp -= (0.1 * (b)) - (0.2 * (kill_count as f32))
+/// `--difficulty easy|normal|hard`, normal when left out
+fn difficulty_from_args() -> Preset {
+    let Some(name) = arg_value("--difficulty") else {
+        return Preset::Normal;
+    };
+    match Preset::from_name(&name) {
+        Some(preset) => preset,
+        None => {
+            eprintln!("--difficulty expects easy, normal or hard, playing on normal");
+            Preset::Normal
+        }
+    }
+}
+
+/// `--autoplay` lets the bot play, `--prefer <id,id,...>` says which
+/// upgrades it goes for
+fn bot_from_args() -> Option<Bot> {
+    if !std::env::args().any(|a| a == "--autoplay") {
+        return None;
+    }
+    let preferences = arg_value("--prefer")
+        .map(|ids| ids.split(',').map(|id| id.trim().to_string()).collect())
+        .unwrap_or_default();
+    Some(Bot::new(preferences))
+}
+
+/// `--replay <file>` plays back a recorded run instead of the title screen
+fn replay_from_args() -> Option<Replay> {
+    let path = arg_value("--replay")?;
+    match Replay::load(&path) {
+        Ok(replay) => Some(replay),
+        Err(e) => {
+            eprintln!("{}: {}", path, e);
+            None
+        }
+    }
+}
+
+fn replay_input(actions: &ActionState, replay_player: &mut ReplayPlayer, player: &mut player::Player, world: &mut World) -> Option<LevelState> {
+    if actions.pressed(Action::Confirm) {
+        replay_player.paused = !replay_player.paused;
+    }
+    if actions.pressed(Action::MoveUp) {
+        replay_player.faster();
+    }
+    if actions.pressed(Action::MoveDown) {
+        replay_player.slower();
+    }
+    if actions.pressed(Action::MoveRight) {
+        let target = replay_player.elapsed_secs() + 5.;
+        replay_player.seek(target, player, world);
+    }
+    if actions.pressed(Action::MoveLeft) {
+        let target = replay_player.elapsed_secs() - 5.;
+        replay_player.seek(target, player, world);
+    }
+    if actions.pressed(Action::Back) {
+        return Some(LevelState::PreGame)
+    }
+    None
 }
 
 #[macroquad::main(window_conf)]
 async fn main() {
     let camera_zoom: f32 = 10.0;
 
-    let mut session = GameSession::new().await.unwrap();
+    let mut session = GameSession::new(seed_from_args(), difficulty_from_args()).await.unwrap();
     session.setup_textures();
+    session.bot = bot_from_args();
+    session.record_telemetry = std::env::args().any(|a| a == "--telemetry");
 
-    let mut upgrades: Vec<Box<dyn Upgrade>> = pick_random_upgrades();
+    let mut upgrades: Vec<Box<dyn Upgrade>> = Vec::new();
     let mut level_state = LevelState::PreGame;
+    let mut can_continue = save::save_exists();
+
+    // Closing the window mid-run saves it first
+    prevent_quit();
+
+    if let Some(replay) = replay_from_args() {
+        let mut replay_player = ReplayPlayer::new(replay);
+        replay_player.restart(&mut session.player, &mut session.world);
+        session.replay_player = Some(replay_player);
+        level_state = LevelState::Replay;
+    }
 
     loop {
+        if is_quit_requested() {
+            match level_state {
+                LevelState::InGame | LevelState::LevelUp | LevelState::Paused if session.player.active => session.save_run(),
+                _ => {}
+            }
+            session.finish_telemetry();
+            break;
+        }
+
         clear_background(Color::from_rgba(37, 33, 41, 255));
         let delta = get_frame_time();
+        let actions = session.controls.poll();
 
         session.world.decay_screen_shake();
 
+        let shake_amount = if session.renderer.screen_shake { session.world.screen_shake_amount } else { 0. };
         let screen_shake = Vec2::new(
-            rand::gen_range(-session.world.screen_shake_amount, session.world.screen_shake_amount),
-            rand::gen_range(-session.world.screen_shake_amount, session.world.screen_shake_amount),
+            rand::gen_range(-shake_amount, shake_amount),
+            rand::gen_range(-shake_amount, shake_amount),
         );
         let camera_focal_y = session.player.pos_y;
         let camera_focal_x = session.player.pos_x;
-        set_camera(&Camera2D {
+        let camera = Camera2D {
             target: vec2(lerp(camera_focal_x + 4., camera_focal_x - 4., get_frame_time()), lerp(camera_focal_y + 4., camera_focal_y - 4., get_frame_time())) + screen_shake,
             zoom: Vec2::new(
                 camera_zoom / 640. * 2., 
                 -camera_zoom / 640. * 2.
             ),
             ..Default::default()
-        });
+        };
+        set_camera(&camera);
+
+        // Nothing moves while paused, the game clock only runs in steps
+        let mut just_paused = false;
+        if matches!(level_state, LevelState::InGame) && session.player.active && (actions.pressed(Action::Pause) || delta > LONG_FRAME_SECS) {
+            session.renderer.pause_index = 0;
+            session.renderer.pause_options_open = false;
+            level_state = LevelState::Paused;
+            // The key that paused isn't also the one that resumes
+            just_paused = true;
+        }
 
         match level_state {
             LevelState::PreGame => {
                 clear_background(Color::from_hex(0x252129));
                 set_default_camera();
 
-                draw_particles(&mut session.world.intro_particles);
+                draw_particles(&session.world.intro_particles, session.player_texture, clock::real_now());
                 draw_texture_ex(session.main_title_texture, screen_width()/2.-180., 100. + session.renderer.main_title_tweener.move_by(delta), WHITE, 
                     DrawTextureParams { 
                         dest_size: Some(vec2(36. * 10., 22. * 10.)), 
@@ -656,272 +722,97 @@ async fn main() {
                     &mut session.world.intro_particles, 
                     screen_width()/4., 
                     0.,
-                    Box::new(IntroParticle{})
+                    Box::new(IntroParticle{}),
+                    &mut session.world.rng.fx,
+                    clock::real_now()
                 );
-                update_particles(&mut session.world.intro_particles);
+                update_particles(&mut session.world.intro_particles, delta, clock::real_now());
                                 
-                if is_key_pressed(KeyCode::Z) {
+                if can_continue {
+                    let prompt = format!("press {} to continue", session.controls.map.label(Action::Continue));
+                    let width = measure_text(&prompt, Some(session.font), 64, 1.).width;
+                    draw_text_ex(
+                        &prompt,
+                        (screen_width() - width) / 2., 
+                        screen_height() - 60., 
+                        TextParams { font: session.font, font_size: 64, font_scale: 1., font_scale_aspect: 1., ..Default::default()}
+                    );
+                }
+
+                if actions.pressed(Action::Confirm) {
                     // restart the "game state"
+                    save::delete_save();
+                    can_continue = false;
                     session.reset();
                     level_state = LevelState::InGame;
                 }
+                if can_continue && actions.pressed(Action::Continue) {
+                    can_continue = false;
+                    if session.continue_run() {
+                        level_state = LevelState::InGame;
+                    }
+                }
                 // tween to start
             }
             LevelState::InGame => {
-                session.world.stopwatch.tick(Duration::from_secs_f32(0.01));
                 session.renderer.choosen_upgrade_index = 0;
-                for x in 0..160 {
-                    for y in 0..100 {
-                        draw_map_cell(session.main_texture, x, y);
-                    }
-                }
-                
-                // Update block
-                if session.player.active {
-                    // Move and Dashing input block
-                    if is_key_pressed(KeyCode::X) && !session.player.is_dashing {
-                        session.player.direction = direction::get_direction();
-                        if let Some(_dir) = session.player.direction {
-                            session.player.dashing_timer.restart();
-                            session.player.is_dashing = true;
-                        }
-                    }
-                    if !session.player.is_dashing {
-                        move_player(&mut session.player, delta);
-                    }
-                    update_enemies_position(&mut session.world.enemies, &mut session.player.pos_x, &mut session.player.pos_y);
-                    update_enemies_pushing(&mut session.world.enemies);
-                    update_enemies_colliding(&mut session.world.enemies, &mut session.player.pos_x, &mut session.player.pos_y, &mut session.player.hp, &session.player.is_dashing, &mut session.player.inv_timer, &mut session.world.screen_shake_amount);
-                    update_bat_enemies_position(&mut session.world.bat_enemies);
-                    update_bat_enemies_colliding(
-                        &mut session.world.bat_enemies, 
-                        &mut session.player.pos_x, 
-                        &mut session.player.pos_y, 
-                        &mut session.player.hp, 
-                        &mut session.player.inv_timer, 
-                        &mut session.world.screen_shake_amount, 
-                        &mut session.world.damage_popups,
-                        &session.player.is_dashing
-                    );
-                    update_tower_enemies(&mut session.world.tower_enemies, &session.player.pos_x, &session.player.pos_y, &mut session.world.enemy_bullets);
-                    update_bullets(&mut session.world.bullets, &mut session.world.particles);
-                    update_enemy_bullets(&mut session.world.enemy_bullets, &mut session.world.particles, delta);
-                }
-                update_dead_enemies(&mut session.world.dead_enemies, &mut session.player.pos_x);
-                update_particles(&mut session.world.particles);
-                
-                if session.player.is_dashing {        
-                    // Calculate the dash distance based on the dash speed and delta time
-                    let dash_distance = session.player.dash_speed * delta;
-        
-                    // Update player position based on dash direction
-                    // 0.7071 was pure experimentation
-                    match session.player.direction {
-                        Some(direction::Direction::Up) => session.player.pos_y -= dash_distance,
-                        Some(direction::Direction::Down) => session.player.pos_y += dash_distance,
-                        Some(direction::Direction::Left) => session.player.pos_x -= dash_distance,
-                        Some(direction::Direction::Right) => session.player.pos_x += dash_distance,
-                        Some(direction::Direction::UpLeft) => {
-                            session.player.pos_x -= dash_distance * 0.7071;
-                            session.player.pos_y -= dash_distance * 0.7071;
-                        }
-                        Some(direction::Direction::UpRight) => {
-                            session.player.pos_x += dash_distance * 0.7071;
-                            session.player.pos_y -= dash_distance * 0.7071;
-                        }
-                        Some(direction::Direction::DownLeft) => {
-                            session.player.pos_x -= dash_distance * 0.7071;
-                            session.player.pos_y += dash_distance * 0.7071;
-                        }
-                        Some(direction::Direction::DownRight) => {
-                            session.player.pos_x += dash_distance * 0.7071;
-                            session.player.pos_y += dash_distance * 0.7071;
-                        }
-                        None => {}
-                    }
-
-                    spawn_particle(
-                        &mut session.world.particles, 
-                        session.player.pos_x, 
-                        session.player.pos_y,
-                        Box::new(PlayerDashParticle{ texture: session.player_texture})
-                    );
 
-                    if session.player.dashing_timer.finished() {
-                        session.player.is_dashing = false;
+                let input = match session.bot.as_ref() {
+                    Some(bot) => bot.input(&session.world, &session.player),
+                    None => {
+                        let cursor = camera.screen_to_world(mouse_position().into())
+                            - vec2(session.player.pos_x + 2., session.player.pos_y + 2.);
+                        InputState::from_actions(&actions, session.controls.map.dash_aim, Some(cursor))
                     }
+                };
+                if let Some(recorder) = session.recorder.as_mut() {
+                    recorder.record_tick(delta, &input);
                 }
-
-                let player_frame = session.renderer.anims.get_mut("idle").unwrap().get_animation_source(Duration::from_secs_f32(get_frame_time()));
-                
-                // Draw block
-                draw_particles(&mut session.world.particles);
-                // player.draw(player_texture, frame);
-                draw_player(
-                    session.player_texture,
-                    player_frame, 
-                    &session.player
-                );
-                draw_enemies(
-                    session.slime_texture, 
-                    &mut session.world.enemies, 
-                    &mut session.player.pos_x, 
-                    &mut session.player.pos_y
-                );
-                draw_tower_enemies(session.slime_texture, &mut session.world.tower_enemies);
-                draw_bat_enemies(
-                    session.slime_texture,
-                    &mut session.world.bat_enemies
-                );
-                draw_dead_enemies(session.slime_texture, &mut session.world.dead_enemies, &mut session.player.pos_x, &mut session.player.pos_y);
-                draw_bullets(session.main_texture, &mut session.world.bullets);
-                draw_enemy_bullets(session.main_texture, &mut session.world.enemy_bullets);
-
-                // draw_player_collider(&mut session.player.pos_x, &mut session.player.pos_y);
-                // draw_enemies_collider(&mut session.world.enemies);
-
-                for popup in session.world.damage_popups.iter_mut() {
-                    popup.update();
-                    popup.draw(session.font);
+                let outcome = session.world.step(&mut session.player, delta, &input);
+                if let Some(telemetry) = session.telemetry.as_mut() {
+                    telemetry.record_step(&session.world, &session.player);
                 }
-
-                // Spawning enemies
-                // Count slimes
-                if session.player.active {
-                    if session.world.enemies.len() < (5*(session.world.progression as usize)) {
-                        let mut given_xp = session.world.base_given_xp - (0.1 * (session.world.base_given_xp)) - (0.5 * (session.world.kill_count as f32)) - session.world.progression*4.;
-                        if given_xp < 3. { given_xp = 3. }
-                        // println!("{} given_xp", given_xp);                    
-                        spawn_enemies(&mut session.world.enemies, &session.player.pos_x, &session.player.pos_y, given_xp);
-                    }
-
-                    // Count Bats
-                    if session.world.bat_enemies.len() < (2*(session.world.progression as usize)) {
-                        let x_dir: f32;
-                        match rand::gen_range(0, 2) {
-                            0 => x_dir = -1.,
-                            _ => x_dir = 1.,
-                        }
-                        let spawn_pos_y = session.player.pos_y * (rand::gen_range(0.5, 2.));
-                        let mut given_xp = session.world.base_given_xp - (0.1 * (session.world.base_given_xp)) - (0.5 * (session.world.kill_count as f32)) - session.world.progression*4.;
-                        if given_xp < 3. { given_xp = 3. }
-                        println!("{} given_xp", given_xp);
-                        session.world.bat_enemies.push(
-                            BatEnemy::new(session.player.pos_x - 64. * (x_dir), spawn_pos_y, x_dir, given_xp)
-                        );
-                    }
-                    // And towers
-                    if session.world.progression >= 3. {
-                        if session.world.tower_enemies.len() < (2*session.world.progression as usize) {
-                            // Random around radius
-                            // let angle = rand::gen_range(0.0, std::f32::consts::TAU); // Random angle in radians
-                            // let distance = rand::gen_range(20.,100.); // Random distance within the spawn radius
-                        
-                            // let spawn_x = session.player.pos_x + distance * angle.cos();
-                            // let spawn_y = session.player.pos_y + distance * angle.sin();
-                        
-                            // Random around circ 
-                            let angle = rand::gen_range(0.0,std::f32::consts::TAU); // Random angle in radians
-        
-                            let spawn_x = session.player.pos_x + 32. * angle.cos();
-                            let spawn_y = session.player.pos_y + 32. * angle.sin();
-        
-                            session.world.tower_enemies.push(
-                                TowerEnemy::new(spawn_x, spawn_y)
-                            );
-                        }
+                if outcome == StepOutcome::LevelUp {
+                    if let Some(offered) = session.next_level_up() {
+                        upgrades = offered;
+                        level_state = LevelState::LevelUp;
                     }
                 }
 
-                if session.player.regen_timer.finished() && session.player.active {
-                    if session.player.hp + session.player.regen >= session.player.max_hp {
-                        session.player.hp = session.player.max_hp
-                    } else {
-                        session.player.hp += session.player.regen;
-                    }
-                    session.player.regen_timer.restart();
-                }
- 
-                damage_enemy(&mut session.world.bullets, &mut session.world.enemies, &mut session.world.damage_popups, &mut session.world.screen_shake_amount, &session.player.damage);
-                bullet_damage_player(&mut session.world.enemy_bullets, &session.player.pos_x, &session.player.pos_y, &mut session.player.hp, &mut session.world.damage_popups, &mut session.world.screen_shake_amount, &mut session.player.inv_timer, &session.player.is_dashing);
-                kill_enemies(&mut session.world.enemies, &mut session.player.xp, &mut session.world.dead_enemies, &mut session.world.kill_count, &mut session.world.progression, &mut session.world.base_given_xp);
-                kill_bat_enemies(&mut session.world.bat_enemies, &mut session.player.xp, &mut session.world.dead_enemies, &mut session.world.kill_count, &mut session.world.progression, &mut session.world.base_given_xp);
-                clean_bat_enemies(&mut session.world.bat_enemies, &session.player.pos_x, &session.player.pos_y);
-
-                if session.player.xp >= session.player.max_xp {
-                    upgrades = pick_random_upgrades();
-                    level_up_player(&mut session.player.xp, &mut session.player.max_xp, &mut session.player.level, &mut level_state);
-                }
-                
-                if session.world.bullet_cooldown.finished() {
-                    spawn_bullet(&mut session.world.bullets, &mut session.world.enemies, &mut session.player.pos_x, &mut session.player.pos_y);
-                    session.world.bullet_cooldown.set_duration_millis(((3000 as f32) * session.world.current_bullet_cooldown_bonus) as u64);
-                    session.world.bullet_cooldown.restart();
-                }
-
-                // Get rid of things that shouldn't be around anymore
-                // Bullets, enemies, particles, pop-ups
-                session.world.bullets.retain(|b| b.active);
-                session.world.enemy_bullets.retain(|b| b.active);
-                session.world.enemies.retain(|e| e.alive);
-                session.world.bat_enemies.retain(|e| e.active);
-                session.world.tower_enemies.retain(|e| e.active);
-                session.world.dead_enemies.retain(|e| e.active);
-                session.world.damage_popups.retain(|e| e.active);
-                session.world.particles.retain(|p| p.active);        
+                draw_in_game(&mut session);
 
                 set_default_camera();
 
-                let current_player_hp_percentage = (session.player.hp / session.player.max_hp) * 100.;
+                let current_player_hp_percentage = (session.player.hp / session.player.max_hp()) * 100.;
                 let current_player_xp_percentage = (session.player.xp / session.player.max_xp) * 100.;
                 draw_level_ui(session.ui_texture, &current_player_hp_percentage, &current_player_xp_percentage, &session.player.level, &session.player.inv_timer);
                 draw_level_timer_ui(
                     session.font, 
-                    get_minutes_from_millis(session.world.stopwatch.elapsed().as_millis()), 
-                    get_seconds_from_millis(session.world.stopwatch.elapsed().as_millis())
+                    get_minutes_from_millis(session.world.elapsed_millis()), 
+                    get_seconds_from_millis(session.world.elapsed_millis())
                 );
-
-                if session.player.hp <= 0. {
+                draw_gold_ui(session.font, session.world.stats.gold);
+
+                if !session.player.active {
+                    // Failed runs get reported too, so they show their seed
+                    draw_text_ex(
+                        &format!("seed {}", session.world.seed()),
+                        (screen_width() / 2.) - 300.,
+                        580.,
+                        TextParams { font: session.font, font_size: 32, font_scale: 1., font_scale_aspect: 1., ..Default::default()}
+                    );
                     session.renderer.death_tweener.move_by(delta);
-                    session.player.hp = 0.;
-                    session.player.active = false;
-                    session.world.stopwatch.pause();
-
-                    session.world.enemies = Vec::new();
-                    session.world.bat_enemies = Vec::new();
-                    session.world.bullets = Vec::new();
-                    session.world.dead_enemies = Vec::new();
-                    session.world.damage_popups = Vec::new();
-                    session.world.particles = Vec::new();
-
-                    session.player.pos_x = -999.;
-                    session.player.pos_y = -999.;
-
-
-                    session.world.screen_shake_amount += 0.5 * 1.1;
-
                     if session.renderer.death_tweener.is_finished() {
+                        session.save_replay();
+                        session.finish_telemetry();
+                        save::delete_save();
                         level_state = LevelState::PreGame;
                     }
                 }
 
                 // Trigger end game progression
-                if session.world.stopwatch.elapsed().as_millis() > 240000 && session.player.active {
-                    // destroy all entities 
-                    // but the player
-                    // - deallocates but not sure if its good
-                    session.world.enemies = Vec::new();
-                    session.world.bat_enemies = Vec::new();
-                    session.world.bullets = Vec::new();
-                    session.world.dead_enemies = Vec::new();
-                    session.world.damage_popups = Vec::new();
-                    session.world.particles = Vec::new();
-
-                    if session.world.stopwatch.elapsed().as_millis() < 246000 {
-                        session.world.screen_shake_amount += 0.5;
-                    }
-
-                    if session.world.stopwatch.elapsed().as_millis() > 248000 {
+                if session.world.stage_cleared() && session.player.active {
+                    if session.world.elapsed_millis() > 248000 {
                         draw_rectangle(
                             0., 
                             0., 
@@ -930,11 +821,77 @@ async fn main() {
                             Color::from_rgba(37, 33, 41, 255)
                         );
                         if session.renderer.tweener.is_finished() {
+                            session.save_replay();
+                            session.finish_telemetry();
+                            save::delete_save();
                             level_state = LevelState::StageCleared
                         }
                     }
                 }
             },
+            LevelState::Paused => {
+                draw_in_game(&mut session);
+
+                set_default_camera();
+
+                let current_player_hp_percentage = (session.player.hp / session.player.max_hp()) * 100.;
+                let current_player_xp_percentage = (session.player.xp / session.player.max_xp) * 100.;
+                draw_level_ui(session.ui_texture, &current_player_hp_percentage, &current_player_xp_percentage, &session.player.level, &session.player.inv_timer);
+                draw_level_timer_ui(
+                    session.font, 
+                    get_minutes_from_millis(session.world.elapsed_millis()), 
+                    get_seconds_from_millis(session.world.elapsed_millis())
+                );
+                draw_gold_ui(session.font, session.world.stats.gold);
+
+                let renderer = &mut session.renderer;
+                if just_paused {
+                    draw_pause_menu(session.font, "paused", &PAUSE_ITEMS.map(String::from), renderer.pause_index);
+                } else if renderer.pause_options_open {
+                    let items = vec![
+                        format!("Screen shake {}", if renderer.screen_shake { "on" } else { "off" }),
+                        "Back".to_string(),
+                    ];
+                    draw_pause_menu(session.font, "options", &items, renderer.pause_index);
+                    match menu_input(&actions, &mut renderer.pause_index, items.len()) {
+                        Some(0) => renderer.screen_shake = !renderer.screen_shake,
+                        Some(_) => {
+                            renderer.pause_options_open = false;
+                            renderer.pause_index = 2;
+                        }
+                        None if actions.pressed(Action::Back) || actions.pressed(Action::Pause) => {
+                            renderer.pause_options_open = false;
+                            renderer.pause_index = 2;
+                        }
+                        None => {}
+                    }
+                } else {
+                    let items = PAUSE_ITEMS.map(String::from);
+                    draw_pause_menu(session.font, "paused", &items, renderer.pause_index);
+                    match menu_input(&actions, &mut renderer.pause_index, items.len()) {
+                        Some(0) => level_state = LevelState::InGame,
+                        Some(1) => {
+                            save::delete_save();
+                            can_continue = false;
+                            session.reset();
+                            level_state = LevelState::InGame;
+                        }
+                        Some(2) => {
+                            renderer.pause_options_open = true;
+                            renderer.pause_index = 0;
+                        }
+                        Some(_) => {
+                            // Same as closing the window, it can be continued
+                            session.save_run();
+                            session.finish_telemetry();
+                            can_continue = true;
+                            level_state = LevelState::PreGame;
+                        }
+                        None if actions.pressed(Action::Pause) => level_state = LevelState::InGame,
+                        None => {}
+                    }
+                }
+            }
             LevelState::StageCleared => {
                 clear_background(Color::from_rgba(37, 33, 41, 255));
                 // Thank player
@@ -971,6 +928,13 @@ async fn main() {
                     TextParams { font: session.font, font_size: 64, font_scale: 1., font_scale_aspect: 1., ..Default::default()}
                 );                
 
+                draw_text_ex(
+                    &format!("seed {}", session.world.seed()),
+                    (screen_width() / 2.) - 300., 
+                    580., 
+                    TextParams { font: session.font, font_size: 32, font_scale: 1., font_scale_aspect: 1., ..Default::default()}
+                );
+
                 draw_text_ex(
                     "Press Z to restart",
                     (screen_width() / 2.) - 300., 
@@ -978,78 +942,124 @@ async fn main() {
                     TextParams { font: session.font, font_size: 64, font_scale: 1., font_scale_aspect: 1., ..Default::default()}
                 );  
 
-                if is_key_pressed(KeyCode::Z) {
+                if actions.pressed(Action::Confirm) {
                     level_state = LevelState::PreGame;
                 }
             }
+            LevelState::Replay => {
+                if let Some(replay_player) = session.replay_player.as_mut() {
+                    if let Some(newstate) = replay_input(&actions, replay_player, &mut session.player, &mut session.world) {
+                        level_state = newstate;
+                    }
+                    replay_player.update(delta, &mut session.player, &mut session.world);
+                }
+
+                draw_in_game(&mut session);
+
+                set_default_camera();
+
+                let current_player_hp_percentage = (session.player.hp / session.player.max_hp()) * 100.;
+                let current_player_xp_percentage = (session.player.xp / session.player.max_xp) * 100.;
+                draw_level_ui(session.ui_texture, &current_player_hp_percentage, &current_player_xp_percentage, &session.player.level, &session.player.inv_timer);
+                draw_level_timer_ui(
+                    session.font, 
+                    get_minutes_from_millis(session.world.elapsed_millis()), 
+                    get_seconds_from_millis(session.world.elapsed_millis())
+                );
+                draw_gold_ui(session.font, session.world.stats.gold);
+
+                if let Some(replay_player) = session.replay_player.as_ref() {
+                    let status = if replay_player.paused {
+                        "paused".to_string()
+                    } else if replay_player.finished() {
+                        "end".to_string()
+                    } else {
+                        format!("{}x", replay_player.speed())
+                    };
+                    draw_text_ex(
+                        &format!("replay {}", status),
+                        10., 
+                        100., 
+                        TextParams { font: session.font, font_size: 32, font_scale: 1., font_scale_aspect: 1., ..Default::default()}
+                    );
+                }
+            }
             LevelState::LevelUp => {
                 for x in 0..80 {
                     for y in 0..50 {
                         draw_map_cell(session.main_texture, x, y);
                     }
                 }
-        
-                session.world.stopwatch.pause();
+
                 let frame = session.renderer.anims.get_mut("idle").unwrap().get_animation_source(Duration::from_secs_f32(get_frame_time()));
-                draw_player(session.player_texture, frame, &session.player);
+                draw_player(session.player_texture, frame, &session.player, session.world.clock.now());
                 // draw_player_collider(&mut session.player.pos_x, &mut session.player.pos_y);
                 draw_enemies(session.slime_texture, &mut session.world.enemies, &mut session.player.pos_x, &mut session.player.pos_y);
-                draw_enemies_collider(&mut session.world.enemies);
-                draw_bullets(session.main_texture, &mut session.world.bullets);
+                draw_enemies_collider(&session.world.enemies);
+                draw_bullets(session.main_texture, &session.world.bullets);
 
                 // In-level UI
                 draw_rectangle(0., screen_height() - 80., screen_width(), 120., BLACK);
                 set_default_camera();
 
-                choose_upgrade_input(&mut session.renderer.choosen_upgrade_index, &mut session.renderer.upgrade_menu_tween);
-                let result = level_up_input();
-                if let Some(newstate) = result {
-                    // fine tune!
-                    let idx = session.renderer.choosen_upgrade_index as usize;
-                    let upg = upgrades[idx].get_name();
-                    match upg {
-                        "Speed" => {
-                            // println!("Speed upgrade");
-                            session.player.speed_bonus += 0.1;
-                        }
-                        "FireRate" => {
-                            session.world.current_bullet_cooldown_bonus -= 0.1;
-                            // println!("FireRate upgrade");
-                        }
-                        "Recovery" => {
-                            session.player.regen += 2.;
-                            // println!("Recovery upgrade");
+                let choice = match session.bot.as_ref() {
+                    Some(bot) => Some(LevelUpChoice::Pick(bot.choose_upgrade(&upgrades))),
+                    None => {
+                        choose_upgrade_input(&actions, &mut session.renderer.choosen_upgrade_index, &mut session.renderer.upgrade_menu_tween, upgrades.len());
+                        let index = session.renderer.choosen_upgrade_index as usize;
+                        level_up_input(&actions, index, upgrades.len(), session.world.banishes_left(), &mut session.renderer.banishing)
+                    }
+                };
+                if let Some(choice) = choice {
+                    if let (LevelUpChoice::Pick(idx), Some(telemetry)) = (choice, session.telemetry.as_mut()) {
+                        telemetry.record_upgrade_chosen(&session.world, upgrades[idx].get_name());
+                    }
+                    if let Some(recorder) = session.recorder.as_mut() {
+                        recorder.record_level_up_choice(choice);
+                    }
+                    let cards = upgrades.len();
+                    let redraws = session.world.rerolls_used + session.world.banishes_used;
+                    if session.world.resolve_level_up(&mut session.player, &mut upgrades, choice) {
+                        // Don't lose a long run to a crash
+                        session.save_run();
+                        // One screen for every level gained at once
+                        match session.next_level_up() {
+                            Some(offered) => {
+                                upgrades = offered;
+                                session.renderer.choosen_upgrade_index = 0;
+                            }
+                            None => {
+                                level_state = LevelState::InGame;
+                            }
                         }
-                        "FasterRecovery" => {
-                            let dur = session.player.regen_timer.duration;
-                            let dur_5_percent = (session.player.regen_timer.duration.as_millis() as f32) * 0.05;
-                            // println!("recovery {} new recovery {}, 5percent {}", dur.as_millis(), (dur.as_millis() - (dur_5_percent.round() as u128)), dur_5_percent);
-                            session.player.regen_timer.set_duration_millis((dur.as_millis() - (dur_5_percent.round() as u128)) as u64);
+                    } else if session.world.rerolls_used + session.world.banishes_used != redraws {
+                        if let Some(telemetry) = session.telemetry.as_mut() {
+                            let names: Vec<&str> = upgrades.iter().map(|u| u.get_name()).collect();
+                            telemetry.record_upgrades_offered(&session.world, &names);
                         }
-                        "Dash" => {
-                            let dash_5_percent = session.player.dash_speed * 0.05;
-                            session.player.dash_speed += dash_5_percent;
-                            // println!("{}", dash_speed);
-                        },
-                        "MoreIframes" => {
-                            let dur = session.player.inv_timer.duration;
-                            let extra = (session.player.inv_timer.duration.as_millis() as f32) * 0.1;
-                            // println!("recovery {} new recovery {}, 5percent {}", dur.as_millis(), (dur.as_millis() + (extra.round() as u128)), extra);
-                            session.player.inv_timer.set_duration_millis((dur.as_millis() + (extra.round() as u128)) as u64);
+                        // Stay on the same button when there are fewer cards
+                        let index = session.renderer.choosen_upgrade_index as usize;
+                        if index >= cards {
+                            session.renderer.choosen_upgrade_index = (upgrades.len() + index - cards) as i32;
                         }
-                        _ => {}
                     }
-                    session.world.stopwatch.unpause();
-                    level_state = newstate;
                 }
 
-                let current_player_hp_percentage = (session.player.hp / session.player.max_hp) * 100.;
+                let current_player_hp_percentage = (session.player.hp / session.player.max_hp()) * 100.;
                 let current_player_xp_percentage = (session.player.xp / session.player.max_xp) * 100.;
                 draw_level_ui(session.ui_texture, &current_player_hp_percentage, &current_player_xp_percentage, &session.player.level, &session.player.inv_timer);
+                let options = LevelUpOptions {
+                    rerolls_left: session.world.rerolls_left(),
+                    banishes_left: session.world.banishes_left(),
+                    skip: session.world.level_rules.skip.label(),
+                    banishing: session.renderer.banishing,
+                };
                 draw_level_up(
                     &session.renderer.choosen_upgrade_index, 
                     &upgrades, 
+                    &options,
                     session.font, 
+                    session.upgrade_texture,
                     &mut session.renderer.upgrade_menu_tween, 
                     &mut session.renderer.init_upgrade_tweener
                 );
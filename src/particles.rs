@@ -1,6 +1,7 @@
 use macroquad::{prelude::*};
 
 use crate::timer::Timer;
+use crate::rng::Rng;
 
 pub struct Particle {
     lifetime: Timer,
@@ -12,28 +13,29 @@ pub struct Particle {
     color_end: Color,
     x: f32,
     y: f32,
-    texture: Option<Texture2D>,
+    textured: bool,
     pub active: bool
 }
 
 pub trait ParticleType {
-    fn new(&self, x: f32, y: f32) -> Particle;
+    fn new(&self, x: f32, y: f32, rng: &mut Rng, now: f64) -> Particle;
 }
 
-impl Default for Particle {
-    fn default() -> Self {
+impl Particle {
+    /// Particles are purely cosmetic, `rng` should be the run's fx stream
+    fn base(rng: &mut Rng, now: f64) -> Self {
         Particle { 
-            lifetime: Timer::new(500), 
+            lifetime: Timer::new(500, now), 
             velocity_start: vec2(5., 0.), 
             velocity_end: vec2(5., 0.),
-            size_start: 1.5 + (rand::gen_range(0.5, 1.) - 0.5), 
-            size_end: 0.2 + (rand::gen_range(0.5, 1.) - 0.5), 
+            size_start: 1.5 + (rng.gen_range_f32(0.5, 1.) - 0.5), 
+            size_end: 0.2 + (rng.gen_range_f32(0.5, 1.) - 0.5), 
             // color_start: Color::new(1., 0.35, 0.0, 1.),
             color_start: Color::from_rgba(251, 185, 84, 255), 
             color_end: Color::new(0.2, 0.2, 0.2, 0.1), 
             x: 50., 
             y: 50.,
-            texture: None,
+            textured: false,
             active: true
         }
     }
@@ -42,12 +44,10 @@ impl Default for Particle {
 pub struct ShotParticle {}
 
 impl ParticleType for ShotParticle {
-    fn new(&self, x: f32, y: f32) -> Particle {
-        let mut particle = Particle {
-            ..Default::default()
-        };
-        particle.x = x + (rand::gen_range(0.5, 1.) - 0.5) * 2.;
-        particle.y = y + (rand::gen_range(0.5, 1.) - 0.5) * 2.;
+    fn new(&self, x: f32, y: f32, rng: &mut Rng, now: f64) -> Particle {
+        let mut particle = Particle::base(rng, now);
+        particle.x = x + (rng.gen_range_f32(0.5, 1.) - 0.5) * 2.;
+        particle.y = y + (rng.gen_range_f32(0.5, 1.) - 0.5) * 2.;
 
         particle        
     }
@@ -56,35 +56,29 @@ impl ParticleType for ShotParticle {
 pub struct EnemyShotParticle {}
 
 impl ParticleType for EnemyShotParticle {
-    fn new(&self, x: f32, y: f32) -> Particle {
-        let mut particle = Particle {
-            ..Default::default()
-        };
+    fn new(&self, x: f32, y: f32, rng: &mut Rng, now: f64) -> Particle {
+        let mut particle = Particle::base(rng, now);
         particle.color_start = Color::from_rgba(168, 132, 243, 255);
         particle.color_end = Color::new(0.2, 0.2, 0.2, 0.1); 
-        particle.x = x + (rand::gen_range(0.5, 1.) - 0.5) * 2.;
-        particle.y = y + (rand::gen_range(0.5, 1.) - 0.5) * 2.;
+        particle.x = x + (rng.gen_range_f32(0.5, 1.) - 0.5) * 2.;
+        particle.y = y + (rng.gen_range_f32(0.5, 1.) - 0.5) * 2.;
 
         particle        
     }
 }
 
-pub struct PlayerDashParticle {
-    pub texture: Texture2D
-}
+pub struct PlayerDashParticle {}
 
 impl ParticleType for PlayerDashParticle {
-    fn new(&self, x: f32, y: f32) -> Particle {
-        let mut particle = Particle {
-            ..Default::default()
-        };
+    fn new(&self, x: f32, y: f32, rng: &mut Rng, now: f64) -> Particle {
+        let mut particle = Particle::base(rng, now);
         particle.velocity_start = vec2(1., 0.); 
         particle.velocity_end = vec2(1., 0.);
         particle.color_start = Color::new(1., 1., 1., 0.3); 
         particle.color_end = Color::new(0.2, 0.2, 0.2, 0.1); 
-        particle.x = x + (rand::gen_range(0.5, 1.) - 0.5) * 0.1;
-        particle.y = y + (rand::gen_range(0.5, 1.) - 0.5) * 0.5;
-        particle.texture = Some(self.texture);
+        particle.x = x + (rng.gen_range_f32(0.5, 1.) - 0.5) * 0.1;
+        particle.y = y + (rng.gen_range_f32(0.5, 1.) - 0.5) * 0.5;
+        particle.textured = true;
 
         particle        
     }
@@ -93,37 +87,34 @@ impl ParticleType for PlayerDashParticle {
 pub struct IntroParticle {}
 
 impl ParticleType for IntroParticle {
-    fn new(&self, x: f32, y: f32) -> Particle {
-        let mut particle = Particle {
-            ..Default::default()
-        };
-        particle.lifetime = Timer::new(10000);
+    fn new(&self, x: f32, y: f32, rng: &mut Rng, now: f64) -> Particle {
+        let mut particle = Particle::base(rng, now);
+        particle.lifetime = Timer::new(10000, now);
         particle.velocity_start = vec2(1., 5.); 
         particle.velocity_end = vec2(1., 5.);
         particle.color_start = Color::new(1., 1., 1., 0.5); 
         particle.color_end = Color::new(0.2, 0.2, 0.2, 0.1);
-        particle.size_start = 1.5 + (rand::gen_range(0.5, 1.) - 0.5) * 3.0; 
-        particle.size_end = 0.2 + (rand::gen_range(0.5, 1.) - 0.5) * 3.0;
-        particle.x = x + (rand::gen_range(0.5, 1.) - 0.5) * 0.1 * 5000.;
-        particle.y = y + (rand::gen_range(0.5, 1.) - 0.5) * 0.5 * 5000.;
+        particle.size_start = 1.5 + (rng.gen_range_f32(0.5, 1.) - 0.5) * 3.0; 
+        particle.size_end = 0.2 + (rng.gen_range_f32(0.5, 1.) - 0.5) * 3.0;
+        particle.x = x + (rng.gen_range_f32(0.5, 1.) - 0.5) * 0.1 * 5000.;
+        particle.y = y + (rng.gen_range_f32(0.5, 1.) - 0.5) * 0.5 * 5000.;
 
         particle        
     }
 }
 
-pub fn spawn_particle(particles: &mut Vec<Particle>, x: f32, y: f32, particle_type: Box<dyn ParticleType>) {
-    let p = particle_type.new(x, y);
+pub fn spawn_particle(particles: &mut Vec<Particle>, x: f32, y: f32, particle_type: Box<dyn ParticleType>, rng: &mut Rng, now: f64) {
+    let p = particle_type.new(x, y, rng, now);
     particles.push(p);
 }
 
-pub fn update_particles(particles: &mut Vec<Particle>) {
-    let delta = get_frame_time();
+pub fn update_particles(particles: &mut [Particle], delta: f32, now: f64) {
     for particle in particles.iter_mut() {
         if particle.active {
-            if particle.lifetime.finished() {
+            if particle.lifetime.finished(now) {
                 particle.active = false;
             }
-            let elapsed = particle.lifetime.elapsed().as_secs_f32() / particle.lifetime.duration.as_secs_f32();
+            let elapsed = particle.lifetime.elapsed(now).as_secs_f32() / particle.lifetime.duration.as_secs_f32();
             let velocity = lerp_vec2(particle.velocity_start, particle.velocity_end, elapsed);
             particle.x += velocity.extend(0.0).x * delta;
             particle.y += velocity.extend(0.0).y * delta;
@@ -131,11 +122,11 @@ pub fn update_particles(particles: &mut Vec<Particle>) {
     }
 }
 
-pub fn draw_particles(particles: &mut Vec<Particle>) {
+pub fn draw_particles(particles: &[Particle], texture: Texture2D, now: f64) {
     for particle in particles.iter() {
         if particle.active {
-            let elapsed = particle.lifetime.elapsed().as_secs_f32() / particle.lifetime.duration.as_secs_f32();
-            if let Some(texture) = particle.texture {
+            let elapsed = particle.lifetime.elapsed(now).as_secs_f32() / particle.lifetime.duration.as_secs_f32();
+            if particle.textured {
                 let color = lerp_color(particle.color_start, particle.color_end, elapsed);
                 draw_texture_ex(
                     texture, 
@@ -0,0 +1,263 @@
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::events::{EventQueue, GameEvent};
+use crate::player::Player;
+use crate::player_stats::Stat;
+
+/// Past this many gems on the ground, the farthest ones from the player
+/// merge into one
+pub const MAX_GEMS: usize = 200;
+/// Close enough to the player's center to be collected
+const COLLECT_RADIUS: f32 = 3.;
+/// Pixels a second a gem starts flying at once attracted, and how much
+/// faster it gets every second after
+const FLY_SPEED: f32 = 20.;
+const FLY_ACCELERATION: f32 = 120.;
+/// Gems worth less are small, then medium, then large
+const MEDIUM_GEM_XP: f32 = 10.;
+const LARGE_GEM_XP: f32 = 40.;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum PickupKind {
+    /// An XP gem worth this much
+    Xp(f32),
+    /// Pulls in every gem on the ground
+    Vacuum,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GemTier {
+    Small,
+    Medium,
+    Large,
+}
+
+impl GemTier {
+    pub fn of(xp: f32) -> Self {
+        if xp >= LARGE_GEM_XP {
+            GemTier::Large
+        } else if xp >= MEDIUM_GEM_XP {
+            GemTier::Medium
+        } else {
+            GemTier::Small
+        }
+    }
+}
+
+/// Something lying on the ground for the player to collect
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Pickup {
+    pub x: f32,
+    pub y: f32,
+    pub kind: PickupKind,
+    /// Flying towards the player at this many pixels a second, 0 when still
+    /// lying there
+    pub speed: f32,
+    pub active: bool,
+}
+
+impl Pickup {
+    pub fn new(x: f32, y: f32, kind: PickupKind) -> Self {
+        Pickup { x, y, kind, speed: 0., active: true }
+    }
+
+    pub fn is_gem(&self) -> bool {
+        matches!(self.kind, PickupKind::Xp(_))
+    }
+
+    fn attract(&mut self) {
+        if self.speed == 0. {
+            self.speed = FLY_SPEED;
+        }
+    }
+}
+
+/// Where gems fly to and get measured from
+fn player_center(player: &Player) -> Vec2 {
+    vec2(player.pos_x + 2., player.pos_y + 2.)
+}
+
+/// Pulls in whatever is within the player's pickup radius, moves what is
+/// flying and collects what arrived
+pub fn update_pickups(pickups: &mut Vec<Pickup>, player: &mut Player, events: &mut EventQueue, delta: f32) {
+    let center = player_center(player);
+    let mut vacuum = false;
+    for p in pickups.iter_mut().filter(|p| p.active) {
+        let offset = center - vec2(p.x, p.y);
+        let distance = offset.length();
+        if distance <= player.stat(Stat::PickupRadius) {
+            p.attract();
+        }
+        if p.speed > 0. {
+            let step = (p.speed * delta).min(distance);
+            if let Some(direction) = offset.try_normalize() {
+                p.x += direction.x * step;
+                p.y += direction.y * step;
+            }
+            p.speed += FLY_ACCELERATION * delta;
+        }
+        if center.distance(vec2(p.x, p.y)) <= COLLECT_RADIUS {
+            p.active = false;
+            match p.kind {
+                PickupKind::Xp(xp) => {
+                    player.add_xp(xp);
+                    events.emit(GameEvent::XpCollected { amount: xp });
+                }
+                PickupKind::Vacuum => {
+                    vacuum = true;
+                    events.emit(GameEvent::VacuumCollected);
+                }
+            }
+        }
+    }
+    if vacuum {
+        for p in pickups.iter_mut().filter(|p| p.active && p.is_gem()) {
+            p.attract();
+        }
+    }
+    pickups.retain(|p| p.active);
+}
+
+/// Keeps at most [`MAX_GEMS`] gems around by merging the farthest ones from
+/// `player` into a single gem where the farthest one was
+pub fn merge_gems(pickups: &mut Vec<Pickup>, player: &Player) {
+    let gems = pickups.iter().filter(|p| p.is_gem()).count();
+    if gems <= MAX_GEMS {
+        return;
+    }
+    let center = player_center(player);
+    let distance = |p: &Pickup| center.distance_squared(vec2(p.x, p.y));
+    let mut far: Vec<usize> = (0..pickups.len()).filter(|&i| pickups[i].is_gem() && pickups[i].speed == 0.).collect();
+    far.sort_by(|&a, &b| distance(&pickups[b]).total_cmp(&distance(&pickups[a])));
+    far.truncate(gems - MAX_GEMS + 1);
+    if far.len() < 2 {
+        return;
+    }
+
+    let mut total = 0.;
+    for &i in far.iter() {
+        if let PickupKind::Xp(xp) = pickups[i].kind {
+            total += xp;
+        }
+        pickups[i].active = false;
+    }
+    let farthest = &mut pickups[far[0]];
+    farthest.kind = PickupKind::Xp(total);
+    farthest.active = true;
+    pickups.retain(|p| p.active);
+}
+
+pub fn draw_pickups(pickups: &[Pickup]) {
+    for p in pickups.iter() {
+        match p.kind {
+            PickupKind::Xp(xp) => {
+                let (color, size) = match GemTier::of(xp) {
+                    GemTier::Small => (Color::from_hex(0x4d9be6), 1.5),
+                    GemTier::Medium => (Color::from_hex(0x5daf8d), 2.),
+                    GemTier::Large => (Color::from_hex(0xe83b3b), 2.5),
+                };
+                draw_poly(p.x, p.y, 4, size, 0., color);
+            }
+            PickupKind::Vacuum => {
+                draw_circle(p.x, p.y, 2.5, Color::from_hex(0xfbff86));
+                draw_circle_lines(p.x, p.y, 3.5, 0.5, WHITE);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn xp(pickups: &[Pickup]) -> f32 {
+        pickups.iter().map(|p| match p.kind {
+            PickupKind::Xp(xp) => xp,
+            PickupKind::Vacuum => 0.,
+        }).sum()
+    }
+
+    /// Gems on a spiral around the player, the later ones farther out
+    fn scattered(count: usize, player: &Player) -> Vec<Pickup> {
+        (0..count).map(|i| {
+            let at = vec2(player.pos_x, player.pos_y) + Vec2::from_angle(i as f32) * (20. + i as f32);
+            Pickup::new(at.x, at.y, PickupKind::Xp(1. + (i % 3) as f32))
+        }).collect()
+    }
+
+    /// Steps until nothing is left on the ground, returning how many it took
+    fn collect_all(pickups: &mut Vec<Pickup>, player: &mut Player, events: &mut EventQueue, max_steps: usize) -> Option<usize> {
+        (1..=max_steps).find(|_| {
+            update_pickups(pickups, player, events, 1. / 60.);
+            pickups.is_empty()
+        })
+    }
+
+    #[test]
+    fn merging_keeps_the_xp_and_the_cap() {
+        let player = Player::new();
+        let mut pickups = scattered(MAX_GEMS + 57, &player);
+        pickups.push(Pickup::new(0., 0., PickupKind::Vacuum));
+        let before = xp(&pickups);
+        merge_gems(&mut pickups, &player);
+        assert_eq!(pickups.iter().filter(|p| p.is_gem()).count(), MAX_GEMS);
+        assert_eq!(xp(&pickups), before);
+        assert!(pickups.iter().any(|p| p.kind == PickupKind::Vacuum));
+    }
+
+    #[test]
+    fn flying_gems_never_merge() {
+        let player = Player::new();
+        let mut pickups = scattered(MAX_GEMS + 50, &player);
+        for p in pickups.iter_mut().skip(10) {
+            p.speed = FLY_SPEED;
+        }
+        let flying = pickups[10..].to_vec();
+        let before = xp(&pickups);
+        merge_gems(&mut pickups, &player);
+        assert_eq!(pickups.iter().filter(|p| p.speed == 0.).count(), 1);
+        assert!(flying.iter().all(|f| pickups.contains(f)));
+        assert_eq!(xp(&pickups), before);
+    }
+
+    #[test]
+    fn merging_measures_from_the_players_center() {
+        let player = Player::new();
+        let center = player_center(&player);
+        // The second is farther from the center, the first from the player's
+        // top left corner
+        let mut pickups: Vec<Pickup> = (0..MAX_GEMS - 1).map(|_| Pickup::new(center.x, center.y, PickupKind::Xp(1.))).collect();
+        pickups.push(Pickup::new(center.x + 50., center.y, PickupKind::Xp(1.)));
+        pickups.push(Pickup::new(center.x - 50.5, center.y, PickupKind::Xp(1.)));
+        merge_gems(&mut pickups, &player);
+        assert!(pickups.iter().any(|p| p.kind == PickupKind::Xp(2.) && p.x == center.x - 50.5));
+    }
+
+    #[test]
+    fn gems_in_reach_get_collected() {
+        let mut player = Player::new();
+        let reach = player.stat(Stat::PickupRadius);
+        let center = player_center(&player);
+        let mut events = EventQueue::default();
+        for angle in [0., 1., 2.5, 4.] {
+            let at = center + Vec2::from_angle(angle) * reach * 0.99;
+            let mut pickups = vec![Pickup::new(at.x, at.y, PickupKind::Xp(1.))];
+            assert!(collect_all(&mut pickups, &mut player, &mut events, 120).is_some());
+        }
+        assert_eq!(events.iter().filter(|e| matches!(e, GameEvent::XpCollected { .. })).count(), 4);
+    }
+
+    #[test]
+    fn vacuum_pulls_in_every_gem() {
+        let mut player = Player::new();
+        let center = player_center(&player);
+        let mut pickups = scattered(50, &player);
+        pickups.push(Pickup::new(center.x, center.y, PickupKind::Vacuum));
+        let mut events = EventQueue::default();
+        update_pickups(&mut pickups, &mut player, &mut events, 1. / 60.);
+        assert_eq!(pickups.len(), 50);
+        assert!(pickups.iter().all(|p| p.speed > 0.));
+        assert!(collect_all(&mut pickups, &mut player, &mut events, 600).is_some());
+    }
+}
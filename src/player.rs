@@ -1,69 +1,102 @@
-use macroquad::prelude::*;
-use crate::direction::Direction;
+use serde::{Serialize, Deserialize};
+use crate::player_stats::{Stat, Stats};
 use crate::timer::Timer;
 
 use super::Position;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Player {
     pub pos_x: f32,
     pub pos_y: f32,
     pub hp: f32,
-    pub max_hp: f32,
+    /// XP gained since the last level
     pub xp: f32,
+    /// XP the current level takes, from the world's level curve
     pub max_xp: f32,
     pub level: i32,
+    /// Levels gained that no upgrade was picked for yet
+    #[serde(default)]
+    pub pending_level_ups: u32,
     pub flip_x: bool,
-    pub speed_bonus: f32,
-    pub regen: f32,
+    /// Base values and upgrades, see [`Player::recalculate_stats`]
+    pub stats: Stats,
     pub regen_timer: Timer,
     pub inv_timer: Timer,
     pub is_dashing: bool,
-    pub direction: Option<Direction>,
+    /// Screen-space unit vector the current dash goes along
+    pub dash_dir_x: f32,
+    pub dash_dir_y: f32,
     pub dashing_timer: Timer,
-    pub dash_speed: f32,
     pub active: bool,
-    pub damage: f32,
 }
 
+/// However much armor there is, hits do at least this much of their damage
+const MIN_DAMAGE_TAKEN: f32 = 0.2;
+
 impl Player {
     pub fn new() -> Self {
         Player {
             pos_x: 128.,
             pos_y: 128.,
             hp: 100.,
-            max_hp: 100.,
             xp: 0.,
             max_xp: 100.,
             level: 1,
+            pending_level_ups: 0,
             flip_x: false,
-            speed_bonus: 1.,
-            regen: 1.,
-            regen_timer: Timer::new(5000),
-            inv_timer: Timer::new(1800),
+            stats: Stats::default(),
+            // A new run's clock starts at zero
+            regen_timer: Timer::new(5000, 0.),
+            inv_timer: Timer::new(1800, 0.),
             is_dashing: false,
-            direction: None,
-            dashing_timer: Timer::new(500),
-            dash_speed: 40.0,
+            dash_dir_x: 0.,
+            dash_dir_y: 0.,
+            dashing_timer: Timer::new(500, 0.),
             active: true,
-            damage: 2.,
         }
     }
 
+    pub fn stat(&self, stat: Stat) -> f32 {
+        self.stats.get(stat)
+    }
+
+    pub fn max_hp(&self) -> f32 {
+        self.stat(Stat::MaxHp)
+    }
+
+    /// Brings the stats up to date with their modifiers, along with
+    /// everything that follows from them
+    pub fn recalculate_stats(&mut self) {
+        self.stats.recalculate();
+        self.regen_timer.set_duration_millis(self.stat(Stat::RegenInterval).round() as u64);
+        self.inv_timer.set_duration_millis(self.stat(Stat::IframeDuration).round() as u64);
+        self.hp = self.hp.min(self.max_hp());
+    }
+
+    /// What a hit for `amount` does once armor took its share
+    pub fn armored(&self, amount: f32) -> f32 {
+        (amount - self.stat(Stat::Armor)).max(amount * MIN_DAMAGE_TAKEN)
+    }
+
     pub fn take_damage(&mut self, amount: f32) {
         self.hp = (self.hp - amount).max(0.);
     }
 
     pub fn heal(&mut self, amount: f32) {
-        self.hp = (self.hp + amount).min(self.max_hp);
+        self.hp = (self.hp + amount).min(self.max_hp());
     }
 
     pub fn add_xp(&mut self, amount: f32) {
         self.xp += amount;
     }
 
-    pub fn level_up(&mut self) {
-        self.xp = 0.;
+    /// Spends `max_xp` on a level, whatever is left carries over towards
+    /// the next one, which takes `next_max_xp`
+    pub fn level_up(&mut self, next_max_xp: f32) {
+        self.xp -= self.max_xp;
         self.level += 1;
+        self.max_xp = next_max_xp;
+        self.pending_level_ups += 1;
     }
 
     pub fn position(&self) -> Position {
@@ -0,0 +1,220 @@
+use serde::{Serialize, Deserialize};
+
+/// Something modifiers can change about the player
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Stat {
+    /// Multiplier on the walking speed
+    MoveSpeed,
+    /// Multiplier on the damage of every weapon
+    #[serde(alias = "Damage")]
+    Might,
+    /// Multiplier on the cooldown of every weapon
+    #[serde(alias = "FireCooldown")]
+    Cooldown,
+    /// Multiplier on how far every weapon reaches
+    Area,
+    /// Extra projectiles, orbs and swings for every weapon
+    Projectiles,
+    /// HP healed on every regen tick
+    Regen,
+    /// Milliseconds between two regen ticks
+    RegenInterval,
+    /// Taken off every hit, hits always do a fifth of their damage though
+    Armor,
+    MaxHp,
+    /// Pixels from which gems fly to the player
+    PickupRadius,
+    /// Multiplier on the odds of rare drops
+    Luck,
+    /// Milliseconds the player can't be hurt for after a hit
+    #[serde(alias = "InvincibilityTime")]
+    IframeDuration,
+    DashSpeed,
+}
+
+impl Stat {
+    pub const ALL: [Stat; 13] = [
+        Stat::MoveSpeed,
+        Stat::Might,
+        Stat::Cooldown,
+        Stat::Area,
+        Stat::Projectiles,
+        Stat::Regen,
+        Stat::RegenInterval,
+        Stat::Armor,
+        Stat::MaxHp,
+        Stat::PickupRadius,
+        Stat::Luck,
+        Stat::IframeDuration,
+        Stat::DashSpeed,
+    ];
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+
+    /// What a fresh player starts with
+    pub fn base(&self) -> f32 {
+        match self {
+            Stat::MoveSpeed => 1.,
+            Stat::Might => 1.,
+            Stat::Cooldown => 1.,
+            Stat::Area => 1.,
+            Stat::Projectiles => 0.,
+            Stat::Regen => 1.,
+            Stat::RegenInterval => 5000.,
+            Stat::Armor => 0.,
+            Stat::MaxHp => 100.,
+            Stat::PickupRadius => 12.,
+            Stat::Luck => 1.,
+            Stat::IframeDuration => 1800.,
+            Stat::DashSpeed => 40.,
+        }
+    }
+
+    /// Lowest and highest it gets, whatever the modifiers
+    pub fn limits(&self) -> (f32, f32) {
+        match self {
+            Stat::MoveSpeed => (0.2, 3.),
+            Stat::Might => (0., 50.),
+            // Weapons firing every frame is as low as it makes sense to go
+            Stat::Cooldown => (0.1, 5.),
+            Stat::Area => (0.25, 4.),
+            Stat::Projectiles => (0., 10.),
+            Stat::Regen => (0., 100.),
+            Stat::RegenInterval => (500., 60000.),
+            Stat::Armor => (0., 50.),
+            Stat::MaxHp => (1., 10000.),
+            Stat::PickupRadius => (0., 256.),
+            Stat::Luck => (0., 10.),
+            Stat::IframeDuration => (0., 10000.),
+            Stat::DashSpeed => (0., 200.),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Modifier {
+    Add(Stat, f32),
+    Multiply(Stat, f32),
+}
+
+impl Modifier {
+    pub fn stat(&self) -> Stat {
+        match *self {
+            Modifier::Add(stat, _) | Modifier::Multiply(stat, _) => stat,
+        }
+    }
+
+    pub fn value(&self) -> f32 {
+        match *self {
+            Modifier::Add(_, v) | Modifier::Multiply(_, v) => v,
+        }
+    }
+}
+
+/// A modifier and what granted it: an upgrade, an item or a character
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SourcedModifier {
+    pub source: String,
+    pub modifier: Modifier,
+}
+
+/// The player's stats: base values plus every modifier on them. Each stat
+/// is its base plus every `Add`, times every `Multiply`, within its limits.
+/// Changes only show once [`Stats::recalculate`] ran.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Stats {
+    base: [f32; Stat::ALL.len()],
+    modifiers: Vec<SourcedModifier>,
+    /// What [`Stats::recalculate`] came up with last
+    values: [f32; Stat::ALL.len()],
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        let base = Stat::ALL.map(|stat| stat.base());
+        Stats { base, modifiers: Vec::new(), values: base }
+    }
+}
+
+impl Stats {
+    pub fn get(&self, stat: Stat) -> f32 {
+        self.values[stat.index()]
+    }
+
+    pub fn base(&self, stat: Stat) -> f32 {
+        self.base[stat.index()]
+    }
+
+    /// For characters starting out different
+    pub fn set_base(&mut self, stat: Stat, value: f32) {
+        self.base[stat.index()] = value;
+    }
+
+    pub fn add(&mut self, source: &str, modifier: Modifier) {
+        self.modifiers.push(SourcedModifier { source: source.to_string(), modifier });
+    }
+
+    /// Takes back every modifier `source` granted
+    pub fn remove_source(&mut self, source: &str) {
+        self.modifiers.retain(|m| m.source != source);
+    }
+
+    pub fn modifiers(&self) -> impl Iterator<Item = &SourcedModifier> {
+        self.modifiers.iter()
+    }
+
+    pub fn recalculate(&mut self) {
+        for stat in Stat::ALL {
+            let mut added = 0.;
+            let mut multiplier = 1.;
+            for m in self.modifiers.iter().map(|m| m.modifier).filter(|m| m.stat() == stat) {
+                match m {
+                    Modifier::Add(_, v) => added += v,
+                    Modifier::Multiply(_, v) => multiplier *= v,
+                }
+            }
+            let (low, high) = stat.limits();
+            self.values[stat.index()] = ((self.base(stat) + added) * multiplier).clamp(low, high);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stacked_modifiers_stay_within_limits() {
+        let mut stats = Stats::default();
+        for _ in 0..20 {
+            stats.add("FireRate", Modifier::Multiply(Stat::Cooldown, 0.8));
+            stats.add("Curse", Modifier::Add(Stat::MoveSpeed, 1.));
+        }
+        stats.recalculate();
+        assert_eq!(stats.get(Stat::Cooldown), 0.1);
+        assert_eq!(stats.get(Stat::MoveSpeed), 3.);
+
+        stats.remove_source("FireRate");
+        for _ in 0..20 {
+            stats.add("Slow", Modifier::Add(Stat::Cooldown, 1.));
+        }
+        stats.recalculate();
+        assert_eq!(stats.get(Stat::Cooldown), 5.);
+    }
+
+    #[test]
+    fn adds_come_before_multipliers() {
+        let mut stats = Stats::default();
+        stats.add("Tome", Modifier::Multiply(Stat::Might, 1.5));
+        stats.add("Ring", Modifier::Add(Stat::Might, 1.));
+        stats.recalculate();
+        assert_eq!(stats.get(Stat::Might), 3.);
+        // Untouched until recalculated
+        stats.remove_source("Tome");
+        assert_eq!(stats.get(Stat::Might), 3.);
+        stats.recalculate();
+        assert_eq!(stats.get(Stat::Might), 2.);
+    }
+}
@@ -0,0 +1,469 @@
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use macroquad::prelude::{vec2, Vec2};
+
+use crate::difficulty::{Difficulty, Preset};
+use crate::input::{InputState, MOVE_STEPS, DASH_STEPS};
+use crate::player::Player;
+use crate::upgrade::Upgrade;
+use crate::world::{World, StepOutcome, LevelUpChoice};
+
+const MAGIC: &[u8; 4] = b"VSRP";
+const VERSION: u16 = 5;
+
+/// Playback speeds the replay player cycles through
+pub const SPEEDS: [f32; 6] = [0.25, 0.5, 1., 2., 4., 8.];
+
+/// Input of a single simulation tick, along with how long that tick was
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TickInput {
+    pub delta: f32,
+    pub input: InputState,
+}
+
+/// Everything needed to re-simulate a run: the seed, the difficulty, the
+/// input of every tick and what was done on every level up screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Replay {
+    pub seed: u64,
+    pub difficulty: Preset,
+    pub ticks: Vec<TickInput>,
+    pub level_up_choices: Vec<LevelUpChoice>,
+    /// [`World::checksum`] once the last tick ran, to spot desyncs
+    pub final_checksum: u64,
+}
+
+#[derive(Debug)]
+pub enum ReplayError {
+    Io(io::Error),
+    NotAReplay,
+    UnsupportedVersion(u16),
+    UnknownDifficulty(u8),
+    UnknownChoice(u8),
+}
+
+impl fmt::Display for ReplayError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ReplayError::Io(e) => write!(f, "couldn't read replay: {}", e),
+            ReplayError::NotAReplay => write!(f, "not a replay file"),
+            ReplayError::UnsupportedVersion(v) => write!(f, "replay version {} is not supported (expected {})", v, VERSION),
+            ReplayError::UnknownDifficulty(b) => write!(f, "replay has an unknown difficulty ({})", b),
+            ReplayError::UnknownChoice(b) => write!(f, "replay has an unknown level up choice ({})", b),
+        }
+    }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl From<io::Error> for ReplayError {
+    fn from(e: io::Error) -> Self {
+        ReplayError::Io(e)
+    }
+}
+
+/// Move axes are -1 to 1 in steps of 1/[`MOVE_STEPS`], as
+/// [`InputState::from_actions`] rounds them
+fn move_to_byte(value: f32) -> u8 {
+    (value.clamp(-1., 1.) * MOVE_STEPS).round() as i8 as u8
+}
+
+fn move_from_byte(byte: u8) -> f32 {
+    byte as i8 as f32 / MOVE_STEPS
+}
+
+/// Dash directions are unit vectors in steps of 1/[`DASH_STEPS`] per axis, as
+/// [`InputState::dash_towards`] rounds them. No dash is stored as zero.
+fn dash_to_bytes(direction: Option<Vec2>) -> [u8; 4] {
+    let v = direction.unwrap_or(Vec2::ZERO);
+    let x = (v.x.clamp(-1., 1.) * DASH_STEPS).round() as i16;
+    let y = (v.y.clamp(-1., 1.) * DASH_STEPS).round() as i16;
+    let (x, y) = (x.to_le_bytes(), y.to_le_bytes());
+    [x[0], x[1], y[0], y[1]]
+}
+
+fn dash_from_bytes(bytes: [u8; 4]) -> Option<Vec2> {
+    let x = i16::from_le_bytes([bytes[0], bytes[1]]);
+    let y = i16::from_le_bytes([bytes[2], bytes[3]]);
+    if x == 0 && y == 0 {
+        return None;
+    }
+    Some(vec2(x as f32 / DASH_STEPS, y as f32 / DASH_STEPS))
+}
+
+/// Picks and banishes keep the card index in the low bits
+const BANISH_BIT: u8 = 0x40;
+const REROLL_BYTE: u8 = 0xfe;
+const SKIP_BYTE: u8 = 0xff;
+
+fn choice_to_byte(choice: LevelUpChoice) -> u8 {
+    match choice {
+        LevelUpChoice::Pick(index) => index as u8,
+        LevelUpChoice::Banish(index) => BANISH_BIT | index as u8,
+        LevelUpChoice::Reroll => REROLL_BYTE,
+        LevelUpChoice::Skip => SKIP_BYTE,
+    }
+}
+
+fn choice_from_byte(byte: u8) -> Option<LevelUpChoice> {
+    match byte {
+        REROLL_BYTE => Some(LevelUpChoice::Reroll),
+        SKIP_BYTE => Some(LevelUpChoice::Skip),
+        b if b < BANISH_BIT => Some(LevelUpChoice::Pick(b as usize)),
+        b if b < BANISH_BIT * 2 => Some(LevelUpChoice::Banish((b & !BANISH_BIT) as usize)),
+        _ => None,
+    }
+}
+
+fn preset_to_byte(preset: Preset) -> u8 {
+    match preset {
+        Preset::Easy => 0,
+        Preset::Normal => 1,
+        Preset::Hard => 2,
+    }
+}
+
+fn preset_from_byte(byte: u8) -> Option<Preset> {
+    match byte {
+        0 => Some(Preset::Easy),
+        1 => Some(Preset::Normal),
+        2 => Some(Preset::Hard),
+        _ => None,
+    }
+}
+
+impl Replay {
+    pub fn new(seed: u64, difficulty: Preset) -> Self {
+        Replay { seed, difficulty, ticks: Vec::new(), level_up_choices: Vec::new(), final_checksum: 0 }
+    }
+
+    pub fn duration_secs(&self) -> f32 {
+        self.ticks.iter().map(|t| t.delta).sum()
+    }
+
+    /// Layout, little endian: magic, version, seed, difficulty byte, tick
+    /// count, choice count, final checksum, then 11 bytes per tick and 1 byte
+    /// per choice.
+    pub fn write_to<W: Write>(&self, w: &mut W) -> io::Result<()> {
+        w.write_all(MAGIC)?;
+        w.write_all(&VERSION.to_le_bytes())?;
+        w.write_all(&self.seed.to_le_bytes())?;
+        w.write_all(&[preset_to_byte(self.difficulty)])?;
+        w.write_all(&(self.ticks.len() as u32).to_le_bytes())?;
+        w.write_all(&(self.level_up_choices.len() as u32).to_le_bytes())?;
+        w.write_all(&self.final_checksum.to_le_bytes())?;
+        for tick in self.ticks.iter() {
+            w.write_all(&tick.delta.to_le_bytes())?;
+            w.write_all(&[
+                move_to_byte(tick.input.move_x),
+                move_to_byte(tick.input.move_y),
+                tick.input.dash_pressed as u8,
+            ])?;
+            w.write_all(&dash_to_bytes(tick.input.dash_direction))?;
+        }
+        let choices: Vec<u8> = self.level_up_choices.iter().map(|c| choice_to_byte(*c)).collect();
+        w.write_all(&choices)?;
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(r: &mut R) -> Result<Replay, ReplayError> {
+        let mut magic = [0u8; 4];
+        r.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(ReplayError::NotAReplay);
+        }
+        let mut u16_buf = [0u8; 2];
+        let mut u32_buf = [0u8; 4];
+        let mut u64_buf = [0u8; 8];
+        r.read_exact(&mut u16_buf)?;
+        let version = u16::from_le_bytes(u16_buf);
+        if version != VERSION {
+            return Err(ReplayError::UnsupportedVersion(version));
+        }
+        r.read_exact(&mut u64_buf)?;
+        let seed = u64::from_le_bytes(u64_buf);
+        let mut preset_buf = [0u8; 1];
+        r.read_exact(&mut preset_buf)?;
+        let difficulty = preset_from_byte(preset_buf[0]).ok_or(ReplayError::UnknownDifficulty(preset_buf[0]))?;
+        r.read_exact(&mut u32_buf)?;
+        let tick_count = u32::from_le_bytes(u32_buf) as usize;
+        r.read_exact(&mut u32_buf)?;
+        let choice_count = u32::from_le_bytes(u32_buf) as usize;
+        r.read_exact(&mut u64_buf)?;
+        let final_checksum = u64::from_le_bytes(u64_buf);
+
+        let mut ticks = Vec::with_capacity(tick_count);
+        let mut tick_buf = [0u8; 11];
+        for _ in 0..tick_count {
+            r.read_exact(&mut tick_buf)?;
+            ticks.push(TickInput {
+                delta: f32::from_le_bytes([tick_buf[0], tick_buf[1], tick_buf[2], tick_buf[3]]),
+                input: InputState {
+                    move_x: move_from_byte(tick_buf[4]),
+                    move_y: move_from_byte(tick_buf[5]),
+                    dash_pressed: tick_buf[6] != 0,
+                    dash_direction: dash_from_bytes([tick_buf[7], tick_buf[8], tick_buf[9], tick_buf[10]]),
+                },
+            });
+        }
+        let mut choice_bytes = vec![0u8; choice_count];
+        r.read_exact(&mut choice_bytes)?;
+        let level_up_choices = choice_bytes.iter()
+            .map(|b| choice_from_byte(*b).ok_or(ReplayError::UnknownChoice(*b)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Replay { seed, difficulty, ticks, level_up_choices, final_checksum })
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        if let Some(dir) = path.as_ref().parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let mut w = BufWriter::new(File::create(path)?);
+        self.write_to(&mut w)?;
+        w.flush()
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Replay, ReplayError> {
+        let mut r = BufReader::new(File::open(path)?);
+        Replay::read_from(&mut r)
+    }
+}
+
+/// Collects the input of a run while it is being played
+pub struct ReplayRecorder {
+    replay: Replay,
+}
+
+impl ReplayRecorder {
+    pub fn new(seed: u64, difficulty: Preset) -> Self {
+        ReplayRecorder { replay: Replay::new(seed, difficulty) }
+    }
+
+    pub fn record_tick(&mut self, delta: f32, input: &InputState) {
+        self.replay.ticks.push(TickInput { delta, input: *input });
+    }
+
+    pub fn record_level_up_choice(&mut self, choice: LevelUpChoice) {
+        self.replay.level_up_choices.push(choice);
+    }
+
+    pub fn finish(mut self, world: &World, player: &Player) -> Replay {
+        self.replay.final_checksum = world.checksum(player);
+        self.replay
+    }
+}
+
+/// Re-simulates a [`Replay`] into a world and player, with pause, speed
+/// control and seeking. Seeking backwards restarts the run from its seed.
+pub struct ReplayPlayer {
+    pub replay: Replay,
+    tick: usize,
+    next_choice: usize,
+    elapsed: f32,
+    accumulator: f32,
+    speed_index: usize,
+    pub paused: bool,
+}
+
+impl ReplayPlayer {
+    pub fn new(replay: Replay) -> Self {
+        ReplayPlayer {
+            replay,
+            tick: 0,
+            next_choice: 0,
+            elapsed: 0.,
+            accumulator: 0.,
+            speed_index: 2,
+            paused: false,
+        }
+    }
+
+    pub fn tick(&self) -> usize {
+        self.tick
+    }
+
+    pub fn elapsed_secs(&self) -> f32 {
+        self.elapsed
+    }
+
+    pub fn speed(&self) -> f32 {
+        SPEEDS[self.speed_index]
+    }
+
+    pub fn faster(&mut self) {
+        self.speed_index = (self.speed_index + 1).min(SPEEDS.len() - 1);
+    }
+
+    pub fn slower(&mut self) {
+        self.speed_index = self.speed_index.saturating_sub(1);
+    }
+
+    pub fn finished(&self) -> bool {
+        self.tick >= self.replay.ticks.len()
+    }
+
+    /// Back to the very first tick
+    pub fn restart(&mut self, player: &mut Player, world: &mut World) {
+        *player = Player::new();
+        world.difficulty = Difficulty::new(self.replay.difficulty);
+        world.reset(self.replay.seed);
+        self.tick = 0;
+        self.next_choice = 0;
+        self.elapsed = 0.;
+        self.accumulator = 0.;
+    }
+
+    /// Runs the next recorded tick. Returns false once the replay is over.
+    pub fn step(&mut self, player: &mut Player, world: &mut World) -> bool {
+        let Some(tick) = self.replay.ticks.get(self.tick).copied() else {
+            return false;
+        };
+        if world.step(player, tick.delta, &tick.input) == StepOutcome::LevelUp {
+            // Level ups with nothing to offer weren't recorded either
+            while let Some(mut offered) = world.next_level_up(player) {
+                if !self.play_level_up(player, world, &mut offered) {
+                    // The recording stopped on the level up screen
+                    break;
+                }
+            }
+        }
+        self.tick += 1;
+        self.elapsed += tick.delta;
+        if self.finished() && world.checksum(player) != self.replay.final_checksum {
+            eprintln!("replay desynced: final state doesn't match the recording");
+        }
+        true
+    }
+
+    /// Runs recorded choices until the level up showing `offered` is over,
+    /// false if the recording runs out first
+    fn play_level_up(&mut self, player: &mut Player, world: &mut World, offered: &mut Vec<Box<dyn Upgrade>>) -> bool {
+        while let Some(choice) = self.replay.level_up_choices.get(self.next_choice).copied() {
+            self.next_choice += 1;
+            if world.resolve_level_up(player, offered, choice) {
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Plays as many ticks as fit in `delta` real seconds at the current speed
+    pub fn update(&mut self, delta: f32, player: &mut Player, world: &mut World) {
+        if self.paused {
+            return;
+        }
+        self.accumulator += delta * self.speed();
+        while let Some(tick) = self.replay.ticks.get(self.tick) {
+            if self.accumulator < tick.delta {
+                break;
+            }
+            self.accumulator -= tick.delta;
+            self.step(player, world);
+        }
+    }
+
+    /// Jumps to the tick closest to `secs` into the run
+    pub fn seek(&mut self, secs: f32, player: &mut Player, world: &mut World) {
+        let secs = secs.max(0.);
+        if secs < self.elapsed {
+            self.restart(player, world);
+        }
+        while !self.finished() && self.elapsed + self.replay.ticks[self.tick].delta <= secs {
+            self.step(player, world);
+        }
+        self.accumulator = 0.;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Circles around, dashing every couple of seconds
+    fn scripted(tick: usize) -> InputState {
+        let angle = tick as f32 * 0.01;
+        let dash_pressed = tick.is_multiple_of(150);
+        InputState {
+            move_x: (angle.cos() * MOVE_STEPS).round() / MOVE_STEPS,
+            move_y: (angle.sin() * MOVE_STEPS).round() / MOVE_STEPS,
+            dash_pressed,
+            dash_direction: if dash_pressed { InputState::dash_towards(vec2(angle.cos(), -angle.sin())) } else { None },
+        }
+    }
+
+    #[test]
+    fn choices_have_their_own_bytes() {
+        assert_eq!(choice_to_byte(LevelUpChoice::Pick(3)), 0x03);
+        assert_eq!(choice_to_byte(LevelUpChoice::Banish(2)), 0x42);
+        assert_eq!(choice_to_byte(LevelUpChoice::Reroll), 0xfe);
+        assert_eq!(choice_to_byte(LevelUpChoice::Skip), 0xff);
+        for choice in [LevelUpChoice::Pick(0), LevelUpChoice::Pick(3), LevelUpChoice::Banish(0), LevelUpChoice::Banish(3), LevelUpChoice::Reroll, LevelUpChoice::Skip] {
+            assert_eq!(choice_from_byte(choice_to_byte(choice)), Some(choice));
+        }
+        assert_eq!(choice_from_byte(0x80), None);
+    }
+
+    #[test]
+    fn round_trips_through_bytes() {
+        let mut replay = Replay::new(1234, Preset::Hard);
+        replay.ticks = (0..500).map(|i| TickInput { delta: 1. / 60. + i as f32 * 1e-5, input: scripted(i) }).collect();
+        replay.level_up_choices = vec![
+            LevelUpChoice::Pick(1),
+            LevelUpChoice::Reroll,
+            LevelUpChoice::Banish(2),
+            LevelUpChoice::Skip,
+            LevelUpChoice::Pick(0),
+        ];
+        replay.final_checksum = 0xdead_beef_cafe;
+
+        let mut bytes = Vec::new();
+        replay.write_to(&mut bytes).unwrap();
+        assert_eq!(Replay::read_from(&mut bytes.as_slice()).unwrap(), replay);
+
+        // Choices come last
+        *bytes.last_mut().unwrap() = 0x90;
+        assert!(matches!(Replay::read_from(&mut bytes.as_slice()), Err(ReplayError::UnknownChoice(0x90))));
+        bytes[0] = b'X';
+        assert!(matches!(Replay::read_from(&mut bytes.as_slice()), Err(ReplayError::NotAReplay)));
+    }
+
+    #[test]
+    fn playback_ends_where_the_recording_did() {
+        let choices = [LevelUpChoice::Reroll, LevelUpChoice::Banish(0), LevelUpChoice::Pick(1), LevelUpChoice::Skip, LevelUpChoice::Pick(0)];
+        let mut player = Player::new();
+        let mut world = World::new(77);
+        let mut recorder = ReplayRecorder::new(77, Preset::Normal);
+        let mut next = 0;
+        for i in 0..3600 {
+            let input = scripted(i);
+            recorder.record_tick(1. / 60., &input);
+            if world.step(&mut player, 1. / 60., &input) == StepOutcome::LevelUp {
+                while let Some(mut offered) = world.next_level_up(&mut player) {
+                    loop {
+                        let choice = choices[next % choices.len()];
+                        next += 1;
+                        recorder.record_level_up_choice(choice);
+                        if world.resolve_level_up(&mut player, &mut offered, choice) {
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        assert!(next > 0, "never leveled up");
+        let replay = recorder.finish(&world, &player);
+
+        let mut bytes = Vec::new();
+        replay.write_to(&mut bytes).unwrap();
+        let mut replay_player = ReplayPlayer::new(Replay::read_from(&mut bytes.as_slice()).unwrap());
+        let mut player = Player::new();
+        let mut world = World::new(1);
+        replay_player.restart(&mut player, &mut world);
+        while replay_player.step(&mut player, &mut world) {}
+        assert_eq!(world.checksum(&player), replay.final_checksum);
+    }
+}
@@ -0,0 +1,120 @@
+use randomize::{PCG32, RandRangeU32, f32_half_open_right};
+use serde::{Serialize, Deserialize};
+
+// Every stream gets its own PCG32 sequence out of the same run seed
+const SPAWN_STREAM: u64 = 1;
+const LOOT_STREAM: u64 = 2;
+const FX_STREAM: u64 = 3;
+
+/// A single random stream
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Rng {
+    gen: PCG32,
+}
+
+impl Rng {
+    pub fn new(seed: u64, stream: u64) -> Self {
+        Rng { gen: PCG32::seed(seed, stream) }
+    }
+
+    pub fn next_u32(&mut self) -> u32 {
+        self.gen.next_u32()
+    }
+
+    /// `low` inclusive, `high` exclusive. `RandRangeU32::new` includes both
+    /// ends, so `gen_range_u32(0, 6)` draws what `RandRangeU32::new(0, 5)` did
+    pub fn gen_range_u32(&mut self, low: u32, high: u32) -> u32 {
+        if high <= low {
+            return low;
+        }
+        RandRangeU32::new(low, high - 1).sample(&mut self.gen)
+    }
+
+    /// `low` inclusive, `high` exclusive
+    pub fn gen_range_i32(&mut self, low: i32, high: i32) -> i32 {
+        if high <= low {
+            return low;
+        }
+        low + self.gen_range_u32(0, (high - low) as u32) as i32
+    }
+
+    /// `low` inclusive, `high` exclusive
+    pub fn gen_range_f32(&mut self, low: f32, high: f32) -> f32 {
+        low + (high - low) * f32_half_open_right(self.gen.next_u32())
+    }
+
+    /// Either -1 or 1
+    pub fn gen_sign(&mut self) -> f32 {
+        match self.gen_range_u32(0, 2) {
+            0 => -1.,
+            _ => 1.,
+        }
+    }
+}
+
+/// All the randomness of a run, built from one seed.
+///
+/// Streams are independent so that, e.g., spawning more particles never
+/// shifts where the next enemy shows up.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunRng {
+    seed: u64,
+    /// Enemy spawn positions and patterns
+    pub spawn: Rng,
+    /// Upgrades offered on level up, drops
+    pub loot: Rng,
+    /// Cosmetic only: particles, screen effects
+    pub fx: Rng,
+}
+
+impl RunRng {
+    pub fn new(seed: u64) -> Self {
+        RunRng {
+            seed,
+            spawn: Rng::new(seed, SPAWN_STREAM),
+            loot: Rng::new(seed, LOOT_STREAM),
+            fx: Rng::new(seed, FX_STREAM),
+        }
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.seed
+    }
+}
+
+/// A seed for when none was asked for
+pub fn seed_from_time() -> u64 {
+    (macroquad::miniquad::date::now() * 1000.) as u64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges_leave_out_high() {
+        let mut rng = Rng::new(11, SPAWN_STREAM);
+        let mut gen = PCG32::seed(11, SPAWN_STREAM);
+        let mut seen = [false; 6];
+        for _ in 0..1000 {
+            let n = rng.gen_range_u32(0, 6);
+            assert_eq!(n, RandRangeU32::new(0, 5).sample(&mut gen));
+            seen[n as usize] = true;
+        }
+        assert!(seen.iter().all(|&s| s));
+        assert_eq!(rng.gen_range_u32(3, 3), 3);
+        assert!((0..1000).all(|_| (-2..2).contains(&rng.gen_range_i32(-2, 2))));
+    }
+
+    #[test]
+    fn streams_dont_share_draws() {
+        let mut a = RunRng::new(42);
+        let mut b = RunRng::new(42);
+        for _ in 0..100 {
+            a.fx.next_u32();
+        }
+        assert_eq!(a.spawn.next_u32(), b.spawn.next_u32());
+        assert_eq!(a.loot.next_u32(), b.loot.next_u32());
+        assert_ne!(a.fx.next_u32(), b.fx.next_u32());
+    }
+}
@@ -0,0 +1,218 @@
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::Bullet;
+use crate::difficulty::Difficulty;
+use crate::enemies::Enemy;
+use crate::pickups::Pickup;
+use crate::player::Player;
+use crate::rng::RunRng;
+use crate::spawn::SpawnState;
+use crate::stats::RunStats;
+use crate::stopwatch_bevy::Stopwatch;
+use crate::weapons::{Inventory, WeaponSave};
+use crate::world::World;
+
+/// Bump whenever the layout below changes, older saves are refused
+pub const SAVE_VERSION: u32 = 13;
+
+/// The only thing the game keeps between sessions
+pub const SAVE_PATH: &str = "saves/run.ron";
+
+/// A run in progress. Only gameplay state is kept, effects like particles,
+/// popups and dying enemies are simply dropped.
+#[derive(Serialize, Deserialize)]
+pub struct SaveGame {
+    pub version: u32,
+    /// Game clock at the time of saving, every timer below is relative to it
+    pub clock_now: f64,
+    pub player: Player,
+    pub world: WorldSave,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct WorldSave {
+    /// Animations aren't kept, they're rebuilt from the enemy definitions
+    pub enemies: Vec<Enemy>,
+    pub next_enemy_id: u64,
+    pub bullets: Vec<Bullet>,
+    pub enemy_bullets: Vec<Bullet>,
+    pub pickups: Vec<Pickup>,
+    pub difficulty: Difficulty,
+    pub kill_count: i32,
+    pub weapons: Vec<WeaponSave>,
+    pub stopwatch: Stopwatch,
+    pub spawn_state: SpawnState,
+    pub rng: RunRng,
+    pub chosen_upgrades: Vec<String>,
+    pub banished: Vec<String>,
+    pub rerolls_used: u32,
+    pub banishes_used: u32,
+    pub stats: RunStats,
+}
+
+#[derive(Debug)]
+pub enum SaveError {
+    Io(io::Error),
+    Format(String),
+    UnsupportedVersion(u32),
+}
+
+impl fmt::Display for SaveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SaveError::Io(e) => write!(f, "couldn't access the save: {}", e),
+            SaveError::Format(e) => write!(f, "save is corrupted: {}", e),
+            SaveError::UnsupportedVersion(v) => write!(f, "save version {} is not supported (expected {})", v, SAVE_VERSION),
+        }
+    }
+}
+
+impl std::error::Error for SaveError {}
+
+impl From<io::Error> for SaveError {
+    fn from(e: io::Error) -> Self {
+        SaveError::Io(e)
+    }
+}
+
+impl SaveGame {
+    pub fn capture(world: &World, player: &Player) -> Self {
+        SaveGame {
+            version: SAVE_VERSION,
+            clock_now: world.clock.now(),
+            player: player.clone(),
+            world: WorldSave {
+                enemies: world.enemies.iter().filter(|e| e.alive).cloned().collect(),
+                next_enemy_id: world.next_enemy_id,
+                bullets: world.bullets.iter().filter(|b| b.active).cloned().collect(),
+                enemy_bullets: world.enemy_bullets.iter().filter(|b| b.active).cloned().collect(),
+                pickups: world.pickups.clone(),
+                difficulty: world.difficulty.clone(),
+                kill_count: world.kill_count,
+                weapons: world.weapons.save(),
+                stopwatch: world.stopwatch.clone(),
+                spawn_state: world.spawn_director.state.clone(),
+                rng: world.rng.clone(),
+                chosen_upgrades: world.chosen_upgrades.clone(),
+                banished: world.banished.clone(),
+                rerolls_used: world.rerolls_used,
+                banishes_used: world.banishes_used,
+                stats: world.stats.clone(),
+            },
+        }
+    }
+
+    /// Puts the saved run back into `world` and `player`, game clock included
+    pub fn restore(self, world: &mut World, player: &mut Player) {
+        let saved = self.world;
+        world.reset(saved.rng.seed());
+        world.clock.set_now(self.clock_now);
+
+        // Enemies whose definition is gone since are dropped
+        let defs = &world.enemy_defs;
+        world.enemies = saved.enemies.into_iter().filter_map(|mut e| {
+            e.anims = defs.get(&e.kind)?.animations();
+            Some(e)
+        }).collect();
+        world.next_enemy_id = saved.next_enemy_id;
+        world.bullets = saved.bullets;
+        world.enemy_bullets = saved.enemy_bullets;
+        world.pickups = saved.pickups;
+        world.difficulty = saved.difficulty;
+        world.kill_count = saved.kill_count;
+        world.weapons = Inventory::restore(&world.weapon_defs, saved.weapons);
+        world.stopwatch = saved.stopwatch;
+        // Saved from the level up screen it can still be paused
+        world.stopwatch.unpause();
+        world.spawn_director.state = saved.spawn_state;
+        world.rng = saved.rng;
+        world.chosen_upgrades = saved.chosen_upgrades;
+        world.banished = saved.banished;
+        world.rerolls_used = saved.rerolls_used;
+        world.banishes_used = saved.banishes_used;
+        world.stats = saved.stats;
+
+        *player = self.player;
+    }
+
+    pub fn to_string(&self) -> Result<String, SaveError> {
+        ron::ser::to_string_pretty(self, ron::ser::PrettyConfig::default())
+            .map_err(|e| SaveError::Format(e.to_string()))
+    }
+
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), SaveError> {
+        if let Some(dir) = path.as_ref().parent() {
+            fs::create_dir_all(dir)?;
+        }
+        fs::write(path, self.to_string()?)?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<SaveGame, SaveError> {
+        fs::read_to_string(path)?.parse()
+    }
+}
+
+impl FromStr for SaveGame {
+    type Err = SaveError;
+
+    fn from_str(s: &str) -> Result<SaveGame, SaveError> {
+        // Peek at the version first, so an old save says so instead of
+        // failing on whatever field changed
+        #[derive(Deserialize)]
+        struct Versioned {
+            version: u32,
+        }
+        let versioned: Versioned = ron::from_str(s).map_err(|e| SaveError::Format(e.to_string()))?;
+        if versioned.version != SAVE_VERSION {
+            return Err(SaveError::UnsupportedVersion(versioned.version));
+        }
+        ron::from_str(s).map_err(|e| SaveError::Format(e.to_string()))
+    }
+}
+
+pub fn save_exists() -> bool {
+    Path::new(SAVE_PATH).exists()
+}
+
+pub fn delete_save() {
+    if save_exists() {
+        if let Err(e) = fs::remove_file(SAVE_PATH) {
+            eprintln!("couldn't remove {}: {}", SAVE_PATH, e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputState;
+
+    #[test]
+    fn restored_stopwatch_keeps_running() {
+        let mut player = Player::new();
+        let mut world = World::new(5);
+        for _ in 0..60 {
+            world.step(&mut player, 1. / 60., &InputState::default());
+        }
+        // What the level up screen used to leave behind
+        world.stopwatch.pause();
+        let saved: SaveGame = SaveGame::capture(&world, &player).to_string().unwrap().parse().unwrap();
+
+        let mut player = Player::new();
+        let mut world = World::new(9);
+        saved.restore(&mut world, &mut player);
+        let before = world.stopwatch.elapsed_secs();
+        assert!(before > 0.9);
+        for _ in 0..60 {
+            world.step(&mut player, 1. / 60., &InputState::default());
+        }
+        assert!(world.stopwatch.elapsed_secs() > before + 0.9);
+    }
+}
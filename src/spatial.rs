@@ -0,0 +1,151 @@
+use std::collections::HashMap;
+
+use crate::Position;
+
+/// Uniform grid broadphase. Things are bucketed by the cell their position
+/// falls in, so a lookup only has to look at the few cells around a point
+/// instead of at everything.
+///
+/// The grid only stores indices into whatever slice it was built from, and
+/// has to be rebuilt whenever those positions move.
+pub struct SpatialGrid {
+    cell_size: f32,
+    cells: HashMap<(i32, i32), Vec<usize>>,
+}
+
+impl SpatialGrid {
+    pub fn new(cell_size: f32) -> Self {
+        SpatialGrid { cell_size, cells: HashMap::new() }
+    }
+
+    fn cell(&self, x: f32, y: f32) -> (i32, i32) {
+        ((x / self.cell_size).floor() as i32, (y / self.cell_size).floor() as i32)
+    }
+
+    /// Empties every cell but keeps their allocations around, the crowd
+    /// mostly stays in the same cells from one rebuild to the next
+    pub fn clear(&mut self) {
+        self.cells.retain(|_, indices| {
+            let used = !indices.is_empty();
+            indices.clear();
+            used
+        });
+    }
+
+    pub fn insert(&mut self, index: usize, pos: Position) {
+        let cell = self.cell(pos.x, pos.y);
+        self.cells.entry(cell).or_default().push(index);
+    }
+
+    /// Starts over with `positions`, indexed in iteration order
+    pub fn rebuild<I: IntoIterator<Item = Position>>(&mut self, positions: I) {
+        self.clear();
+        for (i, pos) in positions.into_iter().enumerate() {
+            self.insert(i, pos);
+        }
+    }
+
+    /// Fills `out` with every index that could be within `radius` of `pos`,
+    /// in ascending order so callers visit things like a plain loop would.
+    /// Candidates still have to be checked for real by the caller.
+    pub fn query(&self, pos: Position, radius: f32, out: &mut Vec<usize>) {
+        out.clear();
+        let (min_x, min_y) = self.cell(pos.x - radius, pos.y - radius);
+        let (max_x, max_y) = self.cell(pos.x + radius, pos.y + radius);
+        for cx in min_x..=max_x {
+            for cy in min_y..=max_y {
+                if let Some(indices) = self.cells.get(&(cx, cy)) {
+                    out.extend_from_slice(indices);
+                }
+            }
+        }
+        out.sort_unstable();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemies::{Enemy, update_enemies_pushing};
+    use crate::enemy_defs::EnemyDefs;
+    use crate::rng::Rng;
+    use crate::{col, dist, get_dir_};
+
+    fn pos(x: f32, y: f32) -> Position {
+        Position { x, y }
+    }
+
+    fn found(grid: &SpatialGrid, at: Position, radius: f32) -> Vec<usize> {
+        let mut out = vec![99];
+        grid.query(at, radius, &mut out);
+        out
+    }
+
+    #[test]
+    fn finds_neighbours_across_negative_cells() {
+        let mut grid = SpatialGrid::new(16.);
+        // -0.5 floors into cell -1, 0.5 into cell 0
+        grid.rebuild([pos(-0.5, -0.5), pos(0.5, 0.5), pos(-16.5, 3.), pos(40., 40.)]);
+        assert_eq!(found(&grid, pos(0., 0.), 1.), vec![0, 1]);
+        assert_eq!(found(&grid, pos(-0.5, -0.5), 0.), vec![0]);
+        assert_eq!(found(&grid, pos(-15.9, 0.), 1.), vec![0, 2]);
+        // Right on a border counts as the cell past it
+        assert!(found(&grid, pos(31.9, 31.9), 0.).is_empty());
+        assert_eq!(found(&grid, pos(32., 32.), 0.), vec![3]);
+    }
+
+    #[test]
+    fn results_come_sorted() {
+        let mut grid = SpatialGrid::new(4.);
+        grid.rebuild((0..50).map(|i| pos(((i * 37) % 11) as f32 - 5., ((i * 13) % 7) as f32 - 3.)));
+        let out = found(&grid, pos(0., 0.), 10.);
+        assert_eq!(out, (0..50).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn clear_leaves_nothing_behind() {
+        let mut grid = SpatialGrid::new(16.);
+        grid.rebuild([pos(1., 1.), pos(-20., 5.)]);
+        grid.clear();
+        assert!(found(&grid, pos(0., 0.), 100.).is_empty());
+        grid.rebuild([pos(-20., 5.)]);
+        assert!(found(&grid, pos(1., 1.), 4.).is_empty());
+        assert_eq!(found(&grid, pos(-20., 5.), 4.), vec![0]);
+    }
+
+    /// The O(n²) loop the grid replaced
+    fn brute_force_pushing(enemies: &mut [Enemy]) {
+        let r = 8.;
+        for i in 0..enemies.len() - 1 {
+            for j in i + 1..enemies.len() {
+                if col(enemies[i].position, enemies[j].position, r) {
+                    let dist = dist(enemies[i].position, enemies[j].position, 10.);
+                    let dir = get_dir_(enemies[i].position, enemies[j].position);
+                    let dif = r - dist;
+                    enemies[i].position.x += dir.cos() * dif;
+                    enemies[i].position.y += dir.sin() * dif;
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pushing_matches_brute_force() {
+        let defs = EnemyDefs::builtin();
+        let slime = defs.get("slime").unwrap();
+        let mut rng = Rng::new(8, 0);
+        let crowd: Vec<Enemy> = (0..60)
+            .map(|_| Enemy::from_def(slime, rng.gen_range_f32(-20., 20.), rng.gen_range_f32(-20., 20.), 1., 0.))
+            .collect();
+
+        let mut expected = crowd.clone();
+        brute_force_pushing(&mut expected);
+        let mut pushed = crowd;
+        let mut grid = SpatialGrid::new(16.);
+        grid.rebuild(pushed.iter().map(|e| e.position));
+        update_enemies_pushing(&mut pushed, &grid);
+        for (a, b) in pushed.iter().zip(expected.iter()) {
+            assert_eq!((a.position.x, a.position.y), (b.position.x, b.position.y));
+        }
+    }
+}
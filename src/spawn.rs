@@ -0,0 +1,322 @@
+use std::str::FromStr;
+
+use serde::{Serialize, Deserialize};
+
+use crate::defs::{self, DefError};
+use crate::difficulty::Scaling;
+use crate::enemies::Enemy;
+use crate::enemy_defs::EnemyDefs;
+use crate::player::Player;
+use crate::rng::Rng;
+
+const BUILTIN: &str = include_str!("../assets/spawns.ron");
+
+/// Where spawned enemies show up, relative to the player
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Pattern {
+    /// Somewhere 60 to 93 pixels away
+    AroundPlayer,
+    /// Off to the left or right, flying across
+    SideSweep,
+    /// On a circle of `radius`. Events spread their enemies evenly on it.
+    Ring { radius: f32 },
+}
+
+/// Keeps up to `cap` enemies of a kind around, spawning `per_second` of
+/// them while there are fewer
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpawnRule {
+    pub enemy: String,
+    pub cap: u32,
+    pub per_second: f32,
+    pub pattern: Pattern,
+}
+
+/// The spawn rules from `at` seconds into the run until the next phase
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Phase {
+    pub at: f32,
+    pub spawns: Vec<SpawnRule>,
+}
+
+/// `count` enemies all at once, `at` seconds into the run
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpawnEvent {
+    pub at: f32,
+    pub enemy: String,
+    pub count: u32,
+    pub pattern: Pattern,
+}
+
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct Timeline {
+    pub phases: Vec<Phase>,
+    #[serde(default)]
+    pub events: Vec<SpawnEvent>,
+}
+
+/// `m:ss` of a time in seconds, for error messages
+fn clock_time(secs: f32) -> String {
+    format!("{}:{:02}", secs as u32 / 60, secs as u32 % 60)
+}
+
+impl Timeline {
+    pub fn builtin() -> Self {
+        BUILTIN.parse().expect("assets/spawns.ron is broken")
+    }
+
+    /// Every enemy the timeline spawns has to be defined
+    pub fn check_enemies(&self, defs: &EnemyDefs) -> Result<(), DefError> {
+        let rules = self.phases.iter().flat_map(|p| p.spawns.iter().map(move |r| (p.at, &r.enemy)));
+        let events = self.events.iter().map(|e| (e.at, &e.enemy));
+        for (at, enemy) in rules.chain(events) {
+            if defs.get(enemy).is_none() {
+                return Err(DefError::Invalid(format!("{}: there's no enemy called \"{}\"", clock_time(at), enemy)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Index of the phase running `secs` into the run
+    fn phase_at(&self, secs: f32) -> Option<usize> {
+        self.phases.iter().rposition(|p| p.at <= secs)
+    }
+}
+
+impl FromStr for Timeline {
+    type Err = DefError;
+
+    /// Events may come in any order, phases have to be in time order
+    fn from_str(s: &str) -> Result<Timeline, DefError> {
+        let mut timeline: Timeline = defs::from_ron(s)?;
+        let mut last_at = 0.;
+        for phase in timeline.phases.iter() {
+            if phase.at.is_nan() || phase.at < last_at {
+                return Err(DefError::Invalid(format!("phase at {} comes before the one at {}", clock_time(phase.at), clock_time(last_at))));
+            }
+            last_at = phase.at;
+            for rule in phase.spawns.iter() {
+                if !rule.per_second.is_finite() || rule.per_second <= 0. {
+                    return Err(DefError::Invalid(format!("phase at {}: {} per_second must be a number above 0", clock_time(phase.at), rule.enemy)));
+                }
+            }
+        }
+        for event in timeline.events.iter() {
+            if event.at.is_nan() || event.at < 0. {
+                return Err(DefError::Invalid(format!("event of {} {} happens before the run starts", event.count, event.enemy)));
+            }
+        }
+        timeline.events.sort_by(|a, b| a.at.total_cmp(&b.at));
+        Ok(timeline)
+    }
+}
+
+/// Where the director is in the timeline, saved along with the run
+#[derive(Debug, Clone, PartialEq, Default, Serialize, Deserialize)]
+pub struct SpawnState {
+    phase: Option<usize>,
+    /// Spawns owed by each rule of the current phase
+    owed: Vec<f32>,
+    next_event: usize,
+}
+
+/// What the director spawns into during a step
+pub struct SpawnContext<'a> {
+    pub enemies: &'a mut Vec<Enemy>,
+    pub defs: &'a EnemyDefs,
+    pub player: &'a Player,
+    pub scaling: &'a Scaling,
+    pub rng: &'a mut Rng,
+    /// Game time, for the timers of whatever spawns
+    pub now: f64,
+}
+
+/// Spawns enemies as the timeline says, going by the run stopwatch
+#[derive(Debug, Clone, PartialEq)]
+pub struct SpawnDirector {
+    pub timeline: Timeline,
+    pub state: SpawnState,
+}
+
+impl SpawnDirector {
+    pub fn new(timeline: Timeline) -> Self {
+        SpawnDirector { timeline, state: SpawnState::default() }
+    }
+
+    pub fn reset(&mut self) {
+        self.state = SpawnState::default();
+    }
+
+    pub fn update(&mut self, elapsed_secs: f32, delta: f32, ctx: SpawnContext) {
+        let SpawnContext { enemies, defs, player, scaling, rng, now } = ctx;
+        let phase = self.timeline.phase_at(elapsed_secs);
+        if phase != self.state.phase {
+            self.state.phase = phase;
+            self.state.owed = vec![0.; phase.map_or(0, |p| self.timeline.phases[p].spawns.len())];
+        }
+
+        if let Some(phase) = phase {
+            for (rule, owed) in self.timeline.phases[phase].spawns.iter().zip(self.state.owed.iter_mut()) {
+                let Some(def) = defs.get(&rule.enemy) else { continue };
+                *owed += rule.per_second * delta;
+                let cap = (rule.cap as f32 * scaling.spawn_caps).round() as u32;
+                let mut count = enemies.iter().filter(|e| e.kind == rule.enemy).count() as u32;
+                while *owed >= 1. && count < cap {
+                    let (x, y, x_dir) = spawn_position(&rule.pattern, 0, 1, player, rng);
+                    enemies.push(Enemy::from_def(def, x, y, scaling.enemy_xp, now).heading(x_dir).scaled(scaling));
+                    *owed -= 1.;
+                    count += 1;
+                }
+                // Being at the cap doesn't pile up spawns for later
+                *owed = owed.min(1.);
+            }
+        }
+
+        while let Some(event) = self.timeline.events.get(self.state.next_event) {
+            if event.at > elapsed_secs {
+                break;
+            }
+            if let Some(def) = defs.get(&event.enemy) {
+                for i in 0..event.count {
+                    let (x, y, x_dir) = spawn_position(&event.pattern, i, event.count, player, rng);
+                    enemies.push(Enemy::from_def(def, x, y, scaling.enemy_xp, now).heading(x_dir).scaled(scaling));
+                }
+            }
+            self.state.next_event += 1;
+        }
+    }
+}
+
+/// Where the `i`th out of `count` enemies spawned together goes, and which
+/// way it heads if it flies
+fn spawn_position(pattern: &Pattern, i: u32, count: u32, player: &Player, rng: &mut Rng) -> (f32, f32, f32) {
+    match pattern {
+        Pattern::AroundPlayer => {
+            // get a random position away from the player
+            let direction = rng.gen_range_i32(-1, 2) as f32;
+            let random = rng.gen_sign();
+
+            let distance = 60. + rng.gen_range_f32(0., 33.).floor();
+            let x = player.pos_x + direction.cos() * distance * random;
+            let y = player.pos_y + direction.sin() * distance * random;
+            (x, y, 1.)
+        }
+        Pattern::SideSweep => {
+            let x_dir = rng.gen_sign();
+            let spawn_pos_y = player.pos_y * (rng.gen_range_f32(0.5, 2.));
+            (player.pos_x - 64. * x_dir, spawn_pos_y, x_dir)
+        }
+        Pattern::Ring { radius } => {
+            let angle = if count > 1 {
+                std::f32::consts::TAU * i as f32 / count as f32
+            } else {
+                // Random angle in radians
+                rng.gen_range_f32(0.0, std::f32::consts::TAU)
+            };
+            (player.pos_x + radius * angle.cos(), player.pos_y + radius * angle.sin(), 1.)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NO_SCALING: Scaling = Scaling { enemy_hp: 1., spawn_caps: 1., enemy_xp: 1., projectile_speed: 1. };
+
+    fn timeline(ron: &str) -> Timeline {
+        ron.parse().unwrap()
+    }
+
+    /// Runs the director from `from` to `to` seconds in steps of `delta`
+    fn run(director: &mut SpawnDirector, enemies: &mut Vec<Enemy>, from: f32, to: f32, delta: f32) {
+        let defs = EnemyDefs::builtin();
+        let player = Player::new();
+        let mut rng = Rng::new(11, 0);
+        let mut secs = from;
+        while secs < to {
+            secs += delta;
+            director.update(secs, delta, SpawnContext { enemies, defs: &defs, player: &player, scaling: &NO_SCALING, rng: &mut rng, now: secs as f64 });
+        }
+    }
+
+    fn count(enemies: &[Enemy], kind: &str) -> usize {
+        enemies.iter().filter(|e| e.kind == kind).count()
+    }
+
+    #[test]
+    fn phases_start_at_their_time() {
+        let timeline = timeline(r#"(phases: [
+            (at: 0.0, spawns: [(enemy: "slime", cap: 50, per_second: 2.0, pattern: AroundPlayer)]),
+            (at: 30.0, spawns: [(enemy: "tower", cap: 50, per_second: 2.0, pattern: Ring(radius: 40.0))]),
+            (at: 30.0, spawns: [(enemy: "bat", cap: 50, per_second: 2.0, pattern: SideSweep)]),
+        ])"#);
+        assert_eq!(timeline.phase_at(-1.), None);
+        assert_eq!(timeline.phase_at(0.), Some(0));
+        assert_eq!(timeline.phase_at(29.9), Some(0));
+        // The last of phases starting together wins
+        assert_eq!(timeline.phase_at(30.), Some(2));
+
+        let mut director = SpawnDirector::new(timeline);
+        let mut enemies = Vec::new();
+        run(&mut director, &mut enemies, 0., 10., 0.1);
+        assert!(count(&enemies, "slime") > 0);
+        assert_eq!(count(&enemies, "bat"), 0);
+        enemies.clear();
+        run(&mut director, &mut enemies, 30., 40., 0.1);
+        assert_eq!(count(&enemies, "slime"), 0);
+        assert_eq!(count(&enemies, "tower"), 0);
+        assert!(count(&enemies, "bat") > 0);
+    }
+
+    #[test]
+    fn rules_stop_at_their_cap() {
+        let mut director = SpawnDirector::new(timeline(r#"(phases: [
+            (at: 0.0, spawns: [(enemy: "slime", cap: 5, per_second: 100.0, pattern: AroundPlayer)]),
+        ])"#));
+        let mut enemies = Vec::new();
+        run(&mut director, &mut enemies, 0., 5., 1. / 60.);
+        assert_eq!(count(&enemies, "slime"), 5);
+        // A freed spot fills up again, never past the cap
+        enemies.pop();
+        run(&mut director, &mut enemies, 5., 5.1, 1. / 60.);
+        assert_eq!(count(&enemies, "slime"), 5);
+    }
+
+    #[test]
+    fn events_fire_once_even_when_skipped_past() {
+        let mut director = SpawnDirector::new(timeline(r#"(phases: [], events: [
+            (at: 120.0, enemy: "bat", count: 8, pattern: Ring(radius: 50.0)),
+        ])"#));
+        let mut enemies = Vec::new();
+        run(&mut director, &mut enemies, 0., 119., 1.);
+        assert_eq!(count(&enemies, "bat"), 0);
+        // One long step from before 2:00 to well past it
+        run(&mut director, &mut enemies, 119., 150., 31.);
+        assert_eq!(count(&enemies, "bat"), 8);
+        run(&mut director, &mut enemies, 150., 200., 1.);
+        assert_eq!(count(&enemies, "bat"), 8);
+
+        director.reset();
+        enemies.clear();
+        run(&mut director, &mut enemies, 0., 121., 1.);
+        assert_eq!(count(&enemies, "bat"), 8);
+    }
+
+    #[test]
+    fn broken_timelines_are_rejected() {
+        for ron in [
+            r#"(phases: [(at: 60.0, spawns: []), (at: 30.0, spawns: [])])"#,
+            r#"(phases: [(at: NaN, spawns: [])])"#,
+            r#"(phases: [(at: 0.0, spawns: [(enemy: "slime", cap: 5, per_second: NaN, pattern: AroundPlayer)])])"#,
+            r#"(phases: [(at: 0.0, spawns: [(enemy: "slime", cap: 5, per_second: 0.0, pattern: AroundPlayer)])])"#,
+            r#"(phases: [(at: 0.0, spawns: [(enemy: "slime", cap: 5, per_second: -1.0, pattern: AroundPlayer)])])"#,
+            r#"(phases: [], events: [(at: -5.0, enemy: "bat", count: 1, pattern: SideSweep)])"#,
+        ] {
+            assert!(matches!(ron.parse::<Timeline>(), Err(DefError::Invalid(_))), "{} was let through", ron);
+        }
+        let unknown = timeline(r#"(phases: [(at: 0.0, spawns: [(enemy: "ghost", cap: 5, per_second: 1.0, pattern: AroundPlayer)])])"#);
+        assert!(unknown.check_enemies(&EnemyDefs::builtin()).is_err());
+        assert!(Timeline::builtin().check_enemies(&EnemyDefs::builtin()).is_ok());
+    }
+}
@@ -0,0 +1,44 @@
+use std::collections::BTreeMap;
+
+use serde::{Serialize, Deserialize};
+
+use crate::events::GameEvent;
+
+/// Damage source of every enemy projectile, whoever fired it
+pub const PROJECTILE: &str = "projectile";
+
+/// Tally of a run, what balance reports are made of
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RunStats {
+    /// Kills by enemy kind
+    pub kills: BTreeMap<String, u32>,
+    /// Damage the player took, by enemy kind or [`PROJECTILE`]
+    pub damage_taken: BTreeMap<String, f32>,
+    /// What hit the player last, what killed them if they died
+    pub last_hit_by: Option<String>,
+    /// XP collected so far, level ups don't take it away
+    pub xp_gained: f32,
+    /// Earned by skipping level ups
+    #[serde(default)]
+    pub gold: u32,
+}
+
+impl RunStats {
+    pub fn record(&mut self, event: &GameEvent) {
+        match event {
+            GameEvent::EnemyKilled { kind, .. } => self.record_kill(kind),
+            GameEvent::XpCollected { amount } => self.xp_gained += amount,
+            GameEvent::PlayerHit { source, amount, .. } => self.record_hit(source, *amount),
+            _ => {}
+        }
+    }
+
+    pub fn record_kill(&mut self, kind: &str) {
+        *self.kills.entry(kind.to_string()).or_default() += 1;
+    }
+
+    pub fn record_hit(&mut self, source: &str, amount: f32) {
+        *self.damage_taken.entry(source.to_string()).or_default() += amount;
+        self.last_hit_by = Some(source.to_string());
+    }
+}
@@ -34,11 +34,11 @@ pub struct StopWatch {
 impl StopWatch {
     /// Start a stopwatch.
     ///
-    /// ```
+    /// ```no_run
     /// use std::time::Duration;
     /// use std::thread::sleep;
     ///
-    /// let mut sw = stopwatch_rs::StopWatch::start();
+    /// let mut sw = lowrez2023::stopwatch::StopWatch::start();
     /// sleep(Duration::from_secs(1));
     /// let sp1 = sw.split(); // split=1s, lap=1s
     /// sw.suspend();
@@ -1,11 +1,12 @@
 use instant::Duration;
+use serde::{Serialize, Deserialize};
 
 /// A Stopwatch is a struct that track elapsed time when started.
 ///
 /// # Examples
 ///
 /// ```
-/// # use bevy_time::*;
+/// # use lowrez2023::stopwatch_bevy::*;
 /// use std::time::Duration;
 /// let mut stopwatch = Stopwatch::new();
 /// assert_eq!(stopwatch.elapsed_secs(), 0.0);
@@ -21,7 +22,7 @@ use instant::Duration;
 /// assert!(stopwatch.paused());
 /// assert_eq!(stopwatch.elapsed_secs(), 0.0);
 /// ```
-#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
 pub struct Stopwatch {
     elapsed: Duration,
     paused: bool,
@@ -32,7 +33,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// let stopwatch = Stopwatch::new();
     /// assert_eq!(stopwatch.elapsed_secs(), 0.0);
     /// assert_eq!(stopwatch.paused(), false);
@@ -46,7 +47,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// use std::time::Duration;
     /// let mut stopwatch = Stopwatch::new();
     /// stopwatch.tick(Duration::from_secs(1));
@@ -67,7 +68,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// use std::time::Duration;
     /// let mut stopwatch = Stopwatch::new();
     /// stopwatch.tick(Duration::from_secs(1));
@@ -99,7 +100,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// use std::time::Duration;
     /// let mut stopwatch = Stopwatch::new();
     /// stopwatch.set_elapsed(Duration::from_secs_f32(1.0));
@@ -116,7 +117,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// use std::time::Duration;
     /// let mut stopwatch = Stopwatch::new();
     /// stopwatch.tick(Duration::from_secs_f32(1.5));
@@ -134,7 +135,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// use std::time::Duration;
     /// let mut stopwatch = Stopwatch::new();
     /// stopwatch.pause();
@@ -151,7 +152,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// use std::time::Duration;
     /// let mut stopwatch = Stopwatch::new();
     /// stopwatch.pause();
@@ -170,7 +171,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// let mut stopwatch = Stopwatch::new();
     /// assert!(!stopwatch.paused());
     /// stopwatch.pause();
@@ -187,7 +188,7 @@ impl Stopwatch {
     ///
     /// # Examples
     /// ```
-    /// # use bevy_time::*;
+    /// # use lowrez2023::stopwatch_bevy::*;
     /// use std::time::Duration;
     /// let mut stopwatch = Stopwatch::new();
     /// stopwatch.tick(Duration::from_secs_f32(1.5));
@@ -0,0 +1,179 @@
+use std::fs::{self, File};
+use std::io::{self, BufWriter, Write};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::difficulty::Preset;
+use crate::events::GameEvent;
+use crate::player::Player;
+use crate::world::World;
+
+/// Where `--telemetry` logs go, one file per run
+pub const TELEMETRY_DIR: &str = "telemetry";
+/// Seconds between two samples of the player's position and HP
+const SAMPLE_INTERVAL: f32 = 0.5;
+
+/// One line of the log. Every line also has `t`, seconds into the run.
+#[derive(Debug, Clone, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+enum Entry<'a> {
+    RunStarted { seed: u64, difficulty: Preset },
+    EnemySpawned { kind: &'a str, x: f32, y: f32 },
+    EnemyDamaged { kind: &'a str, x: f32, y: f32, amount: f32 },
+    EnemyKilled { kind: &'a str, x: f32, y: f32, xp: f32 },
+    PlayerHit { source: &'a str, x: f32, y: f32, amount: f32 },
+    PlayerDied { cause: Option<&'a str> },
+    XpCollected { amount: f32 },
+    VacuumCollected,
+    LevelUp { level: i32 },
+    DashStarted { dir_x: f32, dir_y: f32, x: f32, y: f32 },
+    ProjectileFired { x: f32, y: f32, by_player: bool },
+    UpgradeOffered { upgrades: Vec<&'a str> },
+    UpgradeChosen { upgrade: &'a str },
+    Sample { x: f32, y: f32, hp: f32, xp: f32, enemies: usize },
+    RunEnded { survived: bool, kills: i32, level: i32 },
+}
+
+#[derive(Serialize)]
+struct Line<'a> {
+    t: f32,
+    #[serde(flatten)]
+    entry: Entry<'a>,
+}
+
+/// Writes what happens during a run to a JSON Lines file, for working out
+/// where and why runs end. Stops quietly after the first write error.
+pub struct TelemetryRecorder {
+    path: PathBuf,
+    writer: Option<BufWriter<File>>,
+    next_sample: f32,
+}
+
+impl TelemetryRecorder {
+    pub fn create<P: AsRef<Path>>(path: P, world: &World) -> io::Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        if let Some(dir) = path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let mut recorder = TelemetryRecorder {
+            writer: Some(BufWriter::new(File::create(&path)?)),
+            path,
+            next_sample: 0.,
+        };
+        recorder.write(world, Entry::RunStarted { seed: world.seed(), difficulty: world.difficulty.preset });
+        Ok(recorder)
+    }
+
+    /// `telemetry/run-<seed>-<unix secs>.jsonl`
+    pub fn path_for(seed: u64) -> PathBuf {
+        let now = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |d| d.as_secs());
+        Path::new(TELEMETRY_DIR).join(format!("run-{}-{}.jsonl", seed, now))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    fn write(&mut self, world: &World, entry: Entry) {
+        let Some(writer) = self.writer.as_mut() else { return };
+        let line = Line { t: world.stopwatch.elapsed_secs(), entry };
+        let result = serde_json::to_writer(&mut *writer, &line)
+            .map_err(io::Error::from)
+            .and_then(|_| writer.write_all(b"\n"));
+        if let Err(e) = result {
+            eprintln!("{}: {}, not recording telemetry anymore", self.path.display(), e);
+            self.writer = None;
+        }
+    }
+
+    /// Everything that happened during the step that just ran
+    pub fn record_step(&mut self, world: &World, player: &Player) {
+        for event in world.events.iter() {
+            let entry = match event {
+                GameEvent::EnemySpawned { kind, x, y } => Entry::EnemySpawned { kind, x: *x, y: *y },
+                GameEvent::EnemyDamaged { kind, x, y, amount } => Entry::EnemyDamaged { kind, x: *x, y: *y, amount: *amount },
+                GameEvent::EnemyKilled { kind, x, y, xp } => Entry::EnemyKilled { kind, x: *x, y: *y, xp: *xp },
+                GameEvent::PlayerHit { source, x, y, amount } => Entry::PlayerHit { source, x: *x, y: *y, amount: *amount },
+                GameEvent::PlayerDied => Entry::PlayerDied { cause: world.stats.last_hit_by.as_deref() },
+                GameEvent::XpCollected { amount } => Entry::XpCollected { amount: *amount },
+                GameEvent::VacuumCollected => Entry::VacuumCollected,
+                GameEvent::LevelUp { level } => Entry::LevelUp { level: *level },
+                GameEvent::DashStarted { dir_x, dir_y, x, y } => Entry::DashStarted { dir_x: *dir_x, dir_y: *dir_y, x: *x, y: *y },
+                GameEvent::ProjectileFired { x, y, by_player } => Entry::ProjectileFired { x: *x, y: *y, by_player: *by_player },
+                // The run ending covers it, once
+                GameEvent::StageCleared => continue,
+            };
+            self.write(world, entry);
+        }
+
+        if player.active && world.stopwatch.elapsed_secs() >= self.next_sample {
+            self.next_sample = world.stopwatch.elapsed_secs() + SAMPLE_INTERVAL;
+            self.write(world, Entry::Sample {
+                x: player.pos_x,
+                y: player.pos_y,
+                hp: player.hp,
+                xp: player.xp,
+                enemies: world.enemies.len(),
+            });
+        }
+    }
+
+    pub fn record_upgrades_offered(&mut self, world: &World, upgrades: &[&str]) {
+        self.write(world, Entry::UpgradeOffered { upgrades: upgrades.to_vec() });
+    }
+
+    pub fn record_upgrade_chosen(&mut self, world: &World, upgrade: &str) {
+        self.write(world, Entry::UpgradeChosen { upgrade });
+    }
+
+    /// Last line of the log, flushed right away
+    pub fn finish(mut self, world: &World, player: &Player) {
+        self.write(world, Entry::RunEnded { survived: player.active, kills: world.kill_count, level: player.level });
+        if let Some(mut writer) = self.writer.take() {
+            if let Err(e) = writer.flush() {
+                eprintln!("{}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::input::InputState;
+
+    #[test]
+    fn every_line_is_json_with_a_time_and_an_event() {
+        let path = std::env::temp_dir().join(format!("lowrez2023-telemetry-{}.jsonl", std::process::id()));
+        let mut player = Player::new();
+        let mut world = World::new(4);
+        let mut recorder = TelemetryRecorder::create(&path, &world).unwrap();
+        for _ in 0..120 {
+            world.step(&mut player, 1. / 60., &InputState { move_x: 1., ..Default::default() });
+            recorder.record_step(&world, &player);
+        }
+        recorder.record_upgrades_offered(&world, &["Speed", "Dash"]);
+        recorder.record_upgrade_chosen(&world, "Dash");
+        recorder.finish(&world, &player);
+
+        let log = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        let lines: Vec<serde_json::Value> = log.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        let mut last_t = 0.;
+        for line in lines.iter() {
+            let t = line["t"].as_f64().unwrap_or_else(|| panic!("no t in {}", line));
+            assert!(t >= last_t, "{} goes back in time", line);
+            last_t = t;
+            assert!(line["event"].is_string(), "no event in {}", line);
+        }
+        let events: Vec<&str> = lines.iter().map(|l| l["event"].as_str().unwrap()).collect();
+        assert_eq!(events.first(), Some(&"run_started"));
+        assert!(events.contains(&"sample"));
+        assert!(events.contains(&"enemy_spawned"));
+        assert_eq!(events[events.len() - 3..], ["upgrade_offered", "upgrade_chosen", "run_ended"]);
+        assert_eq!(lines.last().unwrap()["survived"], true);
+    }
+}
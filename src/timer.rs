@@ -1,30 +1,33 @@
 use std::time::Duration;
-use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
 
-#[derive(Debug, Clone, PartialEq)]
+/// Counts from the moment it was started. It keeps no clock of its own:
+/// every call gets the current time, in seconds, from whoever owns the
+/// clock, which is the [`World`](crate::world::World)'s for anything in a
+/// run and [`real_now`](crate::clock::real_now) for menus.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Timer{
 	pub duration: Duration,
 	start_time: f64,
 }
 
 impl Timer{
-	pub fn new(duration_millis: u64)-> Timer{
+	pub fn new(duration_millis: u64, now: f64)-> Timer{
 		Timer{
 			duration: Duration::from_millis(duration_millis),
-			start_time: miniquad::date::now(),
+			start_time: now,
 		}
 	}
 
-	pub fn new_sec(duration_sec: u64)-> Timer{
+	pub fn new_sec(duration_sec: u64, now: f64)-> Timer{
 		Timer{
 			duration: Duration::from_secs(duration_sec),
-			start_time: miniquad::date::now(),
+			start_time: now,
 		}
 	}
 
-	pub fn finished(&self) -> bool{
-		let current_time = miniquad::date::now();
-		let elapsed = current_time - self.start_time;
+	pub fn finished(&self, now: f64) -> bool{
+		let elapsed = now - self.start_time;
 		elapsed >= self.duration.as_secs_f64()
 	}
 
@@ -37,23 +40,21 @@ impl Timer{
 		self.duration = Duration::from_secs(duration);
 	}
 
-	pub fn restart(&mut self){
-		self.start_time = miniquad::date::now();
+	pub fn restart(&mut self, now: f64){
+		self.start_time = now;
 	}
 
-	pub fn elapsed(&self) -> Duration {
-		let current_time = miniquad::date::now();
-		let elapsed = current_time - self.start_time;
-		Duration::from_secs_f64(elapsed)
+	pub fn elapsed(&self, now: f64) -> Duration {
+		let elapsed = now - self.start_time;
+		Duration::from_secs_f64(elapsed.max(0.))
 	}
 
-	pub fn value(&self)-> f32{
-		let current_time = miniquad::date::now();
-		let elapsed = current_time - self.start_time;
+	pub fn value(&self, now: f64)-> f32{
+		let elapsed = now - self.start_time;
 		if elapsed < self.duration.as_secs_f64(){
 			1.0 * (100.0 / self.duration.as_millis() as f32 * elapsed as f32) / 100.0
 		}else{
 			1.0
 		}
 	}
-}
\ No newline at end of file
+}
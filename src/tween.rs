@@ -14,9 +14,9 @@ pub struct Tween {
 
 #[allow(dead_code)]
 impl Tween {
-    pub fn from_keyframes(keyframes: Vec<Keyframe<f32>>, start_at_sec: u64, duration_sec: u64, repeat: bool) -> Tween {
+    pub fn from_keyframes(keyframes: Vec<Keyframe<f32>>, start_at_sec: u64, duration_sec: u64, repeat: bool, now: f64) -> Tween {
         let sequence = AnimationSequence::from(keyframes);
-        let timer = Timer::new_sec(duration_sec);
+        let timer = Timer::new_sec(duration_sec, now);
         Self {
             timer,
             sequence: Some(sequence),
@@ -24,8 +24,8 @@ impl Tween {
         }
     }
 
-    pub fn restart(&mut self) {
-        self.timer.restart();
+    pub fn restart(&mut self, now: f64) {
+        self.timer.restart(now);
     }
 
     pub fn finished(&self) -> bool {
@@ -36,13 +36,13 @@ impl Tween {
         }
     }
 
-    pub fn update(&mut self) {
+    pub fn update(&mut self, now: f64) {
         if let Some(s) = self.sequence.as_mut() {
-            s.advance_to(self.timer.value() as f64);
+            s.advance_to(self.timer.value(now) as f64);
         }
 
-        if self.timer.finished() && self.repeat {
-            self.timer.restart();
+        if self.timer.finished(now) && self.repeat {
+            self.timer.restart(now);
         }
     }
 
@@ -1,6 +1,7 @@
 use macroquad::prelude::*;
 
 use crate::{timer::Timer, Upgrade, tween::Tween, TestTween};
+use crate::clock;
 
 pub fn draw_level_ui(
     texture: Texture2D,
@@ -78,6 +79,44 @@ pub fn draw_level_timer_ui(
 )
 }
 
+/// Gold earned this run, in the top right corner once there is any
+pub fn draw_gold_ui(font: Font, gold: u32) {
+    if gold == 0 {
+        return;
+    }
+    draw_text_ex(
+        &format!("{}g", gold),
+        screen_width() - 150.,
+        50.,
+        TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., ..Default::default()}
+    );
+}
+
+/// Dims whatever is on screen and lists `items` under `title`, the
+/// `selected` one highlighted
+pub fn draw_pause_menu(font: Font, title: &str, items: &[String], selected: usize) {
+    draw_rectangle(0., 0., screen_width(), screen_height(), Color::new(0., 0., 0., 0.6));
+    draw_text_ex(
+        title,
+        (screen_width() / 2.) - 150.,
+        180.,
+        TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., ..Default::default()}
+    );
+    for (i, item) in items.iter().enumerate() {
+        let (text, color) = if i == selected {
+            (format!("> {}", item), WHITE)
+        } else {
+            (format!("  {}", item), Color::from_hex(0x905ea9))
+        };
+        draw_text_ex(
+            &text,
+            (screen_width() / 2.) - 250.,
+            300. + 70. * i as f32,
+            TextParams { font, font_size: 48, font_scale: 1., font_scale_aspect: 1., color, ..Default::default()}
+        );
+    }
+}
+
 pub fn draw_level_up_title(
     font: Font,
     _tween: &mut TestTween<f32, f32>,
@@ -148,38 +187,88 @@ pub fn draw_level_up_title(
     // );
 }
 
+/// Buttons under the level up cards, left to right
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelUpButton {
+    Reroll,
+    Banish,
+    Skip,
+}
+
+pub const LEVEL_UP_BUTTONS: [LevelUpButton; 3] = [LevelUpButton::Reroll, LevelUpButton::Banish, LevelUpButton::Skip];
+
+/// What the buttons under the level up cards show
+pub struct LevelUpOptions {
+    pub rerolls_left: u32,
+    pub banishes_left: u32,
+    /// What skipping gives
+    pub skip: String,
+    /// The next card confirmed gets banished instead of picked
+    pub banishing: bool,
+}
+
+/// `choosen_upgrade_index` runs over the cards, then the buttons
 pub fn draw_level_up(
     choosen_upgrade_index: &i32,
     available_upgrades: &Vec<Box<dyn Upgrade>>,
+    options: &LevelUpOptions,
     font: Font,
+    upgrade_texture: Texture2D,
     tween: &mut Tween,
     _init_tween: &mut TestTween<f32, f32>
 ) {
     // Level UP UI
-    tween.update();
+    tween.update(clock::real_now());
     draw_rectangle(0., 0., screen_width(), screen_height(), Color::new(0., 0., 0., 0.8));
+    let count = available_upgrades.len().max(1) as f32;
+    // Two cards fit at full size, more of them shrink to fit
+    let scale = ((screen_width() - 80.) / (count * 220. - 40.)).min(1.);
+    let upgrade_w = 220. * scale;
+    let start = (screen_width() - (count * upgrade_w - 40. * scale)) / 2.;
     for (i, upgrade) in available_upgrades.iter().enumerate() {
-        let delta = get_frame_time();
         let f = i as f32;
-        let start = 120.;
-        let total_spacing = 40.; // It will be the amount of upgrade available
-        let upgrade_w = 180. + total_spacing;
-        // let upgrade_h = 160.;
-        // let mut x_pos = (screen_width() / 2.) - 245.;
-        // let y_pos = start + f * (upgrade_h);
         let x_pos = start + f * (upgrade_w);
         let mut y_pos = screen_height() / 2.;
-        // init_tween.move_by(delta);
-        // if init_tween.is_finished() {
-        // }
         if *choosen_upgrade_index == (i as i32) {
             y_pos -= 50. + tween.value();
         }
         upgrade.draw(
             font, 
+            upgrade_texture,
             x_pos,
             y_pos, 
+            scale,
             *choosen_upgrade_index == (i as i32)
         );
     }
+
+    for (i, button) in LEVEL_UP_BUTTONS.iter().enumerate() {
+        let (label, usable) = match button {
+            LevelUpButton::Reroll => (format!("Reroll {}", options.rerolls_left), options.rerolls_left > 0),
+            LevelUpButton::Banish => (format!("Banish {}", options.banishes_left), options.banishes_left > 0),
+            LevelUpButton::Skip => (format!("Skip {}", options.skip), true),
+        };
+        let highlighted = *choosen_upgrade_index == (available_upgrades.len() + i) as i32;
+        let color = if highlighted {
+            WHITE
+        } else if usable {
+            GRAY
+        } else {
+            DARKGRAY
+        };
+        draw_text_ex(
+            &label,
+            40. + 200. * i as f32,
+            screen_height() - 70.,
+            TextParams { font, font_size: 32, font_scale: 1., font_scale_aspect: 1., color, ..Default::default()}
+        );
+    }
+    if options.banishing {
+        draw_text_ex(
+            "pick a card to banish",
+            40.,
+            screen_height() - 30.,
+            TextParams { font, font_size: 32, font_scale: 1., font_scale_aspect: 1., color: Color::from_hex(0xf04f78), ..Default::default()}
+        );
+    }
 }
@@ -1,301 +1,354 @@
+use std::collections::HashSet;
+use std::str::FromStr;
+
 use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::defs::{self, DefError};
+use crate::player::Player;
+use crate::player_stats::Modifier;
+use crate::rng::Rng;
+use crate::world::World;
+
+const BUILTIN: &str = include_str!("../assets/upgrades.ron");
+
+/// How likely an upgrade is to be offered on level up, rarer ones show up
+/// less
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Rarity {
+    #[default]
+    Common,
+    Rare,
+    Epic,
+}
+
+impl Rarity {
+    /// Odds of being drawn relative to the other upgrades in the pool
+    pub fn weight(&self) -> u32 {
+        match self {
+            Rarity::Common => 10,
+            Rarity::Rare => 4,
+            Rarity::Epic => 1,
+        }
+    }
+
+    pub fn color(&self) -> Color {
+        match self {
+            Rarity::Common => Color::from_hex(0xc7dcd0),
+            Rarity::Rare => Color::from_hex(0x1ebc73),
+            Rarity::Epic => Color::from_hex(0xf9c22b),
+        }
+    }
+}
+
+/// Outlines a card drawn at `x`, `y` in the color of its rarity
+pub fn draw_rarity_border(rarity: Rarity, x: f32, y: f32, scale: f32) {
+    draw_rectangle_lines(x, y - 50. * scale, 180. * scale, 180. * scale, 6. * scale, rarity.color());
+}
 
 pub trait Upgrade {
-    fn get_name(&self) -> &'static str;
-    fn draw(&self, font: Font, x: f32, y: f32, highlighted: bool);
+    fn get_name(&self) -> &str;
+    fn rarity(&self) -> Rarity {
+        Rarity::Common
+    }
+    /// Draws its card at `scale` times the full 180 pixel width
+    fn draw(&self, font: Font, texture: Texture2D, x: f32, y: f32, scale: f32, highlighted: bool);
+    fn apply(&self, player: &mut Player, world: &mut World);
     fn get_color(&self) -> Color {
         Color::from_hex(0x3e3546)
     }
 }
 
-pub struct SpeedUpgrade {}
-pub struct FireRateUpgrade {}
-pub struct IncreasedRegenUpgrade {}
-pub struct FasterRegenUpgrade {}
-pub struct DashUpgrade {}
-pub struct IframeUpgrade {}
+/// One upgrade out of `assets/upgrades.ron`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct UpgradeDef {
+    pub id: String,
+    /// Lines of the card, top to bottom
+    pub text: Vec<String>,
+    /// `(x, y, w, h)` of its label in vs-dx-upgrades-atlas.png
+    #[serde(default)]
+    pub icon: Option<(f32, f32, f32, f32)>,
+    pub modifiers: Vec<Modifier>,
+    #[serde(default)]
+    pub rarity: Rarity,
+    /// How many times it can be picked in a run, no limit when left out
+    #[serde(default)]
+    pub max_stacks: Option<u32>,
+    /// Ids of the upgrades that have to be picked before this one shows up
+    #[serde(default)]
+    pub prerequisites: Vec<String>,
+}
 
-fn draw_upgrade_bg(w: f32, h: f32, x: f32, y: f32) {
+fn draw_upgrade_bg(w: f32, h: f32, x: f32, y: f32, scale: f32) {
     draw_rectangle(
-        x, 
-        y-50., 
-        w, 
-        h, 
+        x,
+        y-50.*scale,
+        w*scale,
+        h*scale,
         Color::from_hex(0x905ea9)
     );
     draw_rectangle(
-        (x+10.), 
-        (y-10.)-50., 
-        w-20., 
-        h+20., 
+        x+10.*scale,
+        y-60.*scale,
+        (w-20.)*scale,
+        (h+20.)*scale,
         Color::from_hex(0x905ea9)
-    );  
+    );
 }
 
-impl Upgrade for SpeedUpgrade {
-    fn get_name(&self) -> &'static str {
-        "Speed"
+impl Upgrade for UpgradeDef {
+    fn get_name(&self) -> &str {
+        &self.id
+    }
+
+    fn rarity(&self) -> Rarity {
+        self.rarity
     }
 
-    fn draw(&self, font: Font, x: f32, y: f32, highlighted: bool) {
+    fn draw(&self, font: Font, texture: Texture2D, x: f32, y: f32, scale: f32, highlighted: bool) {
         let mut font_color = self.get_color();
         if highlighted {
             font_color = WHITE;
         }
-        draw_upgrade_bg(180., 180., x, y);
-        draw_text_ex(
-            "5%",
-            x, 
-            y, 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "spd",
-            x, 
-            y+50., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-    }   
-}
-
-impl Upgrade for FireRateUpgrade {
-    fn get_name(&self) -> &'static str {
-        "FireRate"
+        draw_upgrade_bg(180., 180., x, y, scale);
+        draw_rarity_border(self.rarity, x, y, scale);
+        for (i, line) in self.text.iter().enumerate() {
+            draw_text_ex(
+                line,
+                x,
+                y + 50. * scale * i as f32,
+                TextParams { font, font_size: 64, font_scale: scale, font_scale_aspect: 1., color: font_color, ..Default::default()}
+            );
+        }
+        // Label right under the card
+        if let Some((ix, iy, iw, ih)) = self.icon {
+            let tint = if highlighted { WHITE } else { GRAY };
+            let zoom = 3. * scale;
+            draw_texture_ex(
+                texture,
+                x + (180. * scale - iw * zoom) / 2.,
+                y + 150. * scale,
+                tint,
+                DrawTextureParams {
+                    dest_size: Some(vec2(iw * zoom, ih * zoom)),
+                    source: Some(Rect::new(ix, iy, iw, ih)),
+                    ..Default::default()
+                }
+            );
+        }
     }
-    fn draw(&self, font: Font, x: f32, y: f32, highlighted: bool) {
-        let mut font_color = self.get_color();
-        if highlighted {
-            font_color = WHITE;
+
+    fn apply(&self, player: &mut Player, _world: &mut World) {
+        for modifier in self.modifiers.iter() {
+            player.stats.add(&self.id, *modifier);
         }
-        draw_upgrade_bg(180., 180., x, y);      
-        draw_text_ex(
-            "+1%",
-            x, 
-            y, 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Fire",
-            x, 
-            y+50., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Rate",
-            x, 
-            y+100., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );        
-    }  
+        player.recalculate_stats();
+    }
 }
 
-impl Upgrade for IncreasedRegenUpgrade {
-    fn get_name(&self) -> &'static str {
-        "Recovery"
-    }
-    fn draw(&self, font: Font, x: f32, y: f32, highlighted: bool) {
-        let mut font_color = self.get_color();
-        if highlighted {
-            font_color = WHITE;
+impl UpgradeDef {
+    fn validate(&self, ids: &HashSet<&str>) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("id can't be empty".to_string());
+        }
+        if self.text.is_empty() {
+            return Err("needs at least one line of text".to_string());
+        }
+        if let Some((x, y, w, h)) = self.icon {
+            if [x, y, w, h].iter().any(|v| !v.is_finite()) || x < 0. || y < 0. || w <= 0. || h <= 0. {
+                return Err(format!("icon ({}, {}, {}, {}) is empty or outside the atlas", x, y, w, h));
+            }
+        }
+        if self.modifiers.is_empty() {
+            return Err("doesn't modify anything".to_string());
+        }
+        if let Some(m) = self.modifiers.iter().find(|m| !m.value().is_finite()) {
+            return Err(format!("{:?} isn't a number", m));
         }
-        draw_upgrade_bg(180., 180., x, y);  
-        draw_text_ex(
-            "+1%",
-            x, 
-            y, 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Inc.",
-            x, 
-            y+50., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Regen",
-            x, 
-            y+100., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-    }  
+        if self.max_stacks == Some(0) {
+            return Err("max_stacks must be above 0, leave it out for no limit".to_string());
+        }
+        for prerequisite in self.prerequisites.iter() {
+            if *prerequisite == self.id {
+                return Err("can't be its own prerequisite".to_string());
+            }
+            if !ids.contains(prerequisite.as_str()) {
+                return Err(format!("prerequisite \"{}\" doesn't exist", prerequisite));
+            }
+        }
+        Ok(())
+    }
 }
 
-impl Upgrade for DashUpgrade {
-    fn get_name(&self) -> &'static str {
-        "Dash"
-    }
-    fn draw(&self, font: Font, x: f32, y: f32, highlighted: bool) {
-        let mut font_color = self.get_color();
-        if highlighted {
-            font_color = WHITE;
-        }
-        draw_upgrade_bg(180., 180., x, y);  
-        draw_text_ex(
-            "+5%",
-            x, 
-            y, 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Fast",
-            x, 
-            y+50., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Dash",
-            x, 
-            y+100., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-    }  
+/// Every upgrade the game knows about, in file order
+#[derive(Debug, Clone, PartialEq)]
+pub struct UpgradeDefs {
+    defs: Vec<UpgradeDef>,
 }
 
-impl Upgrade for FasterRegenUpgrade {
-    fn get_name(&self) -> &'static str {
-        "FasterRecovery"
+impl UpgradeDefs {
+    pub fn builtin() -> Self {
+        BUILTIN.parse().expect("assets/upgrades.ron is broken")
     }
-    fn draw(&self, font: Font, x: f32, y: f32, highlighted: bool) {
-        let mut font_color = self.get_color();
-        if highlighted {
-            font_color = WHITE;
-        }
-        draw_upgrade_bg(180., 180., x, y);  
-        draw_text_ex(
-            "+5%",
-            x, 
-            y, 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Fast",
-            x, 
-            y+50., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Regen",
-            x, 
-            y+100., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-    }  
-}
 
-impl Upgrade for IframeUpgrade {
-    fn get_name(&self) -> &'static str {
-        "MoreIframes"
+    pub fn get(&self, id: &str) -> Option<&UpgradeDef> {
+        self.defs.iter().find(|d| d.id == id)
     }
-    fn draw(&self, font: Font, x: f32, y: f32, highlighted: bool) {
-        let mut font_color = self.get_color();
-        if highlighted {
-            font_color = WHITE;
+
+    pub fn iter(&self) -> impl Iterator<Item = &UpgradeDef> {
+        self.defs.iter()
+    }
+
+    /// Drops every upgrade `keep` says no to
+    pub fn retain<F: FnMut(&UpgradeDef) -> bool>(&mut self, keep: F) {
+        self.defs.retain(keep);
+    }
+
+    /// Upgrades that can still be offered after `chosen`: not stacked to
+    /// their limit yet, with every prerequisite picked already
+    pub fn available<'a>(&'a self, chosen: &'a [String]) -> impl Iterator<Item = &'a UpgradeDef> + 'a {
+        self.defs.iter().filter(move |def| {
+            let stacks = chosen.iter().filter(|c| **c == def.id).count() as u32;
+            def.max_stacks.is_none_or(|max| stacks < max)
+                && def.prerequisites.iter().all(|p| chosen.contains(p))
+        })
+    }
+}
+
+impl FromStr for UpgradeDefs {
+    type Err = DefError;
+
+    fn from_str(s: &str) -> Result<UpgradeDefs, DefError> {
+        let defs: Vec<UpgradeDef> = defs::from_ron(s)?;
+        let ids = defs::unique_ids(defs.iter().map(|d| d.id.as_str()))?;
+        for def in defs.iter() {
+            def.validate(&ids).map_err(|reason| DefError::InvalidDef { id: def.id.clone(), reason })?;
         }
-        draw_upgrade_bg(180., 180., x, y);  
-        draw_text_ex(
-            "+10%",
-            x, 
-            y, 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Inv.",
-            x, 
-            y+50., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-        draw_text_ex(
-            "Timer",
-            x, 
-            y+100., 
-            TextParams { font, font_size: 64, font_scale: 1., font_scale_aspect: 1., color: font_color, ..Default::default()}
-        );
-    }  
+        Ok(UpgradeDefs { defs })
+    }
 }
 
-#[derive(Clone)]
-enum PossibleUpgrades {
-    SpeedUpgrade,
-    FireRateUpgrade,
-    IncreasedRegenUpgrade,
-    DashUpgrade,
-    FasterRegenUpgrade,
-    IframeUpgrade
+/// Up to `count` different upgrades out of `available`, each drawn with the
+/// odds its rarity gives among the ones not drawn yet
+pub fn pick_random_upgrades(available: Vec<Box<dyn Upgrade>>, count: usize, rng: &mut Rng) -> Vec<Box<dyn Upgrade>> {
+    let mut pool: Vec<Box<dyn Upgrade>> = available;
+    let mut picked = Vec::new();
+    while picked.len() < count && !pool.is_empty() {
+        let total: u32 = pool.iter().map(|u| u.rarity().weight()).sum();
+        let mut roll = rng.gen_range_u32(0, total);
+        let mut index = 0;
+        while roll >= pool[index].rarity().weight() {
+            roll -= pool[index].rarity().weight();
+            index += 1;
+        }
+        picked.push(pool.remove(index));
+    }
+    picked
 }
 
-pub fn pick_random_upgrades() -> Vec<Box<dyn Upgrade>> {
-    let mut upgrades : Vec<Box<dyn Upgrade>> = Vec::new();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let mut gen = randomize::PCG32::seed(miniquad::date::now() as _, miniquad::date::now() as _);
+    fn pool(defs: &UpgradeDefs, chosen: &[String]) -> Vec<Box<dyn Upgrade>> {
+        defs.available(chosen).map(|def| Box::new(def.clone()) as Box<dyn Upgrade>).collect()
+    }
 
-    let index1 = randomize::RandRangeU32::new(0, 5).sample(&mut gen);
-    let mut index2 = randomize::RandRangeU32::new(0, 5).sample(&mut gen);
-    while index2 == index1 {
-        index2 = randomize::RandRangeU32::new(0, 5).sample(&mut gen);
+    fn names(upgrades: &[Box<dyn Upgrade>]) -> Vec<String> {
+        upgrades.iter().map(|u| u.get_name().to_string()).collect()
     }
 
-    // Get the enum variants based on the indices
-    let item1 = match index1 {
-        0 => PossibleUpgrades::SpeedUpgrade,
-        1 => PossibleUpgrades::DashUpgrade,
-        2 => PossibleUpgrades::FasterRegenUpgrade,
-        3 => PossibleUpgrades::IframeUpgrade,
-        4 => PossibleUpgrades::IncreasedRegenUpgrade,
-        5 => PossibleUpgrades::FireRateUpgrade,
-        _ => PossibleUpgrades::FireRateUpgrade,
-    };
-
-    let item2 = match index2 {
-        0 => PossibleUpgrades::SpeedUpgrade,
-        1 => PossibleUpgrades::DashUpgrade,
-        2 => PossibleUpgrades::IncreasedRegenUpgrade,
-        3 => PossibleUpgrades::FasterRegenUpgrade,
-        4 => PossibleUpgrades::IframeUpgrade,
-        5 => PossibleUpgrades::FireRateUpgrade,
-        _ => PossibleUpgrades::FireRateUpgrade,
-    };
-
-    match item1 {
-        PossibleUpgrades::DashUpgrade => {
-            upgrades.push(Box::new(DashUpgrade{}));
-        }
-        PossibleUpgrades::SpeedUpgrade => {
-            upgrades.push(Box::new(SpeedUpgrade{}));
-        }
-        PossibleUpgrades::FasterRegenUpgrade => {
-            upgrades.push(Box::new(FasterRegenUpgrade{}));
+    #[test]
+    fn every_upgrade_and_weapon_is_offered() {
+        let mut world = World::new(1);
+        let mut seen = HashSet::new();
+        for _ in 0..2000 {
+            seen.extend(names(&world.pick_upgrades()));
         }
-        PossibleUpgrades::IncreasedRegenUpgrade => {
-            upgrades.push(Box::new(IncreasedRegenUpgrade{}));
+        // The starting weapon is offered too, its next level that is
+        for id in world.upgrade_defs.iter().map(|d| &d.id).chain(world.weapon_defs.iter().map(|d| &d.id)) {
+            assert!(seen.contains(id), "{} is never offered", id);
         }
-        PossibleUpgrades::IframeUpgrade => {
-            upgrades.push(Box::new(DashUpgrade{}));
-        }
-        PossibleUpgrades::FireRateUpgrade => {
-            upgrades.push(Box::new(FireRateUpgrade{}));
-        }                                        
     }
 
-    match item2 {
-        PossibleUpgrades::DashUpgrade => {
-            upgrades.push(Box::new(DashUpgrade{}));
-        }
-        PossibleUpgrades::SpeedUpgrade => {
-            upgrades.push(Box::new(SpeedUpgrade{}));
-        }
-        PossibleUpgrades::FasterRegenUpgrade => {
-            upgrades.push(Box::new(FasterRegenUpgrade{}));
+    #[test]
+    fn picks_are_never_the_same_upgrade_twice() {
+        let defs = UpgradeDefs::builtin();
+        let mut rng = Rng::new(7, 0);
+        for count in 1..=defs.iter().count() + 1 {
+            for _ in 0..200 {
+                let picked = names(&pick_random_upgrades(pool(&defs, &[]), count, &mut rng));
+                assert_eq!(picked.len(), count.min(defs.iter().count()));
+                let distinct: HashSet<&String> = picked.iter().collect();
+                assert_eq!(distinct.len(), picked.len(), "{:?} has duplicates", picked);
+            }
         }
-        PossibleUpgrades::IncreasedRegenUpgrade => {
-            upgrades.push(Box::new(IncreasedRegenUpgrade{}));
+    }
+
+    #[test]
+    fn stacked_upgrades_leave_the_pool() {
+        let defs = UpgradeDefs::builtin();
+        let max = defs.get("FireRate").unwrap().max_stacks.unwrap() as usize;
+        let mut chosen = vec!["FireRate".to_string(); max - 1];
+        assert!(defs.available(&chosen).any(|d| d.id == "FireRate"));
+        chosen.push("FireRate".to_string());
+        assert!(defs.available(&chosen).all(|d| d.id != "FireRate"));
+        // Unlimited ones stay
+        let chosen = vec!["Speed".to_string(); 100];
+        assert!(defs.available(&chosen).any(|d| d.id == "Speed"));
+    }
+
+    #[test]
+    fn prerequisites_hold_upgrades_back_until_picked() {
+        let defs = UpgradeDefs::from_str(r#"[
+            (id: "Base", text: ["b"], modifiers: [Add(Regen, 1.0)]),
+            (id: "Next", text: ["n"], modifiers: [Add(Regen, 1.0)], prerequisites: ["Base"]),
+        ]"#).unwrap();
+        let available = |chosen: &[String]| -> Vec<String> { defs.available(chosen).map(|d| d.id.clone()).collect() };
+        assert_eq!(available(&[]), vec!["Base"]);
+        assert_eq!(available(&["Base".to_string()]), vec!["Base", "Next"]);
+    }
+
+    #[test]
+    fn self_and_unknown_prerequisites_are_rejected() {
+        let own = r#"[(id: "Loop", text: ["l"], modifiers: [Add(Regen, 1.0)], prerequisites: ["Loop"])]"#;
+        assert!(matches!(UpgradeDefs::from_str(own), Err(DefError::InvalidDef { id, .. }) if id == "Loop"));
+        let unknown = r#"[(id: "Orphan", text: ["o"], modifiers: [Add(Regen, 1.0)], prerequisites: ["Nowhere"])]"#;
+        assert!(matches!(UpgradeDefs::from_str(unknown), Err(DefError::InvalidDef { id, .. }) if id == "Orphan"));
+    }
+
+    #[test]
+    fn timer_stats_change_their_timers() {
+        let defs = UpgradeDefs::builtin();
+        let mut player = Player::new();
+        let mut world = World::new(1);
+        let (regen, iframes) = (player.regen_timer.duration, player.inv_timer.duration);
+        defs.get("FasterRecovery").unwrap().apply(&mut player, &mut world);
+        defs.get("MoreIframes").unwrap().apply(&mut player, &mut world);
+        assert_eq!(player.regen_timer.duration.as_millis(), (regen.as_millis() as f32 * 0.95).round() as u128);
+        assert_eq!(player.inv_timer.duration.as_millis(), (iframes.as_millis() as f32 * 1.1).round() as u128);
+    }
+
+    #[test]
+    fn draws_follow_rarity_weights() {
+        let defs = UpgradeDefs::from_str(r#"[
+            (id: "Common", text: ["c"], modifiers: [Add(Regen, 1.0)]),
+            (id: "Rare", text: ["r"], modifiers: [Add(Regen, 1.0)], rarity: Rare),
+            (id: "Epic", text: ["e"], modifiers: [Add(Regen, 1.0)], rarity: Epic),
+        ]"#).unwrap();
+        let total: u32 = defs.iter().map(|d| d.rarity.weight()).sum();
+        let draws = 60000;
+        let mut rng = Rng::new(3, 0);
+        let mut counts: std::collections::HashMap<String, u32> = Default::default();
+        for _ in 0..draws {
+            for name in names(&pick_random_upgrades(pool(&defs, &[]), 1, &mut rng)) {
+                *counts.entry(name).or_default() += 1;
+            }
         }
-        PossibleUpgrades::IframeUpgrade => {
-            upgrades.push(Box::new(DashUpgrade{}));
+        for def in defs.iter() {
+            let expected = def.rarity.weight() as f32 / total as f32;
+            let observed = counts[&def.id] as f32 / draws as f32;
+            assert!((observed - expected).abs() < 0.01, "{} drawn {} of the time, expected {}", def.id, observed, expected);
         }
-        PossibleUpgrades::FireRateUpgrade => {
-            upgrades.push(Box::new(FireRateUpgrade{}));
-        }                                        
-    }    
-
-    upgrades
+    }
 }
@@ -0,0 +1,801 @@
+use std::f32::consts::TAU;
+use std::str::FromStr;
+
+use macroquad::prelude::*;
+use serde::{Serialize, Deserialize};
+
+use crate::{Position, Bullet, dist};
+use crate::defs::{self, DefError};
+use crate::enemies::Enemy;
+use crate::events::{EventQueue, GameEvent};
+use crate::player::Player;
+use crate::player_stats::Stat;
+use crate::spatial::SpatialGrid;
+use crate::timer::Timer;
+use crate::upgrade::{Rarity, Upgrade, draw_rarity_border};
+use crate::world::World;
+
+const BUILTIN: &str = include_str!("../assets/weapons.ron");
+
+/// The player's damage before might, what weapon damage multiplies
+const BASE_DAMAGE: f32 = 2.;
+/// A weapon just picked up attacks this soon, whatever its cooldown
+const FIRST_ATTACK_MILLIS: u64 = 700;
+/// How far away the bolt looks for something to shoot at
+const BOLT_RANGE: f32 = 128.;
+/// How far from its center an orb hits
+const ORB_RADIUS: f32 = 3.;
+/// Seconds an arc stays on screen after swinging
+const SWING_SECS: f32 = 0.15;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum WeaponKind {
+    Bolt,
+    Orbit,
+    Arc,
+    Lance,
+}
+
+/// What a weapon does at one of its levels, see `assets/weapons.ron` for
+/// what each kind makes of them
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WeaponStats {
+    pub cooldown: u64,
+    #[serde(default = "one")]
+    pub projectiles: u32,
+    #[serde(default)]
+    pub spread: f32,
+    #[serde(default)]
+    pub speed: f32,
+    pub damage: f32,
+    #[serde(default)]
+    pub area: f32,
+    #[serde(default)]
+    pub pierce: u32,
+}
+
+fn one() -> u32 {
+    1
+}
+
+impl WeaponStats {
+    /// What these do with the player's might, cooldown, area and projectile
+    /// count on top
+    pub fn boosted(&self, player: &Player) -> WeaponStats {
+        WeaponStats {
+            cooldown: (self.cooldown as f32 * player.stat(Stat::Cooldown)) as u64,
+            projectiles: self.projectiles + player.stat(Stat::Projectiles).round() as u32,
+            damage: self.damage * BASE_DAMAGE * player.stat(Stat::Might),
+            area: self.area * player.stat(Stat::Area),
+            ..*self
+        }
+    }
+}
+
+/// One weapon out of `assets/weapons.ron`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeaponDef {
+    pub id: String,
+    pub kind: WeaponKind,
+    /// Lines of its level up card, top to bottom
+    pub text: Vec<String>,
+    #[serde(default)]
+    pub rarity: Rarity,
+    /// First level first
+    pub levels: Vec<WeaponStats>,
+}
+
+impl WeaponDef {
+    fn validate(&self) -> Result<(), String> {
+        if self.id.is_empty() {
+            return Err("id can't be empty".to_string());
+        }
+        if self.text.is_empty() {
+            return Err("needs at least one line of text".to_string());
+        }
+        if self.levels.is_empty() {
+            return Err("needs at least one level".to_string());
+        }
+        for (i, stats) in self.levels.iter().enumerate() {
+            let level = i + 1;
+            if stats.cooldown == 0 {
+                return Err(format!("level {}: cooldown must be above 0", level));
+            }
+            if stats.projectiles == 0 {
+                return Err(format!("level {}: needs at least one projectile", level));
+            }
+            let numbers = [stats.spread, stats.speed, stats.damage, stats.area];
+            if numbers.iter().any(|n| !n.is_finite() || *n < 0.) {
+                return Err(format!("level {}: spread, speed, damage and area can't be negative", level));
+            }
+        }
+        Ok(())
+    }
+
+    pub fn max_level(&self) -> usize {
+        self.levels.len()
+    }
+}
+
+/// Every weapon the game knows about and how many can be carried
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct WeaponDefs {
+    pub slots: usize,
+    /// Id of the weapon every run starts with
+    pub starting: String,
+    weapons: Vec<WeaponDef>,
+}
+
+impl WeaponDefs {
+    pub fn builtin() -> Self {
+        BUILTIN.parse().expect("assets/weapons.ron is broken")
+    }
+
+    pub fn get(&self, id: &str) -> Option<&WeaponDef> {
+        self.weapons.iter().find(|d| d.id == id)
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &WeaponDef> {
+        self.weapons.iter()
+    }
+
+    /// Drops every weapon `keep` says no to, but the starting one
+    pub fn retain<F: FnMut(&WeaponDef) -> bool>(&mut self, mut keep: F) {
+        let starting = self.starting.clone();
+        self.weapons.retain(|def| def.id == starting || keep(def));
+    }
+}
+
+impl FromStr for WeaponDefs {
+    type Err = DefError;
+
+    fn from_str(s: &str) -> Result<WeaponDefs, DefError> {
+        let defs: WeaponDefs = defs::from_ron(s)?;
+        let ids = defs::unique_ids(defs.weapons.iter().map(|d| d.id.as_str()))?;
+        for def in defs.weapons.iter() {
+            def.validate().map_err(|reason| DefError::InvalidDef { id: def.id.clone(), reason })?;
+        }
+        if defs.slots == 0 {
+            return Err(DefError::Invalid("slots must be at least 1".to_string()));
+        }
+        if !ids.contains(defs.starting.as_str()) {
+            return Err(DefError::Invalid(format!("starting weapon \"{}\" doesn't exist", defs.starting)));
+        }
+        Ok(defs)
+    }
+}
+
+/// What weapons get to hit during a step
+pub struct Field<'a> {
+    pub player: &'a Player,
+    pub enemies: &'a mut [Enemy],
+    /// Broadphase over `enemies`
+    pub grid: &'a SpatialGrid,
+    pub bullets: &'a mut Vec<Bullet>,
+    pub events: &'a mut EventQueue,
+    /// Game time, for the weapon cooldowns
+    pub now: f64,
+}
+
+impl Field<'_> {
+    fn player_center(&self) -> Vec2 {
+        vec2(self.player.pos_x + 2., self.player.pos_y + 2.)
+    }
+
+    /// Screen-space unit vector the player is facing
+    fn facing(&self) -> Vec2 {
+        if self.player.flip_x { vec2(-1., 0.) } else { vec2(1., 0.) }
+    }
+
+    fn fire(&mut self, origin: Vec2, direction: Vec2, stats: &WeaponStats) {
+        // Bullets fly against their dir
+        self.bullets.push(Bullet {
+            x: origin.x,
+            y: origin.y,
+            dir_x: -direction.x,
+            dir_y: -direction.y,
+            speed: stats.speed,
+            damage: stats.damage,
+            active: true,
+            radius: stats.area,
+            pierce: stats.pierce,
+            hit: Vec::new(),
+        });
+        self.events.emit(GameEvent::ProjectileFired { x: origin.x, y: origin.y, by_player: true });
+    }
+}
+
+/// What every weapon has: which one it is, its level and its cooldown
+#[derive(Debug, Clone)]
+pub struct WeaponCore {
+    pub def: WeaponDef,
+    /// 1 is the first level
+    pub level: usize,
+    pub cooldown: Timer,
+}
+
+impl WeaponCore {
+    fn new(def: &WeaponDef, now: f64) -> Self {
+        WeaponCore { def: def.clone(), level: 1, cooldown: Timer::new(FIRST_ATTACK_MILLIS, now) }
+    }
+
+    pub fn stats(&self) -> &WeaponStats {
+        &self.def.levels[self.level - 1]
+    }
+
+    /// Its stats at the current level, as `player` wields them
+    pub fn boosted(&self, player: &Player) -> WeaponStats {
+        self.stats().boosted(player)
+    }
+
+    /// Whether the cooldown ran out, restarting it for `stats` when it did
+    fn ready(&mut self, stats: &WeaponStats, now: f64) -> bool {
+        if !self.cooldown.finished(now) {
+            return false;
+        }
+        self.cooldown.set_duration_millis(stats.cooldown);
+        self.cooldown.restart(now);
+        true
+    }
+}
+
+pub trait Weapon {
+    fn core(&self) -> &WeaponCore;
+    fn core_mut(&mut self) -> &mut WeaponCore;
+    /// Attacks whatever is due this step
+    fn update(&mut self, field: &mut Field, delta: f32);
+    /// Draws what isn't a bullet, in world space
+    fn draw(&self, _texture: Texture2D, _player: &Player) {}
+
+    fn id(&self) -> &str {
+        &self.core().def.id
+    }
+
+    fn level(&self) -> usize {
+        self.core().level
+    }
+
+    fn max_level(&self) -> usize {
+        self.core().def.max_level()
+    }
+
+    fn level_up(&mut self) {
+        let core = self.core_mut();
+        core.level = (core.level + 1).min(core.def.max_level());
+    }
+}
+
+pub fn make_weapon(def: &WeaponDef, now: f64) -> Box<dyn Weapon> {
+    let core = WeaponCore::new(def, now);
+    match def.kind {
+        WeaponKind::Bolt => Box::new(MagicBolt { core }),
+        WeaponKind::Orbit => Box::new(OrbitShield { core, angle: 0., recently_hit: Vec::new() }),
+        WeaponKind::Arc => Box::new(WhipArc { core, swings: Vec::new() }),
+        WeaponKind::Lance => Box::new(PiercingLance { core }),
+    }
+}
+
+/// `count` directions `spread` degrees apart, centered on `direction`
+fn fan(direction: Vec2, count: u32, spread: f32) -> impl Iterator<Item = Vec2> {
+    (0..count).map(move |i| {
+        let offset = (i as f32 - (count - 1) as f32 / 2.) * spread;
+        if offset == 0. {
+            direction
+        } else {
+            Vec2::from_angle(offset.to_radians()).rotate(direction)
+        }
+    })
+}
+
+/// Shoots at the closest enemy
+pub struct MagicBolt {
+    core: WeaponCore,
+}
+
+impl Weapon for MagicBolt {
+    fn core(&self) -> &WeaponCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut WeaponCore {
+        &mut self.core
+    }
+
+    fn update(&mut self, field: &mut Field, _delta: f32) {
+        let stats = self.core.boosted(field.player);
+        if !self.core.ready(&stats, field.now) || field.enemies.is_empty() {
+            return;
+        }
+        let (x, y) = (field.player.pos_x, field.player.pos_y);
+        let mut closest = BOLT_RANGE;
+        // Nothing in range shoots up-left
+        let mut aim = vec2(-1., -1.);
+        let mut nearby = Vec::new();
+        field.grid.query(Position { x, y }, BOLT_RANGE, &mut nearby);
+        for e in nearby.iter().map(|&i| &field.enemies[i]) {
+            let d = dist(Position { x, y }, e.position, closest);
+            if d < closest {
+                closest = d;
+                aim = vec2(e.position.x, e.position.y) - vec2(x, y);
+                if let Some(a) = aim.try_normalize() {
+                    aim = a;
+                }
+            }
+        }
+        for direction in fan(aim, stats.projectiles, stats.spread) {
+            field.fire(vec2(x + 2., y + 2.), direction, &stats);
+        }
+    }
+}
+
+/// Thrown the way the player faces, going through enemies
+pub struct PiercingLance {
+    core: WeaponCore,
+}
+
+impl Weapon for PiercingLance {
+    fn core(&self) -> &WeaponCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut WeaponCore {
+        &mut self.core
+    }
+
+    fn update(&mut self, field: &mut Field, _delta: f32) {
+        let stats = self.core.boosted(field.player);
+        if !self.core.ready(&stats, field.now) {
+            return;
+        }
+        let origin = field.player_center();
+        for direction in fan(field.facing(), stats.projectiles, stats.spread) {
+            field.fire(origin, direction, &stats);
+        }
+    }
+}
+
+/// Orbs circling the player
+pub struct OrbitShield {
+    core: WeaponCore,
+    /// Of the first orb, in radians
+    angle: f32,
+    /// Enemy ids and seconds until this can hit them again
+    recently_hit: Vec<(u64, f32)>,
+}
+
+impl OrbitShield {
+    fn orbs(&self, center: Vec2, stats: WeaponStats) -> impl Iterator<Item = Vec2> + '_ {
+        (0..stats.projectiles).map(move |i| {
+            let angle = self.angle + TAU * i as f32 / stats.projectiles as f32;
+            center + Vec2::from_angle(angle) * stats.area
+        })
+    }
+}
+
+impl Weapon for OrbitShield {
+    fn core(&self) -> &WeaponCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut WeaponCore {
+        &mut self.core
+    }
+
+    fn update(&mut self, field: &mut Field, delta: f32) {
+        let stats = self.core.boosted(field.player);
+        self.angle = (self.angle + stats.speed * TAU * delta) % TAU;
+        for hit in self.recently_hit.iter_mut() {
+            hit.1 -= delta;
+        }
+        self.recently_hit.retain(|hit| hit.1 > 0.);
+
+        let immunity = stats.cooldown as f32 / 1000.;
+        let orbs: Vec<Vec2> = self.orbs(field.player_center(), stats).collect();
+        let mut nearby = Vec::new();
+        for orb in orbs {
+            let pos = Position { x: orb.x, y: orb.y };
+            field.grid.query(pos, ORB_RADIUS * 4., &mut nearby);
+            for &i in nearby.iter() {
+                let e = &mut field.enemies[i];
+                if e.hp > 0. && !self.recently_hit.iter().any(|hit| hit.0 == e.id) && e.is_hit_within(pos, ORB_RADIUS) {
+                    e.hurt(stats.damage, field.events);
+                    self.recently_hit.push((e.id, immunity));
+                }
+            }
+        }
+    }
+
+    fn draw(&self, texture: Texture2D, player: &Player) {
+        for orb in self.orbs(vec2(player.pos_x + 2., player.pos_y + 2.), self.core.boosted(player)) {
+            draw_texture_ex(
+                texture,
+                orb.x - 3.,
+                orb.y - 3.,
+                SKYBLUE,
+                DrawTextureParams {
+                    dest_size: Some(vec2(6., 6.)),
+                    source: Some(Rect::new(40., 2., 8., 8.)),
+                    ..Default::default()
+                }
+            );
+        }
+    }
+}
+
+/// Swings at everything close on the side the player faces
+pub struct WhipArc {
+    core: WeaponCore,
+    /// Where each swing on screen points and seconds it has left
+    swings: Vec<(Vec2, f32)>,
+}
+
+impl Weapon for WhipArc {
+    fn core(&self) -> &WeaponCore {
+        &self.core
+    }
+
+    fn core_mut(&mut self) -> &mut WeaponCore {
+        &mut self.core
+    }
+
+    fn update(&mut self, field: &mut Field, delta: f32) {
+        for swing in self.swings.iter_mut() {
+            swing.1 -= delta;
+        }
+        self.swings.retain(|swing| swing.1 > 0.);
+        let stats = self.core.boosted(field.player);
+        if !self.core.ready(&stats, field.now) {
+            return;
+        }
+
+        let center = field.player_center();
+        let half_width = (stats.spread / 2.).to_radians();
+        let directions: Vec<Vec2> = [field.facing(), -field.facing()].into_iter().take(stats.projectiles.min(2) as usize).collect();
+        for direction in directions.iter() {
+            self.swings.push((*direction, SWING_SECS));
+        }
+        let reach = field.enemies.iter().map(|e| e.hurtbox_reach()).fold(0., f32::max);
+        let mut nearby = Vec::new();
+        field.grid.query(Position { x: center.x, y: center.y }, stats.area + reach, &mut nearby);
+        // Wide enough swings overlap, whatever is in both only gets hit once
+        for &i in nearby.iter() {
+            let e = &mut field.enemies[i];
+            let offset = vec2(e.position.x + e.hurtbox.offset_x, e.position.y + e.hurtbox.offset_y) - center;
+            let in_reach = offset.length() <= stats.area + e.hurtbox.radius;
+            let in_arc = offset.length() == 0. || directions.iter().any(|d| d.angle_between(offset).abs() <= half_width);
+            if e.hp > 0. && in_reach && in_arc {
+                e.hurt(stats.damage, field.events);
+            }
+        }
+    }
+
+    fn draw(&self, _texture: Texture2D, player: &Player) {
+        let stats = self.core.boosted(player);
+        let center = vec2(player.pos_x + 2., player.pos_y + 2.);
+        let half_width = (stats.spread / 2.).to_radians();
+        for (direction, secs_left) in self.swings.iter() {
+            let color = Color::new(1., 1., 1., secs_left / SWING_SECS);
+            let steps = 8;
+            let point = |i: i32| {
+                let angle = -half_width + 2. * half_width * i as f32 / steps as f32;
+                center + Vec2::from_angle(angle).rotate(*direction) * stats.area
+            };
+            for i in 0..steps {
+                let (a, b) = (point(i), point(i + 1));
+                draw_line(a.x, a.y, b.x, b.y, 1., color);
+            }
+        }
+    }
+}
+
+/// The weapons carried, up to [`WeaponDefs::slots`] of them
+pub struct Inventory {
+    weapons: Vec<Box<dyn Weapon>>,
+    pub slots: usize,
+}
+
+/// A weapon as saves keep it
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct WeaponSave {
+    pub id: String,
+    pub level: usize,
+    pub cooldown: Timer,
+}
+
+impl Inventory {
+    /// Just the starting weapon, for a run whose clock is at zero
+    pub fn starting(defs: &WeaponDefs) -> Self {
+        let mut inventory = Inventory { weapons: Vec::new(), slots: defs.slots };
+        if let Some(def) = defs.get(&defs.starting) {
+            inventory.weapons.push(make_weapon(def, 0.));
+        }
+        inventory
+    }
+
+    pub fn get(&self, id: &str) -> Option<&dyn Weapon> {
+        self.weapons.iter().find(|w| w.id() == id).map(|w| w.as_ref())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &dyn Weapon> {
+        self.weapons.iter().map(|w| w.as_ref())
+    }
+
+    pub fn is_full(&self) -> bool {
+        self.weapons.len() >= self.slots
+    }
+
+    /// Levels up the weapon if it's carried, picks it up otherwise
+    pub fn upgrade(&mut self, def: &WeaponDef, now: f64) {
+        let full = self.is_full();
+        match self.weapons.iter_mut().find(|w| w.id() == def.id) {
+            Some(weapon) => weapon.level_up(),
+            None if !full => self.weapons.push(make_weapon(def, now)),
+            None => {}
+        }
+    }
+
+    /// What a level up can offer: the next level of every weapon carried,
+    /// and every other weapon while there's a free slot
+    pub fn offers(&self, defs: &WeaponDefs) -> Vec<WeaponOffer> {
+        defs.iter().filter_map(|def| {
+            let next_level = match self.get(&def.id) {
+                Some(weapon) if weapon.level() < weapon.max_level() => weapon.level() + 1,
+                Some(_) => return None,
+                None if self.is_full() => return None,
+                None => 1,
+            };
+            Some(WeaponOffer { def: def.clone(), level: next_level })
+        }).collect()
+    }
+
+    pub fn update(&mut self, field: &mut Field, delta: f32) {
+        for weapon in self.weapons.iter_mut() {
+            weapon.update(field, delta);
+        }
+    }
+
+    pub fn draw(&self, texture: Texture2D, player: &Player) {
+        for weapon in self.weapons.iter() {
+            weapon.draw(texture, player);
+        }
+    }
+
+    pub fn save(&self) -> Vec<WeaponSave> {
+        self.weapons.iter().map(|w| WeaponSave {
+            id: w.id().to_string(),
+            level: w.level(),
+            cooldown: w.core().cooldown.clone(),
+        }).collect()
+    }
+
+    /// Weapons whose definition is gone since are dropped
+    pub fn restore(defs: &WeaponDefs, saved: Vec<WeaponSave>) -> Self {
+        let mut inventory = Inventory { weapons: Vec::new(), slots: defs.slots };
+        for s in saved {
+            let Some(def) = defs.get(&s.id) else { continue };
+            // The saved cooldown replaces the new one
+            let mut weapon = make_weapon(def, 0.);
+            let core = weapon.core_mut();
+            core.level = s.level.clamp(1, def.max_level());
+            core.cooldown = s.cooldown;
+            inventory.weapons.push(weapon);
+        }
+        inventory
+    }
+}
+
+/// A weapon, or its next level, on a level up card
+#[derive(Debug, Clone)]
+pub struct WeaponOffer {
+    pub def: WeaponDef,
+    /// The level it gets to when picked
+    pub level: usize,
+}
+
+impl Upgrade for WeaponOffer {
+    fn get_name(&self) -> &str {
+        &self.def.id
+    }
+
+    fn rarity(&self) -> Rarity {
+        self.def.rarity
+    }
+
+    fn draw(&self, font: Font, _texture: Texture2D, x: f32, y: f32, scale: f32, highlighted: bool) {
+        let font_color = if highlighted { WHITE } else { self.get_color() };
+        draw_rectangle(x, y - 50. * scale, 180. * scale, 180. * scale, Color::from_hex(0x4d65b4));
+        draw_rectangle(x + 10. * scale, y - 60. * scale, 160. * scale, 200. * scale, Color::from_hex(0x4d65b4));
+        draw_rarity_border(self.def.rarity, x, y, scale);
+        let level = if self.level == 1 { "New".to_string() } else { format!("Lv{}", self.level) };
+        for (i, line) in self.def.text.iter().chain(std::iter::once(&level)).enumerate() {
+            draw_text_ex(
+                line,
+                x,
+                y + 50. * scale * i as f32,
+                TextParams { font, font_size: 64, font_scale: scale, font_scale_aspect: 1., color: font_color, ..Default::default()}
+            );
+        }
+    }
+
+    fn apply(&self, _player: &mut Player, world: &mut World) {
+        world.weapons.upgrade(&self.def, world.clock.now());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::enemy_defs::EnemyDefs;
+    use crate::world::damage_enemy;
+
+    /// A slime too tough to die, its hurtbox centered on `x`, `y`
+    fn dummy(id: u64, x: f32, y: f32) -> Enemy {
+        let defs = EnemyDefs::builtin();
+        let mut e = Enemy::from_def(defs.get("slime").unwrap(), 0., 0., 1., 0.);
+        e.id = id;
+        e.hp = 100.;
+        e.position = Position { x: x - e.hurtbox.offset_x, y: y - e.hurtbox.offset_y };
+        e
+    }
+
+    fn grid_over(enemies: &[Enemy]) -> SpatialGrid {
+        let mut grid = SpatialGrid::new(16.);
+        grid.rebuild(enemies.iter().map(|e| e.position));
+        grid
+    }
+
+    #[test]
+    fn full_inventory_only_levels_what_it_carries() {
+        let defs = WeaponDefs::builtin();
+        let mut inventory = Inventory::starting(&defs);
+        inventory.slots = 1;
+        assert!(inventory.is_full());
+        inventory.upgrade(defs.get("Orbit").unwrap(), 0.);
+        assert!(inventory.get("Orbit").is_none());
+        assert_eq!(inventory.iter().count(), 1);
+        inventory.upgrade(defs.get("Bolt").unwrap(), 0.);
+        assert_eq!(inventory.get("Bolt").unwrap().level(), 2);
+    }
+
+    #[test]
+    fn offers_skip_maxed_and_new_weapons_when_full() {
+        let defs = WeaponDefs::builtin();
+        let mut inventory = Inventory::starting(&defs);
+        let offered = |inventory: &Inventory| -> Vec<(String, usize)> {
+            inventory.offers(&defs).into_iter().map(|o| (o.def.id, o.level)).collect()
+        };
+        let all = offered(&inventory);
+        assert_eq!(all.len(), defs.iter().count());
+        assert!(all.contains(&("Bolt".to_string(), 2)));
+        assert!(all.contains(&("Lance".to_string(), 1)));
+
+        let bolt = defs.get("Bolt").unwrap();
+        for _ in 1..bolt.max_level() {
+            inventory.upgrade(bolt, 0.);
+        }
+        let all = offered(&inventory);
+        assert!(all.iter().all(|(id, _)| id != "Bolt"));
+        assert_eq!(all.len(), defs.iter().count() - 1);
+
+        inventory.upgrade(defs.get("Lance").unwrap(), 0.);
+        inventory.slots = 2;
+        assert_eq!(offered(&inventory), vec![("Lance".to_string(), 2)]);
+    }
+
+    #[test]
+    fn fan_spreads_evenly_around_the_aim() {
+        let aim = vec2(0.6, -0.8);
+        for (count, spread) in [(1, 15.), (2, 15.), (3, 10.), (4, 20.)] {
+            let angles: Vec<f32> = fan(aim, count, spread).map(|d| aim.angle_between(d).to_degrees()).collect();
+            assert_eq!(angles.len(), count as usize);
+            let first = -((count - 1) as f32) / 2. * spread;
+            for (i, angle) in angles.iter().enumerate() {
+                assert!((angle - (first + i as f32 * spread)).abs() < 1e-3, "{} at {}: {:?}", count, spread, angles);
+            }
+        }
+    }
+
+    #[test]
+    fn lance_goes_through_pierce_plus_one_enemies_once_each() {
+        let defs = WeaponDefs::builtin();
+        let lance_def = defs.get("Lance").unwrap();
+        let pierce = lance_def.levels[0].pierce as usize;
+        let player = Player::new();
+        let center = (player.pos_x + 2., player.pos_y + 2.);
+        let mut enemies: Vec<Enemy> = (0..pierce as u64 + 2)
+            .map(|i| dummy(i, center.0 + 10. + 10. * i as f32, center.1))
+            .collect();
+        let grid = grid_over(&enemies);
+        let (mut bullets, mut events) = (Vec::new(), EventQueue::default());
+
+        let mut lance = make_weapon(lance_def, 0.);
+        lance.update(&mut Field { player: &player, enemies: &mut enemies, grid: &grid, bullets: &mut bullets, events: &mut events, now: 1. }, 0.);
+        assert_eq!(bullets.len(), 1);
+        // Slow enough to sit on every enemy for a few steps
+        while bullets[0].active && bullets[0].x < center.0 + 100. {
+            bullets[0].x -= bullets[0].dir_x;
+            bullets[0].y -= bullets[0].dir_y;
+            damage_enemy(&mut bullets, &mut enemies, &grid, &mut events);
+        }
+        assert!(!bullets[0].active);
+        let damage = bullets[0].damage;
+        let hits: Vec<usize> = enemies.iter().map(|e| ((100. - e.hp) / damage).round() as usize).collect();
+        let mut expected = vec![1; pierce + 1];
+        expected.push(0);
+        assert_eq!(hits, expected);
+        assert_eq!(events.iter().filter(|e| matches!(e, GameEvent::EnemyDamaged { .. })).count(), pierce + 1);
+    }
+
+    #[test]
+    fn orbit_spares_enemies_it_just_hit() {
+        let defs = WeaponDefs::builtin();
+        let def = defs.get("Orbit").unwrap();
+        let player = Player::new();
+        let radius = def.levels[0].area;
+        // Where the first orb starts
+        let mut enemies = vec![dummy(7, player.pos_x + 2. + radius, player.pos_y + 2.)];
+        let grid = grid_over(&enemies);
+        let (mut bullets, mut events) = (Vec::new(), EventQueue::default());
+        let mut shield = OrbitShield { core: WeaponCore::new(def, 0.), angle: 0., recently_hit: Vec::new() };
+        let mut step = |shield: &mut OrbitShield, delta: f32, enemies: &mut Vec<Enemy>| {
+            shield.update(&mut Field { player: &player, enemies, grid: &grid, bullets: &mut bullets, events: &mut events, now: 0. }, delta);
+        };
+
+        step(&mut shield, 0.001, &mut enemies);
+        assert_eq!(enemies[0].hp, 100. - 1.);
+        for _ in 0..9 {
+            step(&mut shield, 0.01, &mut enemies);
+        }
+        assert_eq!(enemies[0].hp, 100. - 1., "hit again within its cooldown");
+        // Half a turn later the other orb is there, and the cooldown is over
+        step(&mut shield, 0.95, &mut enemies);
+        assert_eq!(enemies[0].hp, 100. - 2.);
+    }
+
+    #[test]
+    fn overlapping_whip_swings_hit_once() {
+        let defs = WeaponDefs::builtin();
+        let mut def = defs.get("Arc").unwrap().clone();
+        def.levels[0].projectiles = 2;
+        def.levels[0].spread = 200.;
+        let player = Player::new();
+        // Right above the player, inside both swings
+        let mut enemies = vec![
+            dummy(1, player.pos_x + 2., player.pos_y + 2. - def.levels[0].area / 2.),
+            dummy(2, player.pos_x + 2. + def.levels[0].area * 3., player.pos_y + 2.),
+        ];
+        let grid = grid_over(&enemies);
+        let (mut bullets, mut events) = (Vec::new(), EventQueue::default());
+        let mut whip = make_weapon(&def, 0.);
+        whip.update(&mut Field { player: &player, enemies: &mut enemies, grid: &grid, bullets: &mut bullets, events: &mut events, now: 1. }, 0.);
+        let damage = def.levels[0].boosted(&player).damage;
+        assert_eq!(enemies[0].hp, 100. - damage);
+        assert_eq!(enemies[1].hp, 100., "out of reach");
+        assert_eq!(events.iter().filter(|e| matches!(e, GameEvent::EnemyDamaged { .. })).count(), 1);
+    }
+
+    #[test]
+    fn restore_clamps_levels() {
+        let defs = WeaponDefs::builtin();
+        let save = |id: &str, level| WeaponSave { id: id.to_string(), level, cooldown: Timer::new(100, 0.) };
+        let inventory = Inventory::restore(&defs, vec![save("Bolt", 99), save("Orbit", 0), save("Gone", 2)]);
+        assert_eq!(inventory.get("Bolt").unwrap().level(), defs.get("Bolt").unwrap().max_level());
+        assert_eq!(inventory.get("Orbit").unwrap().level(), 1);
+        assert_eq!(inventory.iter().count(), 2);
+    }
+
+    #[test]
+    fn broken_weapon_files_are_rejected() {
+        let builtin = include_str!("../assets/weapons.ron");
+        for (from, to) in [
+            ("slots: 4", "slots: 0"),
+            ("starting: \"Bolt\"", "starting: \"Sword\""),
+            ("id: \"Orbit\"", "id: \"Bolt\""),
+            ("(cooldown: 3000, speed: 20.0", "(cooldown: 0, speed: 20.0"),
+            ("damage: 0.5, area: 12.0", "damage: -0.5, area: 12.0"),
+        ] {
+            assert!(builtin.contains(from));
+            assert!(builtin.replacen(from, to, 1).parse::<WeaponDefs>().is_err(), "{} parsed", to);
+        }
+    }
+}
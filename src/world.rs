@@ -0,0 +1,727 @@
+use std::time::Duration;
+use macroquad::prelude::*;
+
+use crate::{Position, Bullet};
+use crate::clock::GameClock;
+use crate::difficulty::{Difficulty, Preset};
+use crate::enemies::*;
+use crate::enemy_defs::EnemyDefs;
+use crate::upgrade::{Upgrade, UpgradeDefs, pick_random_upgrades};
+use crate::weapons::{Field, Inventory, WeaponDefs};
+use crate::levels::{LevelRules, SkipReward};
+use crate::spawn::{SpawnContext, SpawnDirector, Timeline};
+use crate::damage_popup::*;
+use crate::particles::*;
+use crate::pickups::{Pickup, PickupKind, merge_gems, update_pickups};
+use crate::stopwatch_bevy::*;
+use crate::input::InputState;
+use crate::player::Player;
+use crate::player_stats::Stat;
+use crate::rng::{Rng, RunRng};
+use crate::spatial::SpatialGrid;
+use crate::stats::RunStats;
+use crate::events::{EventQueue, GameEvent};
+
+pub const PLAYER_SPEED: f32 = 10.;
+/// Twice the distance enemies keep from each other
+const ENEMY_GRID_CELL: f32 = 16.;
+/// One kill in this many drops a vacuum
+const VACUUM_DROP_ODDS: u32 = 250;
+/// A run lasts 4 minutes
+pub const STAGE_DURATION_MILLIS: u128 = 240000;
+
+// ============================================================================
+// GAME STATE STRUCTS
+// ============================================================================
+
+pub struct World {
+    /// Game time for this run, every timer of the run is checked against it
+    pub clock: GameClock,
+    pub enemies: Vec<Enemy>,
+    /// Given to the next enemy that spawns
+    pub next_enemy_id: u64,
+    /// What every enemy kind looks and acts like, kept across resets
+    pub enemy_defs: EnemyDefs,
+    /// Every upgrade a level up can offer, kept across resets
+    pub upgrade_defs: UpgradeDefs,
+    /// Every weapon that can be carried, kept across resets
+    pub weapon_defs: WeaponDefs,
+    pub weapons: Inventory,
+    /// XP each level takes and what level up screens allow, kept across resets
+    pub level_rules: LevelRules,
+    /// Decides what spawns when, its timeline is kept across resets
+    pub spawn_director: SpawnDirector,
+    /// Broadphase over `enemies`, rebuilt whenever they move
+    pub enemy_grid: SpatialGrid,
+    pub bullets: Vec<Bullet>,
+    pub enemy_bullets: Vec<Bullet>,
+    pub dead_enemies: Vec<DeadEnemy>,
+    /// XP gems and other drops waiting to be collected
+    pub pickups: Vec<Pickup>,
+    pub damage_popups: Vec<DamagePopup>,
+    pub particles: Vec<Particle>,
+    pub intro_particles: Vec<Particle>,
+    /// Its preset is kept across resets
+    pub difficulty: Difficulty,
+    pub kill_count: i32,
+    pub screen_shake_amount: f32,
+    pub stopwatch: Stopwatch,
+    pub rng: RunRng,
+    /// Names of the upgrades picked so far, in order
+    pub chosen_upgrades: Vec<String>,
+    /// Names of the upgrades and weapons never to be offered again this run
+    pub banished: Vec<String>,
+    pub rerolls_used: u32,
+    pub banishes_used: u32,
+    pub stats: RunStats,
+    /// What happened during the last step
+    pub events: EventQueue,
+}
+
+/// What happened during a [`World::step`] that the caller has to react to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepOutcome {
+    Continue,
+    LevelUp,
+}
+
+/// What the player did on a level up screen
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LevelUpChoice {
+    /// Takes the card at this index
+    Pick(usize),
+    /// Draws new cards
+    Reroll,
+    /// Takes the card at this index out of the pool and draws new cards
+    Banish(usize),
+    /// Takes the skip reward instead of a card
+    Skip,
+}
+
+impl World {
+    pub fn new(seed: u64) -> Self {
+        let weapon_defs = WeaponDefs::builtin();
+        let weapons = Inventory::starting(&weapon_defs);
+        World {
+            clock: GameClock::new(),
+            enemies: Vec::new(),
+            next_enemy_id: 0,
+            enemy_defs: EnemyDefs::builtin(),
+            upgrade_defs: UpgradeDefs::builtin(),
+            weapon_defs,
+            weapons,
+            level_rules: LevelRules::builtin(),
+            spawn_director: SpawnDirector::new(Timeline::builtin()),
+            enemy_grid: SpatialGrid::new(ENEMY_GRID_CELL),
+            bullets: Vec::new(),
+            enemy_bullets: Vec::new(),
+            dead_enemies: Vec::new(),
+            pickups: Vec::new(),
+            damage_popups: Vec::new(),
+            particles: Vec::new(),
+            intro_particles: Vec::new(),
+            difficulty: Difficulty::new(Preset::Normal),
+            kill_count: 0,
+            screen_shake_amount: 0.,
+            stopwatch: Stopwatch::new(),
+            rng: RunRng::new(seed),
+            chosen_upgrades: Vec::new(),
+            banished: Vec::new(),
+            rerolls_used: 0,
+            banishes_used: 0,
+            stats: RunStats::default(),
+            events: EventQueue::default(),
+        }
+    }
+
+    pub fn reset(&mut self, seed: u64) {
+        self.clock = GameClock::new();
+        self.enemies.clear();
+        self.next_enemy_id = 0;
+        self.enemy_grid.clear();
+        self.spawn_director.reset();
+        self.bullets.clear();
+        self.enemy_bullets.clear();
+        self.dead_enemies.clear();
+        self.pickups.clear();
+        self.damage_popups.clear();
+        self.particles.clear();
+        self.difficulty.reset();
+        self.kill_count = 0;
+        self.weapons = Inventory::starting(&self.weapon_defs);
+        self.screen_shake_amount = 0.;
+        self.stopwatch = Stopwatch::new();
+        self.rng = RunRng::new(seed);
+        self.chosen_upgrades.clear();
+        self.banished.clear();
+        self.rerolls_used = 0;
+        self.banishes_used = 0;
+        self.stats = RunStats::default();
+        self.events.clear();
+    }
+
+    pub fn seed(&self) -> u64 {
+        self.rng.seed()
+    }
+
+    /// Cheap fingerprint of the gameplay state (FNV-1a over the raw bits),
+    /// two runs with the same seed and input must end with the same value
+    pub fn checksum(&self, player: &Player) -> u64 {
+        let mut hash: u64 = 0xcbf29ce484222325;
+        let mut feed = |bits: u64| {
+            hash ^= bits;
+            hash = hash.wrapping_mul(0x100000001b3);
+        };
+        feed(player.pos_x.to_bits() as u64);
+        feed(player.pos_y.to_bits() as u64);
+        feed(player.hp.to_bits() as u64);
+        feed(player.xp.to_bits() as u64);
+        feed(player.level as u64);
+        feed(player.pending_level_ups as u64);
+        feed(self.kill_count as u64);
+        feed(self.stats.gold as u64);
+        feed(self.stopwatch.elapsed().as_nanos() as u64);
+        for e in self.enemies.iter() {
+            feed(e.position.x.to_bits() as u64);
+            feed(e.position.y.to_bits() as u64);
+        }
+        hash
+    }
+
+    pub fn apply_screen_shake(&mut self, amount: f32) {
+        self.screen_shake_amount += amount;
+    }
+
+    pub fn decay_screen_shake(&mut self) {
+        self.screen_shake_amount *= 0.94;
+    }
+
+    pub fn enemy_count(&self, kind: &str) -> usize {
+        self.enemies.iter().filter(|e| e.kind == kind).count()
+    }
+
+    pub fn elapsed_millis(&self) -> u128 {
+        self.stopwatch.elapsed().as_millis()
+    }
+
+    pub fn stage_cleared(&self) -> bool {
+        self.elapsed_millis() > STAGE_DURATION_MILLIS
+    }
+
+    /// Advances the whole run by `delta` seconds. Doesn't draw anything nor
+    /// read any input by itself, so it can run without a window.
+    ///
+    /// The world's clock is moved along with it and every timer of the run
+    /// is checked against it, so pausing or scaling the clock pauses or
+    /// scales the run.
+    pub fn step(&mut self, player: &mut Player, delta: f32, input: &InputState) -> StepOutcome {
+        let mut outcome = StepOutcome::Continue;
+        self.events.clear();
+
+        let delta = self.clock.advance(delta);
+        let now = self.clock.now();
+        self.stopwatch.tick(Duration::from_secs_f32(delta));
+        player.max_xp = self.level_rules.curve.xp_to_next(player.level);
+
+        if player.active {
+            // Move and Dashing input block
+            if input.dash_pressed && !player.is_dashing {
+                if let Some(direction) = input.dash_direction {
+                    player.dash_dir_x = direction.x;
+                    player.dash_dir_y = direction.y;
+                    player.dashing_timer.restart(now);
+                    player.is_dashing = true;
+                    self.events.emit(GameEvent::DashStarted { dir_x: direction.x, dir_y: direction.y, x: player.pos_x, y: player.pos_y });
+                }
+            }
+            if !player.is_dashing {
+                move_player(player, input, delta);
+            }
+            update_enemies(&mut self.enemies, &player.pos_x, &player.pos_y, &mut self.enemy_bullets, &mut self.events, delta, now);
+            self.rebuild_enemy_grid();
+            update_enemies_pushing(&mut self.enemies, &self.enemy_grid);
+            self.rebuild_enemy_grid();
+            update_enemies_colliding(&mut self.enemies, &self.enemy_grid, player, &mut self.events, now);
+            update_bullets(&mut self.bullets, &mut self.particles, delta, &mut self.rng.fx, now);
+            update_enemy_bullets(&mut self.enemy_bullets, &mut self.particles, delta, &mut self.rng.fx, now);
+        }
+        update_dead_enemies(&mut self.dead_enemies, &mut player.pos_x, delta, now);
+        update_particles(&mut self.particles, delta, now);
+
+        if player.is_dashing {
+            update_dash(player, &mut self.particles, delta, &mut self.rng.fx, now);
+        }
+
+        for popup in self.damage_popups.iter_mut() {
+            popup.update(now);
+        }
+
+        if player.active {
+            self.spawn_enemies(player, delta);
+        }
+
+        if player.regen_timer.finished(now) && player.active {
+            let regen = player.stat(Stat::Regen);
+            if player.hp + regen >= player.max_hp() {
+                player.hp = player.max_hp()
+            } else {
+                player.hp += regen;
+            }
+            player.regen_timer.restart(now);
+        }
+
+        // Enemies may have spawned since the last rebuild
+        self.rebuild_enemy_grid();
+        damage_enemy(&mut self.bullets, &mut self.enemies, &self.enemy_grid, &mut self.events);
+        bullet_damage_player(&mut self.enemy_bullets, player, &mut self.events, now);
+        let kills = self.kill_enemies(player.stat(Stat::Luck));
+        self.difficulty.record_kills(kills, delta);
+        if player.active {
+            merge_gems(&mut self.pickups, player);
+            update_pickups(&mut self.pickups, player, &mut self.events, delta);
+        }
+
+        while player.xp >= player.max_xp {
+            player.level_up(self.level_rules.curve.xp_to_next(player.level + 1));
+            self.events.emit(GameEvent::LevelUp { level: player.level });
+        }
+        // Also picks up where a resumed run left off
+        if player.pending_level_ups > 0 {
+            outcome = StepOutcome::LevelUp;
+        }
+
+        let mut field = Field {
+            player,
+            enemies: &mut self.enemies,
+            grid: &self.enemy_grid,
+            bullets: &mut self.bullets,
+            events: &mut self.events,
+            now,
+        };
+        self.weapons.update(&mut field, delta);
+
+        // Get rid of things that shouldn't be around anymore
+        // Bullets, enemies, particles, pop-ups
+        self.bullets.retain(|b| b.active);
+        self.enemy_bullets.retain(|b| b.active);
+        self.enemies.retain(|e| e.alive);
+        self.dead_enemies.retain(|e| e.active);
+        self.damage_popups.retain(|e| e.active);
+        self.particles.retain(|p| p.active);
+
+        if player.hp <= 0. {
+            player.hp = 0.;
+            player.active = false;
+            self.stopwatch.pause();
+
+            self.clear_entities();
+
+            player.pos_x = -999.;
+            player.pos_y = -999.;
+
+            self.events.emit(GameEvent::PlayerDied);
+        }
+
+        // Trigger end game progression
+        if self.stage_cleared() && player.active {
+            // destroy all entities
+            // but the player
+            self.clear_entities();
+
+            if self.elapsed_millis() < 246000 {
+                self.events.emit(GameEvent::StageCleared);
+            }
+        }
+
+        self.react_to_events();
+
+        outcome
+    }
+
+    /// Feedback and bookkeeping for what happened this step
+    fn react_to_events(&mut self) {
+        spawn_damage_popups(&mut self.damage_popups, &self.events, self.clock.now());
+        for event in self.events.iter() {
+            self.screen_shake_amount += screen_shake_for(event);
+            self.stats.record(event);
+        }
+    }
+
+    /// Returns how many died this frame. Each leaves an XP gem behind, and now
+    /// and then a vacuum.
+    /// `luck` multiplies the odds of a vacuum dropping
+    fn kill_enemies(&mut self, luck: f32) -> u32 {
+        let vacuum_odds = (VACUUM_DROP_ODDS as f32 / luck.max(0.01)).round().max(1.) as u32;
+        let now = self.clock.now();
+        let mut kills = 0;
+        for e in self.enemies.iter_mut() {
+            if e.alive && e.hp <= 0. {
+                e.alive = false;
+                self.pickups.push(Pickup::new(e.position.x + 2., e.position.y + 2., PickupKind::Xp(e.given_xp)));
+                if self.rng.loot.gen_range_u32(0, vacuum_odds) == 0 {
+                    self.pickups.push(Pickup::new(e.position.x + 4., e.position.y + 2., PickupKind::Vacuum));
+                }
+                self.kill_count += 1;
+                kills += 1;
+                self.events.emit(GameEvent::EnemyKilled { kind: e.kind.clone(), x: e.position.x, y: e.position.y, xp: e.given_xp });
+                let dead_enemy_obj = DeadEnemy::new(e.position.x, e.position.y, e.curr_frame, now);
+                self.dead_enemies.push(dead_enemy_obj);
+            }
+        }
+        kills
+    }
+
+    fn rebuild_enemy_grid(&mut self) {
+        self.enemy_grid.rebuild(self.enemies.iter().map(|e| e.position));
+    }
+
+    fn clear_entities(&mut self) {
+        // - deallocates but not sure if its good
+        self.enemies = Vec::new();
+        self.bullets = Vec::new();
+        self.dead_enemies = Vec::new();
+        self.pickups = Vec::new();
+        self.damage_popups = Vec::new();
+        self.particles = Vec::new();
+    }
+
+    fn spawn_enemies(&mut self, player: &Player, delta: f32) {
+        let elapsed_secs = self.stopwatch.elapsed_secs();
+        let scaling = self.difficulty.scaling(elapsed_secs, player.level);
+        let before = self.enemies.len();
+        self.spawn_director.update(elapsed_secs, delta, SpawnContext {
+            enemies: &mut self.enemies,
+            defs: &self.enemy_defs,
+            player,
+            scaling: &scaling,
+            rng: &mut self.rng.spawn,
+            now: self.clock.now(),
+        });
+        for e in self.enemies[before..].iter_mut() {
+            e.id = self.next_enemy_id;
+            self.next_enemy_id += 1;
+            self.events.emit(GameEvent::EnemySpawned { kind: e.kind.clone(), x: e.position.x, y: e.position.y });
+        }
+    }
+
+    /// Picks `upgrade` for the oldest level up still waiting
+    pub fn apply_upgrade(&mut self, player: &mut Player, upgrade: &dyn Upgrade) {
+        player.pending_level_ups = player.pending_level_ups.saturating_sub(1);
+        self.chosen_upgrades.push(upgrade.get_name().to_string());
+        upgrade.apply(player, self);
+    }
+
+    /// What the oldest level up still waiting offers, None once there are
+    /// none left. Level ups with nothing to offer are let go of
+    pub fn next_level_up(&mut self, player: &mut Player) -> Option<Vec<Box<dyn Upgrade>>> {
+        while player.pending_level_ups > 0 {
+            let offered = self.pick_upgrades();
+            if !offered.is_empty() {
+                return Some(offered);
+            }
+            player.pending_level_ups -= 1;
+        }
+        None
+    }
+
+    pub fn rerolls_left(&self) -> u32 {
+        self.level_rules.rerolls.saturating_sub(self.rerolls_used)
+    }
+
+    pub fn banishes_left(&self) -> u32 {
+        self.level_rules.banishes.saturating_sub(self.banishes_used)
+    }
+
+    /// Acts on `choice` for the level up screen showing `offered`. Returns
+    /// whether that level up is over, `offered` holds the new cards
+    /// otherwise. Rerolls and banishes with none left do nothing.
+    pub fn resolve_level_up(&mut self, player: &mut Player, offered: &mut Vec<Box<dyn Upgrade>>, choice: LevelUpChoice) -> bool {
+        match choice {
+            LevelUpChoice::Pick(index) => {
+                let Some(upgrade) = offered.get(index) else {
+                    return false;
+                };
+                self.apply_upgrade(player, upgrade.as_ref());
+                true
+            }
+            LevelUpChoice::Reroll => {
+                if self.rerolls_left() > 0 {
+                    self.rerolls_used += 1;
+                    *offered = self.pick_upgrades();
+                }
+                false
+            }
+            LevelUpChoice::Banish(index) => {
+                let Some(upgrade) = offered.get(index) else {
+                    return false;
+                };
+                if self.banishes_left() == 0 {
+                    return false;
+                }
+                self.banishes_used += 1;
+                self.banished.push(upgrade.get_name().to_string());
+                *offered = self.pick_upgrades();
+                // Banished the last thing there was to offer
+                if offered.is_empty() {
+                    player.pending_level_ups = player.pending_level_ups.saturating_sub(1);
+                    return true;
+                }
+                false
+            }
+            LevelUpChoice::Skip => {
+                player.pending_level_ups = player.pending_level_ups.saturating_sub(1);
+                match self.level_rules.skip {
+                    SkipReward::Heal(hp) => player.heal(hp),
+                    SkipReward::Gold(gold) => self.stats.gold += gold,
+                }
+                true
+            }
+        }
+    }
+
+    /// What the next level up offers, drawn from the loot stream: upgrades
+    /// and weapons alike, minus the banished ones
+    pub fn pick_upgrades(&mut self) -> Vec<Box<dyn Upgrade>> {
+        let mut pool: Vec<Box<dyn Upgrade>> = self.upgrade_defs.available(&self.chosen_upgrades)
+            .map(|def| Box::new(def.clone()) as Box<dyn Upgrade>)
+            .collect();
+        for offer in self.weapons.offers(&self.weapon_defs) {
+            pool.push(Box::new(offer));
+        }
+        pool.retain(|u| !self.banished.iter().any(|b| b == u.get_name()));
+        pick_random_upgrades(pool, self.level_rules.choices, &mut self.rng.loot)
+    }
+}
+
+fn move_player(player: &mut Player, input: &InputState, delta: f32) {
+    let a = input.move_x;
+    let b = input.move_y;
+    // Sticks pushed part way move slower, diagonals are never faster
+    let magnitude = (a.powi(2) + b.powi(2)).sqrt().max(1.);
+    let foo_x = a / magnitude;
+    let foo_y = b / magnitude;
+    if a != 0. || b != 0. {
+        let speed = PLAYER_SPEED * player.stat(Stat::MoveSpeed);
+        player.pos_x += foo_x * delta * speed;
+        player.pos_y -= foo_y * delta * speed;
+    }
+
+    if a < 0. {
+        player.flip_x = true
+    }
+    if a > 0. {
+        player.flip_x = false
+    }
+}
+
+fn update_dash(player: &mut Player, particles: &mut Vec<Particle>, delta: f32, fx_rng: &mut Rng, now: f64) {
+    // Calculate the dash distance based on the dash speed and delta time
+    let dash_distance = player.stat(Stat::DashSpeed) * delta;
+
+    player.pos_x += player.dash_dir_x * dash_distance;
+    player.pos_y += player.dash_dir_y * dash_distance;
+
+    spawn_particle(
+        particles,
+        player.pos_x,
+        player.pos_y,
+        Box::new(PlayerDashParticle{}),
+        fx_rng,
+        now
+    );
+
+    if player.dashing_timer.finished(now) {
+        player.is_dashing = false;
+    }
+}
+
+/// How much the screen shakes when `event` happens
+fn screen_shake_for(event: &GameEvent) -> f32 {
+    match event {
+        GameEvent::EnemyDamaged { .. } => 1.,
+        GameEvent::PlayerHit { .. } => 5.,
+        GameEvent::PlayerDied => 0.5 * 1.1,
+        GameEvent::StageCleared => 0.5,
+        _ => 0.,
+    }
+}
+
+fn update_bullets(bullets: &mut [Bullet], particles: &mut Vec<Particle>, delta: f32, fx_rng: &mut Rng, now: f64) {
+    for bullet in bullets.iter_mut() {
+        if bullet.active {
+            bullet.x -= bullet.dir_x * delta * bullet.speed;
+            bullet.y -= bullet.dir_y * delta * bullet.speed;
+            spawn_particle(particles, bullet.x, bullet.y, Box::new(ShotParticle{}), fx_rng, now);
+        }
+    }
+}
+
+pub(crate) fn damage_enemy(
+    bullets: &mut [Bullet],
+    enemies: &mut [Enemy],
+    grid: &SpatialGrid,
+    events: &mut EventQueue
+) {
+    let reach = enemies.iter().map(|e| e.hurtbox_reach()).fold(0., f32::max);
+    let mut nearby = Vec::new();
+    for bullet in bullets.iter_mut() {
+        let bullet_pos = Position { x: bullet.x, y: bullet.y };
+        grid.query(bullet_pos, reach + bullet.radius, &mut nearby);
+        for &i in nearby.iter() {
+            let e = &mut enemies[i];
+            // Collide with enemies
+            if bullet.active && e.hp > 0. && !bullet.hit.contains(&e.id) && e.is_hit_within(bullet_pos, bullet.radius) {
+                e.hurt(bullet.damage, events);
+                if bullet.pierce == 0 {
+                    bullet.active = false;
+                } else {
+                    bullet.pierce -= 1;
+                    bullet.hit.push(e.id);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Checksums every second of a run, picking the first card on level up
+    fn run(seed: u64, secs: usize) -> Vec<u64> {
+        let mut player = Player::new();
+        let mut world = World::new(seed);
+        let mut checksums = Vec::new();
+        for i in 0..secs * 60 {
+            let angle = i as f32 * 0.02;
+            let input = InputState { move_x: angle.cos(), move_y: angle.sin(), ..Default::default() };
+            if world.step(&mut player, 1. / 60., &input) == StepOutcome::LevelUp {
+                while let Some(mut offered) = world.next_level_up(&mut player) {
+                    world.resolve_level_up(&mut player, &mut offered, LevelUpChoice::Pick(0));
+                }
+            }
+            if i % 60 == 59 {
+                checksums.push(world.checksum(&player));
+            }
+        }
+        checksums
+    }
+
+    #[test]
+    fn same_seed_same_run() {
+        let first = run(2023, 60);
+        assert_eq!(first, run(2023, 60));
+        assert_ne!(first, run(2024, 60));
+    }
+
+    #[test]
+    fn steps_without_a_window() {
+        let mut player = Player::new();
+        let mut world = World::new(1);
+        let input = InputState { move_x: 1., ..Default::default() };
+        let start_x = player.pos_x;
+        for _ in 0..120 {
+            world.step(&mut player, 1. / 60., &input);
+        }
+        assert!(player.pos_x > start_x);
+        assert!(world.elapsed_millis() > 0);
+    }
+
+    #[test]
+    fn worlds_keep_their_own_time() {
+        let (mut player, mut other_player) = (Player::new(), Player::new());
+        let (mut world, mut other) = (World::new(1), World::new(1));
+        let input = InputState::default();
+        other.clock.pause();
+        for _ in 0..60 {
+            world.step(&mut player, 1. / 60., &input);
+            other.step(&mut other_player, 1. / 60., &input);
+        }
+        assert!((world.clock.now() - 1.).abs() < 1e-4);
+        assert_eq!(other.clock.now(), 0.);
+        assert_eq!(other.elapsed_millis(), 0);
+        assert!(!other_player.regen_timer.finished(other.clock.now()));
+    }
+
+    /// A still bullet right on `e`'s hurtbox
+    fn bullet_on(e: &Enemy, damage: f32) -> Bullet {
+        let (x, y) = (e.position.x + e.hurtbox.offset_x, e.position.y + e.hurtbox.offset_y);
+        Bullet { x, y, dir_x: 1., dir_y: 0., speed: 0., damage, active: true, radius: 0., pierce: 0, hit: Vec::new() }
+    }
+
+    #[test]
+    fn bullets_hurt_and_kill_bats_and_towers() {
+        let mut player = Player::new();
+        let mut world = World::new(1);
+        let input = InputState::default();
+        for (id, kind) in [(1000, "bat"), (1001, "tower")] {
+            let mut e = Enemy::from_def(world.enemy_defs.get(kind).unwrap(), 40., 20. + 30. * (id - 1000) as f32, 1., 0.);
+            e.id = id;
+            world.enemies.push(e);
+        }
+        // Bats swoop on their first step, aim where they got to
+        world.step(&mut player, 1. / 60., &input);
+        for i in 0..2 {
+            let bullet = bullet_on(&world.enemies[i], 1.);
+            world.bullets.push(bullet);
+        }
+        let tower_hp = world.enemies[1].hp;
+        world.step(&mut player, 1. / 60., &input);
+
+        let killed = |world: &World, kind: &str| world.events.iter().any(|e| matches!(e, GameEvent::EnemyKilled { kind: k, .. } if k == kind));
+        assert!(killed(&world, "bat"));
+        let tower = world.enemies.iter().find(|e| e.kind == "tower").unwrap();
+        assert_eq!(tower.hp, tower_hp - 1.);
+        assert!(!killed(&world, "tower"));
+
+        world.bullets.push(bullet_on(tower, tower_hp));
+        world.step(&mut player, 1. / 60., &input);
+        assert!(killed(&world, "tower"));
+        assert!(world.enemies.iter().all(|e| e.id < 1000));
+        let xp = |kind: &str| world.enemy_defs.get(kind).unwrap().xp;
+        for kind in ["bat", "tower"] {
+            assert!(world.pickups.iter().any(|p| p.kind == PickupKind::Xp(xp(kind))), "{} dropped its xp", kind);
+        }
+    }
+
+    #[test]
+    fn steps_report_what_happened_through_events() {
+        let mut player = Player::new();
+        let mut world = World::new(1);
+        // Past the iframes and the first attack of the starting weapon
+        world.clock.set_now(10.);
+        let mut slime = Enemy::from_def(world.enemy_defs.get("slime").unwrap(), player.pos_x, player.pos_y, 1., 0.);
+        slime.id = 1000;
+        world.enemies.push(slime);
+        let happened = |world: &World, what: fn(&GameEvent) -> bool| world.events.iter().any(what);
+
+        world.step(&mut player, 1. / 60., &InputState::default());
+        assert!(happened(&world, |e| matches!(e, GameEvent::PlayerHit { source, .. } if source == "slime")));
+        assert!(happened(&world, |e| matches!(e, GameEvent::ProjectileFired { by_player: true, .. })));
+
+        let slime = world.enemies.iter_mut().find(|e| e.id == 1000).unwrap();
+        slime.hp = 0.;
+        world.step(&mut player, 1. / 60., &InputState::default());
+        assert!(happened(&world, |e| matches!(e, GameEvent::EnemyKilled { kind, .. } if kind == "slime")));
+        assert!(!happened(&world, |e| matches!(e, GameEvent::PlayerHit { .. })), "last step's events are gone");
+
+        let dash = InputState { dash_pressed: true, dash_direction: Some(vec2(1., 0.)), ..Default::default() };
+        world.step(&mut player, 1. / 60., &dash);
+        assert!(happened(&world, |e| matches!(e, GameEvent::DashStarted { dir_x, .. } if *dir_x == 1.)));
+        assert!(!happened(&world, |e| matches!(e, GameEvent::EnemyKilled { .. })));
+    }
+
+    #[test]
+    fn clearing_the_stage_rumbles_through_events() {
+        let mut player = Player::new();
+        let mut world = World::new(1);
+        world.stopwatch.tick(Duration::from_millis(STAGE_DURATION_MILLIS as u64 + 1));
+        world.step(&mut player, 1. / 60., &InputState::default());
+        assert!(world.events.iter().any(|e| *e == GameEvent::StageCleared));
+        assert!(world.screen_shake_amount >= screen_shake_for(&GameEvent::StageCleared));
+
+        world.stopwatch.tick(Duration::from_secs(10));
+        world.step(&mut player, 1. / 60., &InputState::default());
+        assert!(world.events.iter().all(|e| *e != GameEvent::StageCleared));
+    }
+}